@@ -0,0 +1,101 @@
+//! Compiles every shader in `src/bin/shaders` to SPIR-V, along with the
+//! variants and entry points the pipelines in `assets/pipelines.ron` ask
+//! for.
+//!
+//! Compiling at build time means that a typo in a shader is reported by
+//! `cargo build`, with the file name and line number, rather than as a panic
+//! when the program starts. The compiled shaders are written out as Rust
+//! constants, which `src/shaders.rs` includes.
+
+use std::fmt::Write;
+use std::path::Path;
+
+// The same code compiles shaders when they're reloaded at runtime.
+#[path = "src/shader_compiler.rs"]
+mod shader_compiler;
+
+use shader_compiler::{compile_file, compile_jobs, settings_from_ron, CompileJob};
+
+const SHADER_DIR: &str = "src/bin/shaders";
+const PIPELINE_CONFIGS: &str = "assets/pipelines.ron";
+
+fn main() {
+    println!("cargo:rerun-if-changed={}", SHADER_DIR);
+    println!("cargo:rerun-if-changed={}", PIPELINE_CONFIGS);
+
+    let settings = std::fs::read_to_string(PIPELINE_CONFIGS)
+        .map_err(|error| error.to_string())
+        .and_then(|source| settings_from_ron(&source))
+        .unwrap_or_else(|message| panic!("Failed to read {}: {}", PIPELINE_CONFIGS, message));
+
+    let jobs =
+        compile_jobs(Path::new(SHADER_DIR), &settings).expect("Failed to read shader directory");
+
+    let mut compiler = shaderc::Compiler::new().expect("Failed to create shader compiler");
+    let mut output = String::new();
+    let mut errors = vec![];
+
+    for job in &jobs {
+        match compile_file(&mut compiler, &job.path, &job.entry_point, &job.defines) {
+            Ok(shader) => {
+                for warning in shader.warnings.lines() {
+                    println!("cargo:warning={}", warning);
+                }
+
+                write_constant(&mut output, job, &shader.spirv);
+            }
+            Err(error) => errors.push(error),
+        }
+    }
+
+    // Cargo only shows a build script's output when it fails, so we print
+    // every error before giving up, rather than panicking on the first one.
+    if !errors.is_empty() {
+        for error in &errors {
+            eprintln!("{}\n", error);
+        }
+        eprintln!("Failed to compile {} shader(s)", errors.len());
+        std::process::exit(1);
+    }
+
+    // A table of every shader, so they can be looked up by name.
+    writeln!(output, "/// Every compiled shader, by constant name.").unwrap();
+    writeln!(output, "pub const ALL: &[(&str, &[u32])] = &[").unwrap();
+    for job in &jobs {
+        writeln!(output, "    ({:?}, {}),", job.name, job.name).unwrap();
+    }
+    writeln!(output, "];").unwrap();
+
+    let out_dir = std::env::var("OUT_DIR").expect("OUT_DIR isn't set");
+    std::fs::write(Path::new(&out_dir).join("shaders.rs"), output)
+        .expect("Failed to write compiled shaders");
+}
+
+/// Write out a compiled shader as a constant.
+fn write_constant(output: &mut String, job: &CompileJob, spirv: &[u32]) {
+    let file_name = job.path.file_name().unwrap().to_string_lossy();
+    let defines: Vec<String> = job
+        .defines
+        .iter()
+        .map(|(name, value)| format!("`{} = {}`", name, value))
+        .collect();
+
+    if defines.is_empty() {
+        writeln!(output, "/// `{}`, compiled to SPIR-V.", file_name).unwrap();
+    } else {
+        writeln!(
+            output,
+            "/// `{}` with {}, compiled to SPIR-V.",
+            file_name,
+            defines.join(", ")
+        )
+        .unwrap();
+    }
+
+    writeln!(output, "pub const {}: &[u32] = &[", job.name).unwrap();
+    for line in spirv.chunks(8) {
+        let words: Vec<String> = line.iter().map(|word| format!("{:#010x}", word)).collect();
+        writeln!(output, "    {},", words.join(", ")).unwrap();
+    }
+    writeln!(output, "];\n").unwrap();
+}
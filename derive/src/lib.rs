@@ -0,0 +1,262 @@
+//! Derive macros for the teapot demo.
+//!
+//! These live in their own crate because procedural macros have to. See
+//! `gfx_hal_tutorials::shader_data` and `gfx_hal_tutorials::vertex_layout`
+//! for how they're used.
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote, quote_spanned};
+use syn::{
+    parse_macro_input, punctuated::Punctuated, spanned::Spanned, token::Comma, Data, DeriveInput,
+    Field, Fields, Meta, NestedMeta,
+};
+
+/// The GLSL rules a struct is laid out by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Rules {
+    Std140,
+    Std430,
+}
+
+/// Derive `ShaderData` for a `repr(C)` struct, checking at compile time that
+/// its layout matches the rules given by `#[shader_data(std140)]` or
+/// `#[shader_data(std430)]`.
+#[proc_macro_derive(ShaderData, attributes(shader_data))]
+pub fn derive_shader_data(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    match shader_data(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+/// Derive `VertexLayout` for a `repr(C)` struct, describing each field as
+/// a vertex attribute with its location in field order.
+#[proc_macro_derive(VertexLayout)]
+pub fn derive_vertex_layout(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    match vertex_layout(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+fn shader_data(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let fields = repr_c_fields(input, "ShaderData")?;
+    let rules = layout_rules(input)?;
+
+    let rules_trait = match rules {
+        Rules::Std140 => quote!(Std140Type),
+        Rules::Std430 => quote!(Std430Type),
+    };
+    let rules_name = match rules {
+        Rules::Std140 => "std140",
+        Rules::Std430 => "std430",
+    };
+
+    // For each field, we work out its offset in Rust (where every field type
+    // is 4-byte aligned, so it's just the sum of the sizes before it) and
+    // its offset in GLSL. If they differ, we force a compile error on that
+    // field with a constant whose name explains the problem.
+    let mut checks = vec![];
+    let mut members = vec![];
+    let mut rust_end = quote!(0);
+    let mut glsl_end = quote!(0);
+
+    for field in fields {
+        let ident = field.ident.as_ref().unwrap();
+        let ty = &field.ty;
+        let span = ty.span();
+
+        let error_name = format_ident!(
+            "{}_is_misaligned_for_{}_add_padding_before_it",
+            ident,
+            rules_name,
+            span = Span::call_site()
+        );
+
+        let rust_offset = rust_end.clone();
+        let glsl_offset = quote_spanned! {span=>
+            align_up(#glsl_end, <#ty as ::gfx_hal_tutorials::shader_data::#rules_trait>::ALIGN)
+        };
+
+        checks.push(quote_spanned! {span=>
+            #[allow(non_upper_case_globals, dead_code)]
+            const #error_name: usize = 0 - ((#rust_offset != #glsl_offset) as usize);
+        });
+
+        let field_name = ident.to_string();
+        members.push(quote! {
+            BlockMember {
+                name: #field_name.to_owned(),
+                offset: (#rust_offset) as u32,
+                size: std::mem::size_of::<#ty>() as u32,
+            }
+        });
+
+        rust_end = quote!((#rust_offset + std::mem::size_of::<#ty>()));
+        glsl_end = quote!((#glsl_offset + std::mem::size_of::<#ty>()));
+    }
+
+    let name_string = name.to_string();
+
+    Ok(quote! {
+        const _: () = {
+            use ::gfx_hal_tutorials::reflect::{BlockLayout, BlockMember};
+            use ::gfx_hal_tutorials::shader_data::{align_up, ShaderData};
+
+            #(#checks)*
+
+            // If the fields add up to less than the struct's size, there's
+            // padding that `as_words` would expose.
+            #[allow(non_upper_case_globals, dead_code)]
+            const struct_has_unexpected_padding: usize =
+                0 - ((#rust_end != std::mem::size_of::<#name>()) as usize);
+
+            unsafe impl ShaderData for #name {
+                fn block_layout() -> BlockLayout {
+                    BlockLayout {
+                        name: #name_string.to_owned(),
+                        size: std::mem::size_of::<#name>() as u32,
+                        members: vec![#(#members),*],
+                    }
+                }
+            }
+        };
+    })
+}
+
+fn vertex_layout(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let fields = repr_c_fields(input, "VertexLayout")?;
+
+    // Fields in a `repr(C)` struct are laid out in order, each one at the
+    // next offset that suits its alignment.
+    let mut attributes = vec![];
+    let mut end = quote!(0);
+
+    for (location, field) in fields.iter().enumerate() {
+        let ty = &field.ty;
+        let location = location as u32;
+        let offset = quote! {
+            align_up(#end, std::mem::align_of::<#ty>())
+        };
+
+        attributes.push(quote_spanned! {ty.span()=>
+            attribute::<#ty>(#location, binding, #offset)
+        });
+
+        end = quote!((#offset + std::mem::size_of::<#ty>()));
+    }
+
+    Ok(quote! {
+        const _: () = {
+            use ::gfx_hal_tutorials::shader_data::align_up;
+            use ::gfx_hal_tutorials::vertex_layout::{attribute, VertexLayout};
+
+            impl VertexLayout for #name {
+                fn attributes(
+                    binding: ::gfx_hal_tutorials::gfx_hal::pso::BufferIndex,
+                ) -> Vec<::gfx_hal_tutorials::gfx_hal::pso::AttributeDesc> {
+                    vec![#(#attributes),*]
+                }
+            }
+        };
+    })
+}
+
+/// Check that a derive input is a non-generic `repr(C)` struct with named
+/// fields, and return those fields.
+fn repr_c_fields<'a>(
+    input: &'a DeriveInput,
+    derive: &str,
+) -> syn::Result<&'a Punctuated<Field, Comma>> {
+    let name = &input.ident;
+
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new(
+            input.generics.span(),
+            format!("{} can't be derived for generic structs", derive),
+        ));
+    }
+
+    if !is_repr_c(input) {
+        return Err(syn::Error::new(
+            name.span(),
+            format!(
+                "{} structs must be #[repr(C)], or their layout isn't guaranteed",
+                derive
+            ),
+        ));
+    }
+
+    match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => Ok(&fields.named),
+            _ => Err(syn::Error::new(
+                name.span(),
+                format!(
+                    "{} can only be derived for structs with named fields",
+                    derive
+                ),
+            )),
+        },
+        _ => Err(syn::Error::new(
+            name.span(),
+            format!("{} can only be derived for structs", derive),
+        )),
+    }
+}
+
+/// Returns true if the struct has a `#[repr(C)]` attribute.
+fn is_repr_c(input: &DeriveInput) -> bool {
+    input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("repr"))
+        .filter_map(|attr| attr.parse_meta().ok())
+        .any(|meta| match meta {
+            Meta::List(list) => list.nested.iter().any(|nested| match nested {
+                NestedMeta::Meta(meta) => meta.path().is_ident("C"),
+                _ => false,
+            }),
+            _ => false,
+        })
+}
+
+/// Read the layout rules from the `#[shader_data(...)]` attribute.
+fn layout_rules(input: &DeriveInput) -> syn::Result<Rules> {
+    let attr = input
+        .attrs
+        .iter()
+        .find(|attr| attr.path.is_ident("shader_data"))
+        .ok_or_else(|| {
+            syn::Error::new(
+                input.ident.span(),
+                "ShaderData structs need a #[shader_data(std140)] or #[shader_data(std430)] \
+                 attribute",
+            )
+        })?;
+
+    let rules = match attr.parse_meta()? {
+        Meta::List(list) if list.nested.len() == 1 => match &list.nested[0] {
+            NestedMeta::Meta(meta) if meta.path().is_ident("std140") => Some(Rules::Std140),
+            NestedMeta::Meta(meta) if meta.path().is_ident("std430") => Some(Rules::Std430),
+            _ => None,
+        },
+        _ => None,
+    };
+
+    rules.ok_or_else(|| {
+        syn::Error::new(
+            attr.span(),
+            "expected #[shader_data(std140)] or #[shader_data(std430)]",
+        )
+    })
+}
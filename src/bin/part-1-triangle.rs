@@ -6,7 +6,6 @@ fn main() {
         window::{Extent2D, PresentationSurface, Surface},
         Instance,
     };
-    use shaderc::ShaderKind;
 
     const APP_NAME: &'static str = "Part 1: Drawing a triangle";
     const WINDOW_SIZE: [u32; 2] = [512, 512];
@@ -227,38 +226,41 @@ fn main() {
         }
     };
 
+    // The build script compiles every shader in `src/bin/shaders` to SPIR-V,
+    // so any mistakes in them are reported by `cargo build`, with the file
+    // name and line number.
+    let vertex_shader = gfx_hal_tutorials::shaders::PART_1_VERT;
+    let fragment_shader = gfx_hal_tutorials::shaders::PART_1_FRAG;
+
+    // Rather than describing the pipeline layout by hand, we read it from
+    // the compiled shaders. Reflecting each stage finds the descriptors and
+    // push constants it uses, and `from_stages` merges them into one layout.
+    let layout_desc = {
+        use gfx_hal::pso::ShaderStageFlags;
+        use gfx_hal_tutorials::reflect::{PipelineLayoutDesc, StageInterface};
+
+        let reflect = |stage, spirv: &[u32]| {
+            StageInterface::reflect(stage, spirv).expect("Failed to reflect shader")
+        };
+
+        let desc = PipelineLayoutDesc::from_stages(&[
+            reflect(ShaderStageFlags::VERTEX, vertex_shader),
+            reflect(ShaderStageFlags::FRAGMENT, fragment_shader),
+        ])
+        .unwrap_or_else(|message| panic!("Failed to build pipeline layout: {}", message));
+
+        // None of the shaders in these parts use descriptor sets, so there
+        // are no set layouts to create.
+        assert!(desc.sets.is_empty(), "Shaders unexpectedly use descriptors");
+        desc
+    };
+
     let pipeline_layout = unsafe {
         device
-            .create_pipeline_layout(&[], &[])
+            .create_pipeline_layout(&[], &layout_desc.push_constants)
             .expect("Out of memory")
     };
 
-    let vertex_shader = include_str!("shaders/part-1.vert");
-    let fragment_shader = include_str!("shaders/part-1.frag");
-
-    /// Compile some GLSL shader source to SPIR-V.
-    ///
-    /// We tend to write shaders in high-level languages, but the GPU doesn't
-    /// work with that directly. Instead, we can convert it to an intermediate
-    /// representation: SPIR-V. This is more easily interpreted and optimized
-    /// by your graphics card. As an added bonus, this allows us to use the
-    /// same shader code across different backends.
-    fn compile_shader(glsl: &str, shader_kind: ShaderKind) -> Vec<u32> {
-        let mut compiler = shaderc::Compiler::new().unwrap();
-
-        // The `compile_into_spirv` function is pretty straightforward.
-        // It optionally takes a filename (which we haven't, hence "unnamed").
-        // It also takes the entry point of the shader ("main"), and some
-        // other compiler options which we're also ignoring (`None`).
-        let compiled_shader = compiler
-            .compile_into_spirv(glsl, shader_kind, "unnamed", "main", None)
-            .expect("Failed to compile shader");
-
-        // The result is an opaque object. We can use the `as_binary` method to
-        // get a `&[u32]` view of it, and then convert it to an owned `Vec`.
-        compiled_shader.as_binary().to_vec()
-    }
-
     /// Create a pipeline with the given layout and shaders.
     ///
     /// A pipeline contains nearly all the required information for rendering,
@@ -267,20 +269,35 @@ fn main() {
         device: &B::Device,
         render_pass: &B::RenderPass,
         pipeline_layout: &B::PipelineLayout,
-        vertex_shader: &str,
-        fragment_shader: &str,
+        vertex_shader: &[u32],
+        fragment_shader: &[u32],
+        push_constants: Option<&gfx_hal_tutorials::reflect::PushConstantLayout>,
     ) -> B::GraphicsPipeline {
         use gfx_hal::pass::Subpass;
+        use gfx_hal::pso::ShaderStageFlags;
         use gfx_hal::pso::{
             BlendState, ColorBlendDesc, ColorMask, EntryPoint, Face, GraphicsPipelineDesc,
             InputAssemblerDesc, Primitive, PrimitiveAssemblerDesc, Rasterizer, Specialization,
         };
+        use gfx_hal_tutorials::reflect;
+
+        // If the shaders use push constants, check that they match the range
+        // in the pipeline layout, and the struct we fill it from. A mismatch
+        // wouldn't cause an error later on, just garbled rendering.
+        for &(stage, spirv) in &[
+            (ShaderStageFlags::VERTEX, vertex_shader),
+            (ShaderStageFlags::FRAGMENT, fragment_shader),
+        ] {
+            reflect::check_push_constants(stage, spirv, push_constants)
+                .unwrap_or_else(|message| panic!("Push constant layout mismatch: {}", message));
+        }
+
         let vertex_shader_module = device
-            .create_shader_module(&compile_shader(vertex_shader, ShaderKind::Vertex))
+            .create_shader_module(vertex_shader)
             .expect("Failed to create vertex shader module");
 
         let fragment_shader_module = device
-            .create_shader_module(&compile_shader(fragment_shader, ShaderKind::Fragment))
+            .create_shader_module(fragment_shader)
             .expect("Failed to create fragment shader module");
 
         // Shader modules are re-usable, and we could choose to define multiple
@@ -368,6 +385,7 @@ fn main() {
             &pipeline_layout,
             vertex_shader,
             fragment_shader,
+            None,
         )
     };
 
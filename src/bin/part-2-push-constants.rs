@@ -1,9 +1,14 @@
+use gfx_hal_tutorials::shader_data::ShaderData;
+
 /// A struct representing the data that we want to supply in push constants.
 ///
 /// The `repr(C)` attribute is required to ensure that the memory layout is
-/// what we expect. Without it, no specific layout is guaranteed.
+/// what we expect. Without it, no specific layout is guaranteed. Deriving
+/// `ShaderData` checks that the layout matches GLSL's rules, which for push
+/// constants are `std430`.
 #[repr(C)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, ShaderData)]
+#[shader_data(std430)]
 struct PushConstants {
     color: [f32; 4],
     pos: [f32; 2],
@@ -18,7 +23,6 @@ fn main() {
         window::{Extent2D, PresentationSurface, Surface},
         Instance,
     };
-    use shaderc::ShaderKind;
 
     const APP_NAME: &'static str = "Part 2: Push constants";
     const WINDOW_SIZE: [u32; 2] = [512, 512];
@@ -140,43 +144,49 @@ fn main() {
         }
     };
 
-    let pipeline_layout = unsafe {
+    let vertex_shader = gfx_hal_tutorials::shaders::PART_2_VERT;
+    let fragment_shader = gfx_hal_tutorials::shaders::PART_2_FRAG;
+
+    let layout_desc = {
         use gfx_hal::pso::ShaderStageFlags;
+        use gfx_hal_tutorials::reflect::{PipelineLayoutDesc, StageInterface};
+
+        let reflect = |stage, spirv: &[u32]| {
+            StageInterface::reflect(stage, spirv).expect("Failed to reflect shader")
+        };
 
-        let push_constant_bytes = std::mem::size_of::<PushConstants>() as u32;
+        let desc = PipelineLayoutDesc::from_stages(&[
+            reflect(ShaderStageFlags::VERTEX, vertex_shader),
+            reflect(ShaderStageFlags::FRAGMENT, fragment_shader),
+        ])
+        .unwrap_or_else(|message| panic!("Failed to build pipeline layout: {}", message));
+
+        assert!(desc.sets.is_empty(), "Shaders unexpectedly use descriptors");
+        desc
+    };
+
+    // The push constant range comes from the shaders, by way of
+    // `layout_desc`. We pair it with the layout of the `PushConstants` struct
+    // that fills it, and `make_pipeline` checks the two against each other,
+    // so if we change one without the other, we'll find out then, instead of
+    // getting mysteriously broken rendering.
+    let push_constant_layout = {
+        use gfx_hal_tutorials::reflect::PushConstantLayout;
+
+        let (stages, range) = layout_desc.push_constants[0].clone();
+        PushConstantLayout::new(stages, range, PushConstants::block_layout())
+    };
 
+    let pipeline_layout = unsafe {
         // The second slice passed here defines the ranges of push constants
-        // available to each shader stage. In this example, we're going to give
-        // one `PushConstants` struct worth of bytes to the vertex shader.
-        //
-        // Out data _could_ be offset, which is why we pass a range of bytes,
-        // but here we can start at zero since there's no data before our
-        // struct.
+        // available to each shader stage. Only the vertex shader uses them,
+        // so the reflected layout gives it one `PushConstants` struct worth
+        // of bytes, starting at zero.
         device
-            .create_pipeline_layout(&[], &[(ShaderStageFlags::VERTEX, 0..push_constant_bytes)])
+            .create_pipeline_layout(&[], &layout_desc.push_constants)
             .expect("Out of memory")
     };
 
-    let vertex_shader = include_str!("shaders/part-2.vert");
-    let fragment_shader = include_str!("shaders/part-2.frag");
-
-    /// Compile some GLSL shader source to SPIR-V.
-    ///
-    /// We tend to write shaders in high-level languages, but the GPU doesn't
-    /// work with that directly. Instead, we can convert it to an intermediate
-    /// representation: SPIR-V. This is more easily interpreted and optimized
-    /// by your graphics card. As an added bonus, this allows us to use the
-    /// same shader code across different backends.
-    fn compile_shader(glsl: &str, shader_kind: ShaderKind) -> Vec<u32> {
-        let mut compiler = shaderc::Compiler::new().unwrap();
-
-        let compiled_shader = compiler
-            .compile_into_spirv(glsl, shader_kind, "unnamed", "main", None)
-            .expect("Failed to compile shader");
-
-        compiled_shader.as_binary().to_vec()
-    }
-
     /// Create a pipeline with the given layout and shaders.
     ///
     /// A pipeline contains nearly all the required information for rendering,
@@ -185,20 +195,32 @@ fn main() {
         device: &B::Device,
         render_pass: &B::RenderPass,
         pipeline_layout: &B::PipelineLayout,
-        vertex_shader: &str,
-        fragment_shader: &str,
+        vertex_shader: &[u32],
+        fragment_shader: &[u32],
+        push_constants: Option<&gfx_hal_tutorials::reflect::PushConstantLayout>,
     ) -> B::GraphicsPipeline {
         use gfx_hal::pass::Subpass;
+        use gfx_hal::pso::ShaderStageFlags;
         use gfx_hal::pso::{
             BlendState, ColorBlendDesc, ColorMask, EntryPoint, Face, GraphicsPipelineDesc,
             InputAssemblerDesc, Primitive, PrimitiveAssemblerDesc, Rasterizer, Specialization,
         };
+        use gfx_hal_tutorials::reflect;
+
+        for &(stage, spirv) in &[
+            (ShaderStageFlags::VERTEX, vertex_shader),
+            (ShaderStageFlags::FRAGMENT, fragment_shader),
+        ] {
+            reflect::check_push_constants(stage, spirv, push_constants)
+                .unwrap_or_else(|message| panic!("Push constant layout mismatch: {}", message));
+        }
+
         let vertex_shader_module = device
-            .create_shader_module(&compile_shader(vertex_shader, ShaderKind::Vertex))
+            .create_shader_module(vertex_shader)
             .expect("Failed to create vertex shader module");
 
         let fragment_shader_module = device
-            .create_shader_module(&compile_shader(fragment_shader, ShaderKind::Fragment))
+            .create_shader_module(fragment_shader)
             .expect("Failed to create fragment shader module");
 
         let (vs_entry, fs_entry) = (
@@ -256,6 +278,7 @@ fn main() {
             &pipeline_layout,
             vertex_shader,
             fragment_shader,
+            Some(&push_constant_layout),
         )
     };
 
@@ -492,18 +515,6 @@ fn main() {
                     },
                 ];
 
-                /// Returns a view of a struct as a slice of `u32`s.
-                ///
-                /// Note that this assumes the struct divides evenly into
-                /// 4-byte chunks. If the contents are all `f32`s, which they
-                /// often are, then this will always be the case.
-                unsafe fn push_constant_bytes<T>(push_constants: &T) -> &[u32] {
-                    let size_in_bytes = std::mem::size_of::<T>();
-                    let size_in_u32s = size_in_bytes / std::mem::size_of::<u32>();
-                    let start_ptr = push_constants as *const T as *const u32;
-                    std::slice::from_raw_parts(start_ptr, size_in_u32s)
-                }
-
                 unsafe {
                     use gfx_hal::command::{
                         ClearColor, ClearValue, CommandBuffer, CommandBufferFlags, SubpassContents,
@@ -538,7 +549,7 @@ fn main() {
                             pipeline_layout,
                             ShaderStageFlags::VERTEX,
                             0,
-                            push_constant_bytes(triangle),
+                            triangle.as_words(),
                         );
 
                         command_buffer.draw(0..3, 0..1);
@@ -1,9 +1,14 @@
+use gfx_hal_tutorials::{shader_data::ShaderData, vertex_layout::VertexLayout};
+
 /// A struct representing the data that we want to supply in push constants.
 ///
 /// The `repr(C)` attribute is required to ensure that the memory layout is
-/// what we expect. Without it, no specific layout is guaranteed.
+/// what we expect. Without it, no specific layout is guaranteed. Deriving
+/// `ShaderData` checks that the layout matches GLSL's rules, which for push
+/// constants are `std430`.
 #[repr(C)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, ShaderData)]
+#[shader_data(std430)]
 struct PushConstants {
     transform: [[f32; 4]; 4],
 }
@@ -11,8 +16,9 @@ struct PushConstants {
 /// A struct representing a single vertex in 3D space with a normal.
 ///
 /// The `repr(C)` attribute is required to ensure that the memory layout is
-/// what we expect. Without it, no specific layout is guaranteed.
-#[derive(serde::Deserialize)]
+/// what we expect. Without it, no specific layout is guaranteed. Deriving
+/// `VertexLayout` describes the fields as vertex attributes 0 and 1.
+#[derive(Debug, Clone, Copy, serde::Deserialize, VertexLayout)]
 #[repr(C)]
 struct Vertex {
     position: [f32; 3],
@@ -27,7 +33,6 @@ fn main() {
         window::{Extent2D, PresentationSurface, Surface},
         Instance,
     };
-    use shaderc::ShaderKind;
 
     const APP_NAME: &'static str = "Part 3: Vertex buffers";
     const WINDOW_SIZE: [u32; 2] = [512, 512];
@@ -273,35 +278,39 @@ fn main() {
         }
     };
 
-    let pipeline_layout = unsafe {
+    let vertex_shader = gfx_hal_tutorials::shaders::PART_3_VERT;
+    let fragment_shader = gfx_hal_tutorials::shaders::PART_3_FRAG;
+
+    let layout_desc = {
         use gfx_hal::pso::ShaderStageFlags;
+        use gfx_hal_tutorials::reflect::{PipelineLayoutDesc, StageInterface};
 
-        let push_constant_bytes = std::mem::size_of::<PushConstants>() as u32;
+        let reflect = |stage, spirv: &[u32]| {
+            StageInterface::reflect(stage, spirv).expect("Failed to reflect shader")
+        };
 
-        device
-            .create_pipeline_layout(&[], &[(ShaderStageFlags::VERTEX, 0..push_constant_bytes)])
-            .expect("Out of memory")
+        let desc = PipelineLayoutDesc::from_stages(&[
+            reflect(ShaderStageFlags::VERTEX, vertex_shader),
+            reflect(ShaderStageFlags::FRAGMENT, fragment_shader),
+        ])
+        .unwrap_or_else(|message| panic!("Failed to build pipeline layout: {}", message));
+
+        assert!(desc.sets.is_empty(), "Shaders unexpectedly use descriptors");
+        desc
     };
 
-    let vertex_shader = include_str!("shaders/part-3.vert");
-    let fragment_shader = include_str!("shaders/part-3.frag");
+    let push_constant_layout = {
+        use gfx_hal_tutorials::reflect::PushConstantLayout;
 
-    /// Compile some GLSL shader source to SPIR-V.
-    ///
-    /// We tend to write shaders in high-level languages, but the GPU doesn't
-    /// work with that directly. Instead, we can convert it to an intermediate
-    /// representation: SPIR-V. This is more easily interpreted and optimized
-    /// by your graphics card. As an added bonus, this allows us to use the
-    /// same shader code across different backends.
-    fn compile_shader(glsl: &str, shader_kind: ShaderKind) -> Vec<u32> {
-        let mut compiler = shaderc::Compiler::new().unwrap();
-
-        let compiled_shader = compiler
-            .compile_into_spirv(glsl, shader_kind, "unnamed", "main", None)
-            .expect("Failed to compile shader");
-
-        compiled_shader.as_binary().to_vec()
-    }
+        let (stages, range) = layout_desc.push_constants[0].clone();
+        PushConstantLayout::new(stages, range, PushConstants::block_layout())
+    };
+
+    let pipeline_layout = unsafe {
+        device
+            .create_pipeline_layout(&[], &layout_desc.push_constants)
+            .expect("Out of memory")
+    };
 
     /// Create a pipeline with the given layout and shaders.
     ///
@@ -311,20 +320,32 @@ fn main() {
         device: &B::Device,
         render_pass: &B::RenderPass,
         pipeline_layout: &B::PipelineLayout,
-        vertex_shader: &str,
-        fragment_shader: &str,
+        vertex_shader: &[u32],
+        fragment_shader: &[u32],
+        push_constants: Option<&gfx_hal_tutorials::reflect::PushConstantLayout>,
     ) -> B::GraphicsPipeline {
         use gfx_hal::pass::Subpass;
+        use gfx_hal::pso::ShaderStageFlags;
         use gfx_hal::pso::{
             BlendState, ColorBlendDesc, ColorMask, EntryPoint, Face, GraphicsPipelineDesc,
             InputAssemblerDesc, Primitive, PrimitiveAssemblerDesc, Rasterizer, Specialization,
         };
+        use gfx_hal_tutorials::reflect;
+
+        for &(stage, spirv) in &[
+            (ShaderStageFlags::VERTEX, vertex_shader),
+            (ShaderStageFlags::FRAGMENT, fragment_shader),
+        ] {
+            reflect::check_push_constants(stage, spirv, push_constants)
+                .unwrap_or_else(|message| panic!("Push constant layout mismatch: {}", message));
+        }
+
         let vertex_shader_module = device
-            .create_shader_module(&compile_shader(vertex_shader, ShaderKind::Vertex))
+            .create_shader_module(vertex_shader)
             .expect("Failed to create vertex shader module");
 
         let fragment_shader_module = device
-            .create_shader_module(&compile_shader(fragment_shader, ShaderKind::Fragment))
+            .create_shader_module(fragment_shader)
             .expect("Failed to create fragment shader module");
 
         let (vs_entry, fs_entry) = (
@@ -339,68 +360,51 @@ fn main() {
                 specialization: Specialization::default(),
             },
         );
-        let primitive_assembler = {
-            use gfx_hal::format::Format;
-            use gfx_hal::pso::{AttributeDesc, Element, VertexBufferDesc, VertexInputRate};
-
-            PrimitiveAssemblerDesc::Vertex {
-                // We need to add a new section to our primitive assembler so
-                // that it understands how to interpret the vertex data it is
-                // given.
-                //
-                // We start by giving it a `binding` number, which is more or
-                // less an ID or a slot for the vertex buffer. You'll see it
-                // used later.
-                //
-                // The `stride` is the size of one item in the buffer.
-                // The `rate` defines how to progress through the buffer.
-                // Passing `Vertex` to this tells it to advance after every
-                // vertex. This is usually what you want to do if you're not
-                // making use of instanced rendering.
-                buffers: &[VertexBufferDesc {
-                    binding: 0,
-                    stride: std::mem::size_of::<Vertex>() as u32,
-                    rate: VertexInputRate::Vertex,
-                }],
-
-                // Then we need to define the attributes _within_ the vertices.
-                // For us this is the `position` and the `normal`.
-                //
-                // The vertex buffer we just defined has a `binding` number of
-                // `0`. The `location` refers to the location in the `layout`
-                // definition in the vertex shader.
-                //
-                // Finally the `element` describes the size and position of the
-                // attribute. Both of our elements are 3-component 32-bit float
-                // vectors, and so the `format` is `Rgb32Sfloat`. (I don't know
-                // why it's `Rgb` and not `Xyz` or `Vec3` but here we are.)
-                //
-                // Note that the second attribute has an offset of `12` bytes,
-                // because it has 3 4-byte floats before it (e.g. the previous
-                // attribute).
-                attributes: &[
-                    AttributeDesc {
-                        location: 0,
-                        binding: 0,
-                        element: Element {
-                            format: Format::Rgb32Sfloat,
-                            offset: 0,
-                        },
-                    },
-                    AttributeDesc {
-                        location: 1,
-                        binding: 0,
-                        element: Element {
-                            format: Format::Rgb32Sfloat,
-                            offset: 12,
-                        },
-                    },
-                ],
-                input_assembler: InputAssemblerDesc::new(Primitive::TriangleList),
-                vertex: vs_entry,
-                tessellation: None,
-                geometry: None,
-            }
+        let primitive_assembler = PrimitiveAssemblerDesc::Vertex {
+            // We need to add a new section to our primitive assembler so
+            // that it understands how to interpret the vertex data it is
+            // given.
+            //
+            // We start by giving it a `binding` number, which is more or
+            // less an ID or a slot for the vertex buffer. You'll see it
+            // used later.
+            //
+            // The `stride` is the size of one item in the buffer.
+            // The `rate` defines how to progress through the buffer.
+            // Passing `Vertex` to this tells it to advance after every
+            // vertex. This is usually what you want to do if you're not
+            // making use of instanced rendering.
+            //
+            // `Vertex` derives `VertexLayout`, so `vertex_buffer` fills
+            // these in for us: the `stride` is the size of the `Vertex`
+            // struct, and the `rate` is `Vertex`.
+            buffers: &[Vertex::vertex_buffer(0)],
+
+            // Then we need to define the attributes _within_ the vertices.
+            // For us this is the `position` and the `normal`.
+            //
+            // The vertex buffer we just defined has a `binding` number of
+            // `0`. The `location` refers to the location in the `layout`
+            // definition in the vertex shader.
+            //
+            // Finally the `element` describes the size and position of the
+            // attribute. Both of our elements are 3-component 32-bit float
+            // vectors, and so the `format` is `Rgb32Sfloat`. (I don't know
+            // why it's `Rgb` and not `Xyz` or `Vec3` but here we are.)
+            //
+            // Note that the second attribute has an offset of `12` bytes,
+            // because it has 3 4-byte floats before it (e.g. the previous
+            // attribute).
+            //
+            // The derived `attributes` works all of this out from the fields
+            // of `Vertex`: the locations are in field order, the formats come
+            // from the field types, and the offsets from their sizes. That
+            // way, changing the struct can't silently break the pipeline.
+            attributes: &Vertex::attributes(0),
+            input_assembler: InputAssemblerDesc::new(Primitive::TriangleList),
+            vertex: vs_entry,
+            tessellation: None,
+            geometry: None,
         };
         let mut pipeline_desc = GraphicsPipelineDesc::new(
             primitive_assembler,
@@ -437,6 +441,7 @@ fn main() {
             &pipeline_layout,
             vertex_shader,
             fragment_shader,
+            Some(&push_constant_layout),
         )
     };
 
@@ -651,18 +656,6 @@ fn main() {
                     transform: make_transform([0., 0., 0.5], angle, 1.0),
                 }];
 
-                /// Returns a view of a struct as a slice of `u32`s.
-                ///
-                /// Note that this assumes the struct divides evenly into
-                /// 4-byte chunks. If the contents are all `f32`s, which they
-                /// often are, then this will always be the case.
-                unsafe fn push_constant_bytes<T>(push_constants: &T) -> &[u32] {
-                    let size_in_bytes = std::mem::size_of::<T>();
-                    let size_in_u32s = size_in_bytes / std::mem::size_of::<u32>();
-                    let start_ptr = push_constants as *const T as *const u32;
-                    std::slice::from_raw_parts(start_ptr, size_in_u32s)
-                }
-
                 unsafe {
                     use gfx_hal::command::{
                         ClearColor, ClearValue, CommandBuffer, CommandBufferFlags, SubpassContents,
@@ -706,7 +699,7 @@ fn main() {
                             pipeline_layout,
                             ShaderStageFlags::VERTEX,
                             0,
-                            push_constant_bytes(teapot),
+                            teapot.as_words(),
                         );
 
                         // This is the number of vertices in the whole teapot.
@@ -0,0 +1,46 @@
+// Each bloom pass reads a single HDR texture, and filters it as it resizes
+// it. The sampler is linear, so each sample between texels blends four of
+// them at once, which keeps the number of samples down.
+layout(set = 0, binding = 0) uniform texture2D bloom_texture;
+layout(set = 0, binding = 1) uniform sampler bloom_sampler;
+
+vec3 bloom_sample(vec2 uv) {
+    return texture(sampler2D(bloom_texture, bloom_sampler), uv).rgb;
+}
+
+vec2 bloom_texel_size() {
+    return 1.0 / vec2(textureSize(sampler2D(bloom_texture, bloom_sampler), 0));
+}
+
+// Shrink to half the size, averaging four samples, each a texel diagonally
+// away from the center. Between them, they cover a 4x4 block of texels.
+vec3 downsample(vec2 uv) {
+    vec2 texel = bloom_texel_size();
+
+    vec3 sum = bloom_sample(uv + texel * vec2(-1.0, -1.0));
+    sum += bloom_sample(uv + texel * vec2(1.0, -1.0));
+    sum += bloom_sample(uv + texel * vec2(-1.0, 1.0));
+    sum += bloom_sample(uv + texel * vec2(1.0, 1.0));
+
+    return sum * 0.25;
+}
+
+// Grow to twice the size with a 3x3 tent filter, which spreads the light out
+// smoothly rather than leaving it in blocky texels.
+vec3 upsample(vec2 uv) {
+    vec2 texel = bloom_texel_size();
+
+    vec3 sum = bloom_sample(uv) * 4.0;
+
+    sum += bloom_sample(uv + texel * vec2(-1.0, 0.0)) * 2.0;
+    sum += bloom_sample(uv + texel * vec2(1.0, 0.0)) * 2.0;
+    sum += bloom_sample(uv + texel * vec2(0.0, -1.0)) * 2.0;
+    sum += bloom_sample(uv + texel * vec2(0.0, 1.0)) * 2.0;
+
+    sum += bloom_sample(uv + texel * vec2(-1.0, -1.0));
+    sum += bloom_sample(uv + texel * vec2(1.0, -1.0));
+    sum += bloom_sample(uv + texel * vec2(-1.0, 1.0));
+    sum += bloom_sample(uv + texel * vec2(1.0, 1.0));
+
+    return sum / 16.0;
+}
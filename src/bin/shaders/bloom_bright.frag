@@ -0,0 +1,26 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+#include "bloom.glsl"
+
+// Only light brighter than the threshold blooms. These match
+// `BloomConstants` in `teapot/post.rs`.
+layout(push_constant) uniform BloomConstants {
+    float threshold;
+} bloom;
+
+layout(location = 0) in vec2 vertex_uv;
+
+layout(location = 0) out vec4 fragment_color;
+
+// The first bloom pass: it halves the HDR scene, and keeps only its
+// brightest parts. Rather than keeping whole pixels that pass the threshold,
+// which would give the bloom hard edges, it keeps the light above it.
+void main() {
+    vec3 color = downsample(vertex_uv);
+
+    float brightness = max(color.r, max(color.g, color.b));
+    float excess = max(brightness - bloom.threshold, 0.0);
+
+    fragment_color = vec4(color * excess / max(brightness, 0.0001), 1.0);
+}
@@ -0,0 +1,22 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+#include "bloom.glsl"
+
+layout(location = 0) in vec2 vertex_uv;
+
+layout(location = 0) out vec4 fragment_color;
+
+// Each step down the chain blurs the bright parts a little more, at half the
+// size of the last.
+//
+// With `UPSAMPLE` defined, each step back up the chain is added onto the
+// target one size up instead, so the largest target ends up with every level
+// of blur: tight around the bright parts, and wide and faint further out.
+void main() {
+#ifdef UPSAMPLE
+    fragment_color = vec4(upsample(vertex_uv), 1.0);
+#else
+    fragment_color = vec4(downsample(vertex_uv), 1.0);
+#endif
+}
@@ -0,0 +1,11 @@
+// The camera, shared by every shader that draws in world space.
+//
+// The view axes are scaled so that they reach the edges of the screen at a
+// distance of 1.0 in front of the camera.
+layout(set = 1, binding = 0) uniform Camera {
+    mat4 view_projection;
+    vec4 position;
+    vec4 view_right;
+    vec4 view_down;
+    vec4 view_forward;
+} camera;
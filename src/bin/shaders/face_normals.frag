@@ -0,0 +1,12 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) out vec4 fragment_color;
+
+// Lines aren't surfaces, so like the sky, they have no normal or depth.
+layout(location = 1) out vec4 fragment_normal_depth;
+
+void main() {
+    fragment_color = vec4(1.0, 0.8, 0.0, 1.0);
+    fragment_normal_depth = vec4(0.0);
+}
@@ -0,0 +1,13 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) in vec3 position;
+
+#include "camera.glsl"
+#include "push_constants.glsl"
+
+// The lines are already in the teapot's model space, so they're transformed
+// the same way as the teapot.
+void main() {
+    gl_Position = camera.view_projection * push_constants.transform * vec4(position, 1.0);
+}
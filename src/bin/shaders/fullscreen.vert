@@ -0,0 +1,13 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) out vec2 vertex_uv;
+
+// Like the sky, this draws a single triangle big enough to cover the whole
+// screen, with no vertex buffer. Post-processing passes use it to run their
+// fragment shader once for every pixel, with `vertex_uv` going from (0, 0)
+// in the top left corner to (1, 1) in the bottom right.
+void main() {
+    vertex_uv = vec2(gl_VertexIndex & 2, (gl_VertexIndex << 1) & 2);
+    gl_Position = vec4(vertex_uv * 2.0 - 1.0, 0.0, 1.0);
+}
@@ -0,0 +1,108 @@
+// The lights in the scene, shared by every shader that does lighting.
+
+const float PI = 3.14159265;
+
+// There's one directional light, like the sun. Its direction is the way the
+// light travels, and its color is already multiplied by its brightness.
+//
+// The sky lights the scene too. Its diffuse light is stored as spherical
+// harmonics, which `sky_irradiance` turns back into a color.
+layout(set = 1, binding = 2) uniform Lighting {
+    vec4 direction;
+    vec4 color;
+    vec4 irradiance[9];
+} lighting;
+
+// Any number of point and spot lights. This matches `Light` in `light.rs`.
+// A point light is a spot light whose cone covers every direction.
+struct Light {
+    vec3 position;
+    float range;
+    vec3 color;
+    float inner_cone_cos;
+    vec3 direction;
+    float outer_cone_cos;
+};
+
+// The buffer can hold more lights than are in use: only the first
+// `push_constants.light_count` are valid.
+layout(set = 1, binding = 3) readonly buffer Lights {
+    Light lights[];
+};
+
+// The diffuse light from the sky reaching a surface with the given normal,
+// already divided by pi, so it only needs multiplying by the diffuse color.
+// The coefficients come from `ibl::irradiance_sh`.
+vec3 sky_irradiance(vec3 normal) {
+    float x = normal.x;
+    float y = normal.y;
+    float z = normal.z;
+
+    vec3 irradiance = lighting.irradiance[0].rgb * 0.282095
+        + lighting.irradiance[1].rgb * 0.488603 * y
+        + lighting.irradiance[2].rgb * 0.488603 * z
+        + lighting.irradiance[3].rgb * 0.488603 * x
+        + lighting.irradiance[4].rgb * 1.092548 * x * y
+        + lighting.irradiance[5].rgb * 1.092548 * y * z
+        + lighting.irradiance[6].rgb * 0.315392 * (3.0 * z * z - 1.0)
+        + lighting.irradiance[7].rgb * 1.092548 * x * z
+        + lighting.irradiance[8].rgb * 0.546274 * (x * x - y * y);
+
+    // Nine coefficients can't capture a very bright sun exactly, so the
+    // result can ring a little below zero on the opposite side.
+    return max(irradiance, vec3(0.0));
+}
+
+// The Blinn-Phong lighting model: the light reflected by a surface with the
+// given normal, lit by a light in direction `to_light` and seen from
+// direction `to_eye`. All three should be normalized.
+//
+// The diffuse term is brightest where the surface faces the light. The
+// specular highlight is brightest where the normal lines up with the vector
+// halfway between the light and the eye, and `shininess` makes it tighter.
+//
+// Both terms are normalized so that they never reflect more light than
+// arrives, which keeps them comparable with `cook_torrance` in `pbr.glsl`.
+vec3 blinn_phong(
+    vec3 normal,
+    vec3 to_light,
+    vec3 to_eye,
+    vec3 light_color,
+    vec3 diffuse_color,
+    vec3 specular_color,
+    float shininess
+) {
+    float diffuse = max(dot(normal, to_light), 0.0);
+
+    // Without this, surfaces facing away from the light could still get a
+    // highlight.
+    if (diffuse <= 0.0) {
+        return vec3(0.0);
+    }
+
+    vec3 halfway = normalize(to_light + to_eye);
+    float specular = pow(max(dot(normal, halfway), 0.0), shininess) * (shininess + 8.0) / 8.0;
+
+    return light_color * diffuse * (diffuse_color + specular_color * specular) / PI;
+}
+
+// How much of a point or spot light's color reaches `position`, and the
+// direction from there to the light.
+//
+// The light falls off with the square of the distance, but is also faded
+// out smoothly to nothing at its range, so that far away surfaces don't
+// need to consider it at all.
+vec3 light_intensity(Light light, vec3 position, out vec3 to_light) {
+    vec3 offset = light.position - position;
+    float light_distance = length(offset);
+    to_light = offset / light_distance;
+
+    float range_fade = clamp(1.0 - pow(light_distance / light.range, 4.0), 0.0, 1.0);
+    float falloff = range_fade * range_fade / (light_distance * light_distance + 1.0);
+
+    float cone_cos = dot(-to_light, light.direction);
+    float cone_width = max(light.inner_cone_cos - light.outer_cone_cos, 0.0001);
+    float cone = clamp((cone_cos - light.outer_cone_cos) / cone_width, 0.0, 1.0);
+
+    return light.color * falloff * cone * cone;
+}
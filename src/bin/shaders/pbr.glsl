@@ -0,0 +1,83 @@
+// Physically based shading, with the metallic-roughness model.
+//
+// This needs `PI` from `lighting.glsl`, so include that first.
+
+// The GGX normal distribution: how many of the surface's microfacets face
+// in the `halfway` direction, so that they reflect the light into the eye.
+// Rougher surfaces spread their microfacets out more.
+float distribution_ggx(float n_dot_h, float roughness) {
+    // Squaring the roughness makes it look more even from 0 to 1.
+    float alpha = roughness * roughness;
+    float alpha_squared = alpha * alpha;
+    float denominator = n_dot_h * n_dot_h * (alpha_squared - 1.0) + 1.0;
+
+    return alpha_squared / (PI * denominator * denominator);
+}
+
+// The Smith geometry term, with Schlick's approximation: how many of the
+// microfacets are visible from both the light and the eye, rather than
+// being shadowed by their neighbours.
+float geometry_smith(float n_dot_v, float n_dot_l, float roughness) {
+    float k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+    float view = n_dot_v / (n_dot_v * (1.0 - k) + k);
+    float light = n_dot_l / (n_dot_l * (1.0 - k) + k);
+
+    return view * light;
+}
+
+// Schlick's approximation of the Fresnel effect: surfaces reflect more light
+// at grazing angles. The `f0` is the reflectance when looking straight on.
+vec3 fresnel_schlick(float cos_theta, vec3 f0) {
+    return f0 + (1.0 - f0) * pow(1.0 - cos_theta, 5.0);
+}
+
+// The Cook-Torrance lighting model: the light reflected by a surface with
+// the given normal, lit by a light in direction `to_light` and seen from
+// direction `to_eye`. All three should be normalized.
+//
+// Whatever light isn't reflected by the specular term is left for the
+// diffuse term, so together they never reflect more light than arrives.
+vec3 cook_torrance(
+    vec3 normal,
+    vec3 to_light,
+    vec3 to_eye,
+    vec3 light_color,
+    vec3 diffuse_color,
+    vec3 f0,
+    float roughness
+) {
+    float n_dot_l = max(dot(normal, to_light), 0.0);
+
+    if (n_dot_l <= 0.0) {
+        return vec3(0.0);
+    }
+
+    vec3 halfway = normalize(to_light + to_eye);
+    float n_dot_v = max(dot(normal, to_eye), 0.0001);
+    float n_dot_h = max(dot(normal, halfway), 0.0);
+
+    vec3 fresnel = fresnel_schlick(max(dot(halfway, to_eye), 0.0), f0);
+    vec3 specular = distribution_ggx(n_dot_h, roughness)
+        * geometry_smith(n_dot_v, n_dot_l, roughness)
+        * fresnel
+        / (4.0 * n_dot_v * n_dot_l);
+    vec3 diffuse = (1.0 - fresnel) * diffuse_color / PI;
+
+    return (diffuse + specular) * light_color * n_dot_l;
+}
+
+// The scale and bias to apply to `f0` to get the total reflectance of the
+// environment, averaged over the specular lobe.
+//
+// This is usually looked up in a precomputed texture. Instead, we use
+// Karis' analytic fit of that texture, from "Physically Based Shading on
+// Mobile".
+vec2 environment_brdf(float roughness, float n_dot_v) {
+    const vec4 c0 = vec4(-1.0, -0.0275, -0.572, 0.022);
+    const vec4 c1 = vec4(1.0, 0.0425, 1.04, -0.04);
+
+    vec4 r = roughness * c0 + c1;
+    float a004 = min(r.x * r.x, exp2(-9.28 * n_dot_v)) * r.x + r.y;
+
+    return vec2(-1.04, 1.04) * a004 + r.zw;
+}
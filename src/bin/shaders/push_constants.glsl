@@ -0,0 +1,12 @@
+// The data for a single draw, set with push constants before each one.
+// Every shader using it has to declare the same block, so that it matches
+// the Rust struct that fills it.
+//
+// The normal matrix keeps normals perpendicular to their surface, even if
+// the transform scales unevenly. The light count says how many of the
+// lights in `lighting.glsl` are in use.
+layout(push_constant) uniform PushConstants {
+    mat4 transform;
+    mat3 normal_matrix;
+    uint light_count;
+} push_constants;
@@ -0,0 +1,7 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+// The shadow pass has no color attachment: the depth test writes the depth,
+// so there's nothing left for the fragment shader to do.
+void main() {
+}
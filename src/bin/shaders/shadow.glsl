@@ -0,0 +1,6 @@
+// The directional light's view of the scene, for shadow mapping. Positions
+// transformed by this matrix end up in the light's clip space, which is
+// where the shadow map was rendered.
+layout(set = 1, binding = 4) uniform Shadow {
+    mat4 view_projection;
+} shadow;
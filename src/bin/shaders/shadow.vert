@@ -0,0 +1,14 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) in vec3 position;
+
+#include "push_constants.glsl"
+#include "shadow.glsl"
+
+// The shadow map only needs each teapot's depth as seen from the light, so
+// this is the teapot's vertex shader with the light in place of the camera,
+// and nothing else.
+void main() {
+    gl_Position = shadow.view_projection * push_constants.transform * vec4(position, 1.0);
+}
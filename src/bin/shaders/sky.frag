@@ -0,0 +1,26 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(set = 0, binding = 1) uniform sampler sky_sampler;
+layout(set = 0, binding = 2) uniform textureCube sky_texture;
+
+#include "camera.glsl"
+#include "sky.glsl"
+
+layout(location = 0) in vec2 vertex_ndc;
+
+layout(location = 0) out vec4 fragment_color;
+
+// The sky isn't a surface, so it has no normal or depth.
+layout(location = 1) out vec4 fragment_normal_depth;
+
+void main() {
+    // The direction we're looking in for this pixel. Cube maps are sampled
+    // with a direction, which doesn't even need to be normalized.
+    vec3 direction = camera.view_right.xyz * vertex_ndc.x
+        + camera.view_down.xyz * vertex_ndc.y
+        + camera.view_forward.xyz;
+
+    fragment_color = texture(samplerCube(sky_texture, sky_sampler), sky_direction(direction));
+    fragment_normal_depth = vec4(0.0);
+}
@@ -0,0 +1,7 @@
+// Sampling the sky cube map.
+
+// The world has y pointing down, but the sky cube map has it pointing up, so
+// world space directions need flipping before they're used to sample it.
+vec3 sky_direction(vec3 world_direction) {
+    return vec3(world_direction.x, -world_direction.y, world_direction.z);
+}
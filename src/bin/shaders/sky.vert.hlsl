@@ -0,0 +1,22 @@
+// The sky's vertex shader is written in HLSL, to show that HLSL and GLSL
+// shaders can be mixed in one pipeline. Its entry point is given by the
+// `vertex_entry_point` of the `sky` pipeline in `assets/pipelines.ron`.
+
+struct VertexOutput {
+    float4 position : SV_Position;
+    [[vk::location(0)]] float2 ndc : TEXCOORD0;
+};
+
+// This draws a single triangle big enough to cover the whole screen, with
+// no vertex buffer required. The vertices are at (-1, -1), (-1, 3), and
+// (3, -1) in normalized device coordinates.
+VertexOutput vs_main(uint vertex_index : SV_VertexID) {
+    float2 uv = float2(vertex_index & 2, (vertex_index << 1) & 2);
+
+    VertexOutput output;
+    output.ndc = uv * 2.0 - 1.0;
+
+    // A depth of 1.0 puts the sky on the far plane, behind everything else.
+    output.position = float4(output.ndc, 1.0, 1.0);
+    return output;
+}
@@ -0,0 +1,219 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(set = 0, binding = 0) uniform texture2D teapot_texture;
+layout(set = 0, binding = 1) uniform sampler teapot_sampler;
+layout(set = 0, binding = 2) uniform textureCube environment_texture;
+layout(set = 0, binding = 3) uniform texture2D metallic_roughness_texture;
+layout(set = 0, binding = 4) uniform texture2D shadow_map;
+
+// A comparison sampler: instead of the depth in the shadow map, sampling
+// gives how much of the filtered area is nearer than the depth we pass in.
+layout(set = 0, binding = 5) uniform samplerShadow shadow_sampler;
+
+#include "camera.glsl"
+#include "push_constants.glsl"
+#include "lighting.glsl"
+#include "pbr.glsl"
+#include "sky.glsl"
+
+// The ways of drawing the teapot. These match `ViewMode` in `teapot/scene.rs`.
+const uint VIEW_BLINN_PHONG = 0;
+const uint VIEW_PBR = 1;
+const uint VIEW_NORMALS = 2;
+const uint VIEW_DEPTH = 3;
+const uint VIEW_WIREFRAME = 4;
+
+layout(constant_id = 0) const uint VIEW_MODE = VIEW_PBR;
+
+// Devices without `NON_FILL_POLYGON_MODE` can't draw a wireframe with lines,
+// so instead we fill the triangles and throw away everything but the edges.
+layout(constant_id = 1) const bool BARYCENTRIC_WIREFRAME = false;
+
+// The range of distances the depth view shows, from white to black. This
+// roughly covers the teapot from where the camera is.
+const float DEPTH_VIEW_NEAR = 0.6;
+const float DEPTH_VIEW_FAR = 1.8;
+
+// Flags for which textures a material uses. These match
+// `MaterialConfig::texture_flags`.
+const uint TEXTURE_BASE_COLOR = 1;
+const uint TEXTURE_METALLIC_ROUGHNESS = 2;
+
+layout(set = 1, binding = 1) uniform Material {
+    vec4 base_color;
+    float metallic;
+    float roughness;
+    uint texture_flags;
+} material;
+
+layout(location = 0) in vec2 vertex_uv;
+layout(location = 1) in vec3 vertex_normal;
+layout(location = 2) in vec3 vertex_position;
+layout(location = 3) in vec3 vertex_barycentric;
+layout(location = 4) in vec4 vertex_shadow_position;
+
+layout(location = 0) out vec4 fragment_color;
+
+// The surface's normal, and its distance in front of the camera, for the
+// toon pass to find the edges of things.
+layout(location = 1) out vec4 fragment_normal_depth;
+
+// The light reflected towards the eye from a single light, using the
+// lighting model for the current view mode.
+//
+// The Blinn-Phong view approximates the same material, to compare the two
+// models: its specular color is the reflectance `f0`, and its shininess is
+// picked to give highlights about the same size as the GGX ones.
+vec3 direct_light(
+    vec3 normal,
+    vec3 to_light,
+    vec3 to_eye,
+    vec3 light_color,
+    vec3 diffuse_color,
+    vec3 f0,
+    float roughness
+) {
+    if (VIEW_MODE == VIEW_BLINN_PHONG) {
+        float alpha = roughness * roughness;
+        float shininess = 2.0 / (alpha * alpha) - 2.0;
+
+        return blinn_phong(normal, to_light, to_eye, light_color, diffuse_color, f0, shininess);
+    }
+
+    return cook_torrance(normal, to_light, to_eye, light_color, diffuse_color, f0, roughness);
+}
+
+// How much of the directional light reaches this fragment, from 0.0 in full
+// shadow to 1.0 fully lit.
+//
+// Each comparison is already filtered between neighbouring texels, and we
+// average a 3x3 block of them on top of that (percentage-closer filtering),
+// which softens the shadow's jagged edges.
+float directional_shadow(vec4 shadow_position) {
+    vec3 coords = shadow_position.xyz / shadow_position.w;
+    vec2 uv = coords.xy * 0.5 + 0.5;
+
+    // Anything outside the shadow map isn't shadowed by anything in it.
+    if (any(lessThan(uv, vec2(0.0))) || any(greaterThan(uv, vec2(1.0))) || coords.z > 1.0) {
+        return 1.0;
+    }
+
+    vec2 texel_size = 1.0 / vec2(textureSize(sampler2DShadow(shadow_map, shadow_sampler), 0));
+    float lit = 0.0;
+
+    for (int x = -1; x <= 1; x++) {
+        for (int y = -1; y <= 1; y++) {
+            vec2 offset = vec2(x, y) * texel_size;
+            lit += texture(sampler2DShadow(shadow_map, shadow_sampler), vec3(uv + offset, coords.z));
+        }
+    }
+
+    return lit / 9.0;
+}
+
+void main() {
+    float view_depth = dot(vertex_position - camera.position.xyz, camera.view_forward.xyz);
+    fragment_normal_depth = vec4(normalize(vertex_normal), view_depth);
+
+    if (VIEW_MODE == VIEW_NORMALS) {
+        // Normals range from -1 to 1, so we squash them into the range of
+        // colors.
+        fragment_color = vec4(normalize(vertex_normal) * 0.5 + 0.5, 1.0);
+        return;
+    }
+
+    if (VIEW_MODE == VIEW_DEPTH) {
+        float brightness = 1.0 - smoothstep(DEPTH_VIEW_NEAR, DEPTH_VIEW_FAR, view_depth);
+        fragment_color = vec4(vec3(brightness), 1.0);
+        return;
+    }
+
+    if (VIEW_MODE == VIEW_WIREFRAME) {
+        if (BARYCENTRIC_WIREFRAME) {
+            // Each barycentric coordinate is 0 along one edge. Dividing by
+            // how much it changes per pixel gives the distance to that edge
+            // in pixels, so the lines stay a pixel wide at any distance.
+            vec3 edge_distance = vertex_barycentric / fwidth(vertex_barycentric);
+
+            if (min(edge_distance.x, min(edge_distance.y, edge_distance.z)) > 1.0) {
+                discard;
+            }
+        }
+
+        fragment_color = vec4(1.0);
+        return;
+    }
+
+    vec3 normal = normalize(vertex_normal);
+    vec3 view_direction = normalize(vertex_position - camera.position.xyz);
+    vec3 to_eye = -view_direction;
+    vec3 reflection = reflect(view_direction, normal);
+
+    vec4 base_color = material.base_color;
+    float metallic = material.metallic;
+    float roughness = material.roughness;
+
+    if ((material.texture_flags & TEXTURE_BASE_COLOR) != 0) {
+        base_color *= texture(sampler2D(teapot_texture, teapot_sampler), vertex_uv);
+    }
+
+    if ((material.texture_flags & TEXTURE_METALLIC_ROUGHNESS) != 0) {
+        vec4 texel = texture(sampler2D(metallic_roughness_texture, teapot_sampler), vertex_uv);
+        roughness *= texel.g;
+        metallic *= texel.b;
+    }
+
+    // A perfectly smooth surface would make the highlights of our tiny
+    // lights infinitely small and bright, so we stop a little short of it.
+    roughness = max(roughness, 0.03);
+
+    // Metals don't have a diffuse color: all the light they reflect comes
+    // from the specular term, tinted by the base color. Dielectrics reflect
+    // about 4% of the light that hits them head-on, whatever their color.
+    vec3 diffuse_color = base_color.rgb * (1.0 - metallic);
+    vec3 f0 = mix(vec3(0.04), base_color.rgb, metallic);
+
+    vec3 lit_color = direct_light(
+        normal,
+        -normalize(lighting.direction.xyz),
+        to_eye,
+        lighting.color.rgb * directional_shadow(vertex_shadow_position),
+        diffuse_color,
+        f0,
+        roughness
+    );
+
+    for (uint i = 0; i < push_constants.light_count; i++) {
+        vec3 to_light;
+        vec3 light_color = light_intensity(lights[i], vertex_position, to_light);
+
+        lit_color += direct_light(
+            normal,
+            to_light,
+            to_eye,
+            light_color,
+            diffuse_color,
+            f0,
+            roughness
+        );
+    }
+
+    // The sky's light. The diffuse part comes from the spherical harmonics.
+    // For the specular part, we sample the sky in the reflected direction,
+    // using blurrier mip levels for rougher surfaces. That's not exactly
+    // how a rough surface blurs its reflections, but it's close enough.
+    float max_lod =
+        float(textureQueryLevels(samplerCube(environment_texture, teapot_sampler)) - 1);
+    vec3 environment_color = textureLod(
+        samplerCube(environment_texture, teapot_sampler),
+        sky_direction(reflection),
+        roughness * max_lod
+    ).rgb;
+    vec2 environment_scale_bias = environment_brdf(roughness, max(dot(normal, to_eye), 0.0));
+
+    lit_color += diffuse_color * sky_irradiance(normal);
+    lit_color += environment_color * (f0 * environment_scale_bias.x + environment_scale_bias.y);
+
+    fragment_color = vec4(lit_color, base_color.a);
+}
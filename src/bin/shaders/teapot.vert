@@ -0,0 +1,33 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec3 normal;
+layout(location = 2) in vec2 uv;
+
+#include "camera.glsl"
+#include "push_constants.glsl"
+#include "shadow.glsl"
+
+layout(location = 0) out vec2 vertex_uv;
+layout(location = 1) out vec3 vertex_normal;
+layout(location = 2) out vec3 vertex_position;
+layout(location = 3) out vec3 vertex_barycentric;
+layout(location = 4) out vec4 vertex_shadow_position;
+
+void main() {
+    vec4 world_position = push_constants.transform * vec4(position, 1.0);
+
+    vertex_uv = uv;
+    vertex_normal = push_constants.normal_matrix * normal;
+    vertex_position = world_position.xyz;
+    vertex_shadow_position = shadow.view_projection * world_position;
+
+    // The mesh isn't indexed, so every three vertices make a new triangle.
+    // Giving each corner a different axis lets the fragment shader tell how
+    // close it is to each edge, for drawing wireframes.
+    vertex_barycentric = vec3(0.0);
+    vertex_barycentric[gl_VertexIndex % 3] = 1.0;
+
+    gl_Position = camera.view_projection * world_position;
+}
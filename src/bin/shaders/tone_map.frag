@@ -0,0 +1,56 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(set = 0, binding = 0) uniform texture2D hdr_texture;
+layout(set = 0, binding = 1) uniform sampler hdr_sampler;
+
+// The blurred bright parts of the scene, from the bloom passes, at half the
+// size of the scene.
+layout(set = 0, binding = 2) uniform texture2D bloom_texture;
+
+// The ways of squeezing HDR colors into the range the screen can show.
+// These match `ToneMapping` in `teapot/post.rs`.
+const uint TONE_MAP_CLAMP = 0;
+const uint TONE_MAP_REINHARD = 1;
+const uint TONE_MAP_ACES = 2;
+
+// The exposure is a plain multiplier, already converted from stops.
+layout(push_constant) uniform ToneMapConstants {
+    float exposure;
+    uint tone_mapping;
+    float bloom_intensity;
+} tone_map;
+
+layout(location = 0) in vec2 vertex_uv;
+
+layout(location = 0) out vec4 fragment_color;
+
+// Krzysztof Narkowicz's fit of the ACES filmic curve. Compared to Reinhard,
+// it keeps more contrast in the darks, and rolls bright colors off more
+// gently towards white.
+vec3 aces_filmic(vec3 color) {
+    vec3 numerator = color * (2.51 * color + 0.03);
+    vec3 denominator = color * (2.43 * color + 0.59) + 0.14;
+    return numerator / denominator;
+}
+
+void main() {
+    vec3 color = texture(sampler2D(hdr_texture, hdr_sampler), vertex_uv).rgb;
+
+    // The bloom is light too, so it's added before the exposure and tone
+    // mapping, rather than on top of the finished image.
+    vec3 bloom = texture(sampler2D(bloom_texture, hdr_sampler), vertex_uv).rgb;
+    color += bloom * tone_map.bloom_intensity;
+
+    color *= tone_map.exposure;
+
+    if (tone_map.tone_mapping == TONE_MAP_REINHARD) {
+        color = color / (1.0 + color);
+    } else if (tone_map.tone_mapping == TONE_MAP_ACES) {
+        color = aces_filmic(color);
+    }
+
+    // The swapchain image is sRGB, so the color is still linear here: the
+    // conversion happens when it's written.
+    fragment_color = vec4(clamp(color, 0.0, 1.0), 1.0);
+}
@@ -0,0 +1,70 @@
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(set = 0, binding = 0) uniform texture2D scene_texture;
+layout(set = 0, binding = 1) uniform sampler scene_sampler;
+layout(set = 0, binding = 2) uniform texture2D normal_depth_texture;
+
+// How many flat bands the lighting is split into.
+const float BANDS = 4.0;
+
+// How different neighbouring pixels have to be to draw an outline between
+// them. The depth threshold is relative to their distance from the camera,
+// so outlines don't get more sensitive further away.
+const float NORMAL_THRESHOLD = 0.4;
+const float DEPTH_THRESHOLD = 0.05;
+
+const vec3 OUTLINE_COLOR = vec3(0.0);
+
+layout(location = 0) in vec2 vertex_uv;
+
+layout(location = 0) out vec4 fragment_color;
+
+float luminance(vec3 color) {
+    return dot(color, vec3(0.2126, 0.7152, 0.0722));
+}
+
+// Whether there's an edge between two pixels, given their normals and
+// depths. The sky has a normal and depth of zero, so there's always an edge
+// between it and a surface.
+bool is_edge(vec4 a, vec4 b) {
+    bool normal_edge = distance(a.xyz, b.xyz) > NORMAL_THRESHOLD;
+    bool depth_edge = abs(a.w - b.w) > DEPTH_THRESHOLD * max(a.w, b.w);
+    return normal_edge || depth_edge;
+}
+
+void main() {
+    vec3 color = texture(sampler2D(scene_texture, scene_sampler), vertex_uv).rgb;
+
+    // The scene is HDR, so brightness has no upper limit. To split it into
+    // a few bands, we squash it into the 0 to 1 range first, with the same
+    // curve as Reinhard tone mapping, then move each pixel to the middle of
+    // its band, and scale its color to match. That flattens the shading,
+    // but keeps the hues.
+    float brightness = luminance(color);
+    float squashed = brightness / (1.0 + brightness);
+    float banded = (min(floor(squashed * BANDS), BANDS - 1.0) + 0.5) / BANDS;
+    float banded_brightness = banded / (1.0 - banded);
+
+    color *= banded_brightness / max(brightness, 0.0001);
+
+    // Compare each pixel with the ones next to it, and outline it if it's
+    // on the edge of something. The offsets are a whole pixel, so each
+    // sample lands exactly on a pixel, and isn't blended with its
+    // neighbours.
+    vec2 texel_size = 1.0 / vec2(textureSize(sampler2D(normal_depth_texture, scene_sampler), 0));
+    vec4 center = texture(sampler2D(normal_depth_texture, scene_sampler), vertex_uv);
+    vec2 offsets[4] = vec2[](vec2(1.0, 0.0), vec2(-1.0, 0.0), vec2(0.0, 1.0), vec2(0.0, -1.0));
+
+    for (int i = 0; i < 4; i++) {
+        vec2 uv = vertex_uv + offsets[i] * texel_size;
+        vec4 neighbour = texture(sampler2D(normal_depth_texture, scene_sampler), uv);
+
+        if (is_edge(center, neighbour)) {
+            color = OUTLINE_COLOR;
+            break;
+        }
+    }
+
+    fragment_color = vec4(color, 1.0);
+}
@@ -0,0 +1,2383 @@
+mod pipeline;
+mod post;
+mod scene;
+mod shadow;
+mod sky;
+
+use gfx_hal_tutorials::{
+    buffer, camera, camera::Camera, file_watcher::FileWatcher, light::Light, light::LightBuffer,
+    material, material::MaterialConfig, mesh, mesh::Vertex, pipeline_cache::PipelineCacheFile,
+    pipeline_config, pipeline_config::PipelineConfig, pipeline_config::PipelineConfigs, reflect,
+    reflect::PipelineLayoutDesc, reflect::PushConstantLayout, ring::UniformRing,
+    sampler::SamplerConfig, shader_data::ShaderData, shader_watcher::ShaderWatcher, shaders,
+    specialization::SpecConstants, texture, texture::Texture,
+};
+
+use pipeline::PipelineContext;
+use post::{
+    BloomConstants, ToneMapConstants, ToneMapping, BLOOM_BRIGHT_SET, BLOOM_TARGETS,
+    FIRST_BLOOM_SET, FIRST_POST_SET, HDR_FORMAT, HDR_TARGET, NORMAL_DEPTH_TARGET, POST_SET_INPUTS,
+    TONE_MAP_SET, TOON_SET, TOON_TARGET, TOON_TONE_MAP_SET,
+};
+use scene::{CameraUniforms, LightingUniforms, MaterialUniforms, PushConstants, ViewMode};
+use shadow::{ShadowUniforms, SHADOW_MAP_SIZE};
+
+/// Read a file from the `assets` directory, so that edits to it take effect
+/// without a rebuild. If it can't be read (for example, if the binary has been
+/// moved away from the source tree), the copy embedded at build time is used.
+fn load_asset(file_name: &str, embedded: &'static str) -> String {
+    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("assets")
+        .join(file_name);
+
+    std::fs::read_to_string(&path).unwrap_or_else(|error| {
+        eprintln!(
+            "Couldn't read {}, using the embedded copy: {}",
+            path.display(),
+            error
+        );
+        embedded.to_owned()
+    })
+}
+
+fn main() {
+    use std::collections::HashMap;
+    use std::mem::ManuallyDrop;
+
+    use gfx_hal::{
+        device::Device,
+        window::{Extent2D, PresentationSurface, Surface},
+        Features, Instance,
+    };
+
+    const APP_NAME: &'static str = "Teapot";
+    const WINDOW_SIZE: [u32; 2] = [512, 512];
+
+    let event_loop = winit::event_loop::EventLoop::new();
+
+    let (logical_window_size, physical_window_size) = {
+        use winit::dpi::{LogicalSize, PhysicalSize};
+
+        let dpi = event_loop.primary_monitor().scale_factor();
+        let logical: LogicalSize<u32> = WINDOW_SIZE.into();
+        let physical: PhysicalSize<u32> = logical.to_physical(dpi);
+
+        (logical, physical)
+    };
+
+    let mut surface_extent = Extent2D {
+        width: physical_window_size.width,
+        height: physical_window_size.height,
+    };
+
+    let window = winit::window::WindowBuilder::new()
+        .with_title(APP_NAME)
+        .with_inner_size(logical_window_size)
+        .build(&event_loop)
+        .expect("Failed to create window");
+
+    let (instance, surface, adapter) = {
+        let instance = backend::Instance::create(APP_NAME, 1).expect("Backend not supported");
+
+        let surface = unsafe {
+            instance
+                .create_surface(&window)
+                .expect("Failed to create surface for window")
+        };
+
+        let adapter = instance.enumerate_adapters().remove(0);
+
+        (instance, surface, adapter)
+    };
+
+    let (device, mut queue_group, features) = {
+        use gfx_hal::queue::QueueFamily;
+
+        let queue_family = adapter
+            .queue_families
+            .iter()
+            .find(|family| {
+                surface.supports_queue_family(family) && family.queue_type().supports_graphics()
+            })
+            .expect("No compatible queue family found");
+
+        // Optional features are only enabled if the device supports them, so
+        // we have to remember which ones we ended up with.
+        let features = {
+            use gfx_hal::adapter::PhysicalDevice;
+
+            adapter.physical_device.features()
+                & (SamplerConfig::wanted_features() | PipelineConfig::wanted_features())
+        };
+
+        let mut gpu = unsafe {
+            use gfx_hal::adapter::PhysicalDevice;
+
+            adapter
+                .physical_device
+                .open(&[(queue_family, &[1.0])], features)
+                .expect("Failed to open device")
+        };
+
+        (gpu.device, gpu.queue_groups.pop().unwrap(), features)
+    };
+
+    // Compiled pipelines are saved to disk when we exit, and loaded again
+    // here, so that creating them is quicker next time.
+    let (pipeline_cache_file, pipeline_cache) = unsafe {
+        let file = PipelineCacheFile::new::<backend::Backend>(&device, &adapter.info, APP_NAME);
+        let cache = file.load::<backend::Backend>(&device);
+
+        (file, cache)
+    };
+
+    let (mut command_pool, mut command_buffer) = unsafe {
+        use gfx_hal::command::Level;
+        use gfx_hal::pool::{CommandPool, CommandPoolCreateFlags};
+
+        let mut command_pool = device
+            .create_command_pool(queue_group.family, CommandPoolCreateFlags::empty())
+            .expect("Out of memory");
+
+        let command_buffer = command_pool.allocate_one(Level::Primary);
+
+        (command_pool, command_buffer)
+    };
+
+    let surface_color_format = {
+        use gfx_hal::format::{ChannelType, Format};
+
+        let supported_formats = surface
+            .supported_formats(&adapter.physical_device)
+            .unwrap_or(vec![]);
+
+        let default_format = *supported_formats.get(0).unwrap_or(&Format::Rgba8Srgb);
+
+        supported_formats
+            .into_iter()
+            .find(|format| format.base_format().1 == ChannelType::Srgb)
+            .unwrap_or(default_format)
+    };
+
+    let binary_mesh_data = include_bytes!("../../../assets/teapot_mesh.bin");
+    let mesh = mesh::deserialize(binary_mesh_data);
+
+    let (vertex_buffer_memory, vertex_buffer) = unsafe {
+        use gfx_hal::buffer::Usage;
+
+        buffer::make_filled_buffer::<backend::Backend, _>(
+            &device,
+            &adapter.physical_device,
+            &mesh,
+            Usage::VERTEX,
+        )
+    };
+
+    // A line for each face of the teapot, showing which way it faces.
+    let face_normals = mesh::face_normal_lines(&mesh, 0.02);
+
+    let (face_normal_buffer_memory, face_normal_buffer) = unsafe {
+        use gfx_hal::buffer::Usage;
+
+        buffer::make_filled_buffer::<backend::Backend, _>(
+            &device,
+            &adapter.physical_device,
+            &face_normals,
+            Usage::VERTEX,
+        )
+    };
+
+    // A square of ground for the teapot to sit on, and cast its shadow onto.
+    // The teapot's base is at `y = 0.23` (remember that `y` points down), so
+    // the ground is just below it, facing up.
+    let ground = {
+        let (y, half_size) = (0.23, 1.5);
+        let corner = |x: f32, z: f32| Vertex {
+            position: [x * half_size, y, z * half_size],
+            normal: [0.0, -1.0, 0.0],
+            uv: [x * 0.5 + 0.5, z * 0.5 + 0.5],
+        };
+
+        vec![
+            corner(-1.0, -1.0),
+            corner(1.0, -1.0),
+            corner(1.0, 1.0),
+            corner(-1.0, -1.0),
+            corner(1.0, 1.0),
+            corner(-1.0, 1.0),
+        ]
+    };
+
+    let (ground_buffer_memory, ground_buffer) = unsafe {
+        use gfx_hal::buffer::Usage;
+
+        buffer::make_filled_buffer::<backend::Backend, _>(
+            &device,
+            &adapter.physical_device,
+            &ground,
+            Usage::VERTEX,
+        )
+    };
+
+    // The texture is stored as a PNG, so we decode it into raw RGBA pixels
+    // before uploading it. The pixels are in the sRGB color space, so we use
+    // an sRGB format. That way the sampler converts them to linear values
+    // for us when the shader reads them.
+    let teapot_texture = unsafe {
+        use gfx_hal::format::Format;
+
+        let png_data = include_bytes!("../../../assets/floral_teapot.png");
+        let pixels = image::load_from_memory(png_data)
+            .expect("Failed to decode texture")
+            .to_rgba8();
+
+        texture::upload_texture::<backend::Backend>(
+            &device,
+            &adapter.physical_device,
+            &mut command_pool,
+            &mut queue_group.queues[0],
+            &pixels,
+            Format::Rgba8Srgb,
+        )
+    };
+
+    // Each material can scale its metallic and roughness by this texture.
+    // Unlike the color texture, its values aren't colors, so they aren't
+    // sRGB-encoded, and we use a linear format.
+    let metallic_roughness_texture = unsafe {
+        use gfx_hal::format::Format;
+
+        let png_data = include_bytes!("../../../assets/floral_teapot_metallic_roughness.png");
+        let pixels = image::load_from_memory(png_data)
+            .expect("Failed to decode texture")
+            .to_rgba8();
+
+        texture::upload_texture::<backend::Backend>(
+            &device,
+            &adapter.physical_device,
+            &mut command_pool,
+            &mut queue_group.queues[0],
+            &pixels,
+            Format::Rgba8Unorm,
+        )
+    };
+
+    let (sky_texture, sky_irradiance) = unsafe {
+        sky::upload_sky::<backend::Backend>(
+            &device,
+            &adapter.physical_device,
+            &mut command_pool,
+            &mut queue_group.queues[0],
+        )
+    };
+
+    // The sampler decides how the texture is read: how it is filtered when
+    // it's scaled up or down, and what happens with coordinates outside the
+    // `0.0..1.0` range.
+    //
+    // The settings are loaded from `assets/sampler.ron`, which is shared by
+    // every textured material. It's read at startup, so it can be tweaked
+    // without rebuilding.
+    let sampler = unsafe {
+        use gfx_hal::adapter::PhysicalDevice;
+
+        let config = SamplerConfig::from_ron(&load_asset(
+            "sampler.ron",
+            include_str!("../../../assets/sampler.ron"),
+        ))
+        .expect("Failed to parse sampler config");
+
+        device
+            .create_sampler(&config.sampler_desc(features, &adapter.physical_device.limits()))
+            .expect("Failed to create sampler")
+    };
+
+    // The shadow map is the scene's depth as seen from the directional light.
+    let shadow_map_format =
+        texture::find_shadow_map_format::<backend::Backend>(&adapter.physical_device);
+
+    let shadow_map = unsafe {
+        texture::make_shadow_map::<backend::Backend>(
+            &device,
+            &adapter.physical_device,
+            shadow_map_format,
+            SHADOW_MAP_SIZE,
+        )
+    };
+
+    let shadow_sampler = unsafe {
+        shadow::make_sampler::<backend::Backend>(
+            &device,
+            &adapter.physical_device,
+            shadow_map_format,
+        )
+    };
+
+    // Post-processing passes read their input with this sampler. Their
+    // inputs are the same size as the screen, so it rarely matters how it
+    // filters, but it shouldn't wrap around at the edges.
+    let post_sampler = unsafe {
+        use gfx_hal::image::{Filter, SamplerDesc, WrapMode};
+
+        device
+            .create_sampler(&SamplerDesc::new(Filter::Linear, WrapMode::Clamp))
+            .expect("Failed to create sampler")
+    };
+
+    /// Which function a pipeline is made with.
+    #[derive(Debug, Clone, Copy)]
+    enum PipelineKind {
+        Teapot,
+        Sky,
+        FaceNormals,
+        Shadow,
+        ToneMap,
+        Toon,
+        BloomBright,
+        Bloom,
+    }
+
+    // Devices without `NON_FILL_POLYGON_MODE` can't draw lines instead of
+    // filled triangles, so the fragment shader draws the wireframe instead.
+    let line_wireframe = features.contains(Features::NON_FILL_POLYGON_MODE);
+
+    // Every pipeline we make, in the same order as the `pipelines` in
+    // `Resources`: the name of its config in `assets/pipelines.ron`, which
+    // function makes it, and the specialization constants for its fragment
+    // shader. The teapot has one pipeline for each `ViewMode`, which come
+    // first.
+    let pipeline_list: Vec<(&str, PipelineKind, SpecConstants)> = {
+        let teapot = |mode: ViewMode| {
+            let wireframe = mode == ViewMode::Wireframe;
+            let config_name = if wireframe && line_wireframe {
+                "teapot_wireframe"
+            } else {
+                "teapot"
+            };
+            let constants = SpecConstants::new()
+                .with(0, mode as u32)
+                .with(1, wireframe && !line_wireframe);
+
+            (config_name, PipelineKind::Teapot, constants)
+        };
+
+        ViewMode::ALL
+            .iter()
+            .map(|&mode| teapot(mode))
+            .chain(vec![
+                ("sky", PipelineKind::Sky, SpecConstants::new()),
+                (
+                    "face_normals",
+                    PipelineKind::FaceNormals,
+                    SpecConstants::new(),
+                ),
+                ("shadow", PipelineKind::Shadow, SpecConstants::new()),
+                ("tone_map", PipelineKind::ToneMap, SpecConstants::new()),
+                ("toon", PipelineKind::Toon, SpecConstants::new()),
+                (
+                    "bloom_bright",
+                    PipelineKind::BloomBright,
+                    SpecConstants::new(),
+                ),
+                ("bloom_down", PipelineKind::Bloom, SpecConstants::new()),
+                ("bloom_up", PipelineKind::Bloom, SpecConstants::new()),
+            ])
+            .collect()
+    };
+    const SKY_PIPELINE: usize = ViewMode::ALL.len();
+    const FACE_NORMALS_PIPELINE: usize = ViewMode::ALL.len() + 1;
+    const SHADOW_PIPELINE: usize = ViewMode::ALL.len() + 2;
+    const TONE_MAP_PIPELINE: usize = ViewMode::ALL.len() + 3;
+    const TOON_PIPELINE: usize = ViewMode::ALL.len() + 4;
+    const BLOOM_BRIGHT_PIPELINE: usize = ViewMode::ALL.len() + 5;
+    const BLOOM_DOWN_PIPELINE: usize = ViewMode::ALL.len() + 6;
+    const BLOOM_UP_PIPELINE: usize = ViewMode::ALL.len() + 7;
+
+    let config_names: Vec<&str> = pipeline_list.iter().map(|&(name, _, _)| name).collect();
+
+    /// Pick out the config for each of the `config_names`.
+    fn select_configs(
+        configs: &PipelineConfigs,
+        config_names: &[&str],
+    ) -> Result<Vec<PipelineConfig>, String> {
+        config_names
+            .iter()
+            .map(|&name| {
+                configs
+                    .get(name)
+                    .cloned()
+                    .ok_or_else(|| format!("There's no config for the `{}` pipeline", name))
+            })
+            .collect()
+    }
+
+    let mut pipeline_configs = pipeline_config::configs_from_ron(&load_asset(
+        "pipelines.ron",
+        include_str!("../../../assets/pipelines.ron"),
+    ))
+    .map_err(|error| error.to_string())
+    .and_then(|configs| select_configs(&configs, &config_names))
+    .unwrap_or_else(|message| panic!("Failed to load pipeline configs: {}", message));
+
+    // The shaders are compiled to SPIR-V by the build script, so any errors
+    // in them show up when we build, rather than when we run. We keep our
+    // own copy of each one, by constant name, so that they can be replaced
+    // when they're reloaded.
+    let mut shader_spirv: HashMap<String, Vec<u32>> = shaders::ALL
+        .iter()
+        .map(|&(name, spirv)| (name.to_owned(), spirv.to_vec()))
+        .collect();
+
+    /// Find the SPIR-V for the vertex and fragment shaders in a config, or
+    /// the variants of them if it has defines.
+    fn pipeline_spirv<'a>(
+        shader_spirv: &'a HashMap<String, Vec<u32>>,
+        config: &PipelineConfig,
+    ) -> Result<(&'a [u32], &'a [u32]), String> {
+        let find = |name: &str| {
+            shader_spirv
+                .get(name)
+                .map(Vec::as_slice)
+                .ok_or_else(|| format!("There's no shader called `{}`", name))
+        };
+
+        let (vertex_name, fragment_name) = config.shader_names();
+        Ok((find(&vertex_name)?, find(&fragment_name)?))
+    }
+
+    let (vertex_spirv, fragment_spirv) = pipeline_spirv(&shader_spirv, &pipeline_configs[0])
+        .unwrap_or_else(|message| panic!("{}", message));
+    let (sky_vertex_spirv, sky_fragment_spirv) =
+        pipeline_spirv(&shader_spirv, &pipeline_configs[SKY_PIPELINE])
+            .unwrap_or_else(|message| panic!("{}", message));
+    let (shadow_vertex_spirv, shadow_fragment_spirv) =
+        pipeline_spirv(&shader_spirv, &pipeline_configs[SHADOW_PIPELINE])
+            .unwrap_or_else(|message| panic!("{}", message));
+    let (tone_map_vertex_spirv, tone_map_fragment_spirv) =
+        pipeline_spirv(&shader_spirv, &pipeline_configs[TONE_MAP_PIPELINE])
+            .unwrap_or_else(|message| panic!("{}", message));
+    let (toon_vertex_spirv, toon_fragment_spirv) =
+        pipeline_spirv(&shader_spirv, &pipeline_configs[TOON_PIPELINE])
+            .unwrap_or_else(|message| panic!("{}", message));
+    let bloom_spirv: Vec<(&[u32], &[u32])> = [
+        BLOOM_BRIGHT_PIPELINE,
+        BLOOM_DOWN_PIPELINE,
+        BLOOM_UP_PIPELINE,
+    ]
+    .iter()
+    .map(|&index| pipeline_spirv(&shader_spirv, &pipeline_configs[index]))
+    .collect::<Result<_, _>>()
+    .unwrap_or_else(|message| panic!("{}", message));
+
+    // Each shader already says which descriptors and push constants it uses,
+    // so rather than writing out the layouts by hand, we reflect the SPIR-V
+    // and build them from that.
+    let (teapot_stages, sky_stages, shadow_stages, tone_map_stages, toon_stages, bloom_stages) = {
+        use gfx_hal::pso::ShaderStageFlags;
+        use reflect::StageInterface;
+
+        let reflect = |stage, spirv: &[u32]| {
+            StageInterface::reflect(stage, spirv).expect("Failed to reflect shader")
+        };
+
+        (
+            [
+                reflect(ShaderStageFlags::VERTEX, vertex_spirv),
+                reflect(ShaderStageFlags::FRAGMENT, fragment_spirv),
+            ],
+            [
+                reflect(ShaderStageFlags::VERTEX, sky_vertex_spirv),
+                reflect(ShaderStageFlags::FRAGMENT, sky_fragment_spirv),
+            ],
+            [
+                reflect(ShaderStageFlags::VERTEX, shadow_vertex_spirv),
+                reflect(ShaderStageFlags::FRAGMENT, shadow_fragment_spirv),
+            ],
+            [
+                reflect(ShaderStageFlags::VERTEX, tone_map_vertex_spirv),
+                reflect(ShaderStageFlags::FRAGMENT, tone_map_fragment_spirv),
+            ],
+            [
+                reflect(ShaderStageFlags::VERTEX, toon_vertex_spirv),
+                reflect(ShaderStageFlags::FRAGMENT, toon_fragment_spirv),
+            ],
+            bloom_spirv
+                .iter()
+                .flat_map(|&(vertex_spirv, fragment_spirv)| {
+                    vec![
+                        reflect(ShaderStageFlags::VERTEX, vertex_spirv),
+                        reflect(ShaderStageFlags::FRAGMENT, fragment_spirv),
+                    ]
+                })
+                .collect::<Vec<_>>(),
+        )
+    };
+
+    // The teapot, the sky, and the shadow are drawn with the same descriptor
+    // sets, so their descriptor set layouts have to be identical. We build
+    // them from all six shaders at once. Set 0 has the teapot texture, a
+    // sampler, the sky cube map, the metallic-roughness texture, the shadow
+    // map, and its sampler. Set 1 has the uniform buffers (the camera, the
+    // material, the lighting, and the shadow) and the storage buffer of
+    // lights.
+    //
+    // The uniform buffers are _dynamic_ uniform buffers, which means we pass
+    // in an offset into the buffer each time we bind the set. That lets us
+    // write new data every frame (or every draw) without having to update
+    // the descriptor set itself. The shaders can't tell us that, so we have
+    // to say so ourselves.
+    let set_layout_desc = {
+        let all_stages: Vec<_> = teapot_stages
+            .iter()
+            .chain(&sky_stages)
+            .chain(&shadow_stages)
+            .cloned()
+            .collect();
+
+        let mut desc = PipelineLayoutDesc::from_stages(&all_stages)
+            .unwrap_or_else(|message| panic!("Failed to build set layouts: {}", message));
+        desc.make_uniforms_dynamic(1);
+        desc
+    };
+
+    let (descriptor_set_layout, uniform_set_layout) = unsafe {
+        let mut set_layouts = set_layout_desc.sets.iter().map(|bindings| {
+            device
+                .create_descriptor_set_layout(bindings, &[])
+                .expect("Out of memory")
+        });
+
+        (
+            set_layouts.next().expect("Shaders don't use set 0"),
+            set_layouts.next().expect("Shaders don't use set 1"),
+        )
+    };
+
+    // Descriptor sets are allocated from a pool, which has to be created with
+    // enough room for all of the sets (and descriptors) we'll allocate.
+    let mut descriptor_pool = unsafe {
+        use gfx_hal::pso::{
+            BufferDescriptorFormat, BufferDescriptorType, DescriptorPoolCreateFlags,
+            DescriptorRangeDesc, DescriptorType, ImageDescriptorType,
+        };
+
+        device
+            .create_descriptor_pool(
+                2,
+                &[
+                    DescriptorRangeDesc {
+                        ty: DescriptorType::Image {
+                            ty: ImageDescriptorType::Sampled {
+                                with_sampler: false,
+                            },
+                        },
+                        count: 4,
+                    },
+                    DescriptorRangeDesc {
+                        ty: DescriptorType::Sampler,
+                        count: 2,
+                    },
+                    DescriptorRangeDesc {
+                        ty: DescriptorType::Buffer {
+                            ty: BufferDescriptorType::Uniform,
+                            format: BufferDescriptorFormat::Structured {
+                                dynamic_offset: true,
+                            },
+                        },
+                        count: 4,
+                    },
+                    DescriptorRangeDesc {
+                        ty: DescriptorType::Buffer {
+                            ty: BufferDescriptorType::Storage { read_only: true },
+                            format: BufferDescriptorFormat::Structured {
+                                dynamic_offset: false,
+                            },
+                        },
+                        count: 1,
+                    },
+                ],
+                DescriptorPoolCreateFlags::empty(),
+            )
+            .expect("Out of memory")
+    };
+
+    // The descriptor set itself is where we point the shader bindings at our
+    // actual textures and sampler.
+    let descriptor_set = unsafe {
+        use gfx_hal::image::Layout;
+        use gfx_hal::pso::{Descriptor, DescriptorPool, DescriptorSetWrite};
+
+        let descriptor_set = descriptor_pool
+            .allocate_set(&descriptor_set_layout)
+            .expect("Failed to allocate descriptor set");
+
+        device.write_descriptor_sets(vec![DescriptorSetWrite {
+            set: &descriptor_set,
+            binding: 0,
+            array_offset: 0,
+            descriptors: vec![
+                Descriptor::Image(&teapot_texture.view, Layout::ShaderReadOnlyOptimal),
+                Descriptor::Sampler(&sampler),
+                Descriptor::Image(&sky_texture.view, Layout::ShaderReadOnlyOptimal),
+                Descriptor::Image(
+                    &metallic_roughness_texture.view,
+                    Layout::ShaderReadOnlyOptimal,
+                ),
+                Descriptor::Image(&shadow_map.view, Layout::ShaderReadOnlyOptimal),
+                Descriptor::Sampler(&shadow_sampler),
+            ],
+        }]);
+
+        descriptor_set
+    };
+
+    // The uniform ring holds the data for our uniform buffers. Each frame it
+    // moves on to a fresh region of the buffer, so we can write this frame's
+    // data while the GPU might still be reading the last frame's.
+    //
+    // We wait for each frame to finish before starting the next, so we only
+    // have one frame in flight, but using two regions leaves room to stop
+    // waiting later.
+    let uniform_ring = unsafe {
+        let frame_size = 16 * 1024;
+        let frame_count = 2;
+
+        UniformRing::<backend::Backend>::new(
+            &device,
+            &adapter.physical_device,
+            frame_size,
+            frame_count,
+        )
+    };
+
+    // The point and spot lights go in a storage buffer, so there can be as
+    // many as we like, up to the size of the buffer. Like the uniform ring,
+    // it's rewritten every frame.
+    const MAX_LIGHTS: usize = 64;
+
+    let light_buffer = unsafe {
+        LightBuffer::<backend::Backend>::new(&device, &adapter.physical_device, MAX_LIGHTS)
+    };
+
+    // The uniform set points every binding at the start of the ring's buffer.
+    // The dynamic offsets we pass when binding it say where the data really
+    // is. The size of each descriptor is the size of a single uniform block.
+    let uniform_set = unsafe {
+        use gfx_hal::buffer::SubRange;
+        use gfx_hal::pso::{Descriptor, DescriptorPool, DescriptorSetWrite};
+
+        let uniform_set = descriptor_pool
+            .allocate_set(&uniform_set_layout)
+            .expect("Failed to allocate descriptor set");
+
+        let uniform_range = |size: usize| SubRange {
+            offset: 0,
+            size: Some(size as u64),
+        };
+
+        device.write_descriptor_sets(vec![DescriptorSetWrite {
+            set: &uniform_set,
+            binding: 0,
+            array_offset: 0,
+            descriptors: vec![
+                Descriptor::Buffer(
+                    uniform_ring.buffer(),
+                    uniform_range(std::mem::size_of::<CameraUniforms>()),
+                ),
+                Descriptor::Buffer(
+                    uniform_ring.buffer(),
+                    uniform_range(std::mem::size_of::<MaterialUniforms>()),
+                ),
+                Descriptor::Buffer(
+                    uniform_ring.buffer(),
+                    uniform_range(std::mem::size_of::<LightingUniforms>()),
+                ),
+            ],
+        }]);
+
+        // The light buffer isn't a uniform buffer, so it needs its own write:
+        // one write can only fill consecutive bindings of the same type.
+        device.write_descriptor_sets(vec![DescriptorSetWrite {
+            set: &uniform_set,
+            binding: 3,
+            array_offset: 0,
+            descriptors: vec![Descriptor::Buffer(light_buffer.buffer(), SubRange::WHOLE)],
+        }]);
+
+        // That leaves the shadow's uniform buffer on its own, after it.
+        device.write_descriptor_sets(vec![DescriptorSetWrite {
+            set: &uniform_set,
+            binding: 4,
+            array_offset: 0,
+            descriptors: vec![Descriptor::Buffer(
+                uniform_ring.buffer(),
+                uniform_range(std::mem::size_of::<ShadowUniforms>()),
+            )],
+        }]);
+
+        uniform_set
+    };
+
+    // The depth buffer lets us draw the teapot without worrying about which
+    // triangles are in front, and keeps the sky behind it. It has to match
+    // the size of the swapchain images, so it's recreated along with them.
+    let depth_format = texture::find_depth_format::<backend::Backend>(&adapter.physical_device);
+
+    let depth_texture = unsafe {
+        texture::make_depth_texture::<backend::Backend>(
+            &device,
+            &adapter.physical_device,
+            depth_format,
+            surface_extent.width,
+            surface_extent.height,
+        )
+    };
+
+    // The scene is drawn into HDR render targets, which the post-processing
+    // passes read from. See the `post` module for what each one holds.
+    let render_targets = unsafe {
+        post::make_render_targets::<backend::Backend>(
+            &device,
+            &adapter.physical_device,
+            surface_extent,
+        )
+    };
+
+    // The post-processing passes don't share any descriptors with the
+    // scene, so their layouts come from their own shaders.
+    let post_layout_desc = |stages: &[reflect::StageInterface]| {
+        PipelineLayoutDesc::from_stages(stages)
+            .unwrap_or_else(|message| panic!("Failed to build post-processing layout: {}", message))
+    };
+    let toon_layout_desc = post_layout_desc(&toon_stages);
+    let tone_map_layout_desc = post_layout_desc(&tone_map_stages);
+
+    // The bloom passes all read a single target, so they share a layout.
+    let bloom_layout_desc = post_layout_desc(&bloom_stages);
+
+    let (toon_set_layout, tone_map_set_layout, bloom_set_layout) = unsafe {
+        let make_set_layout = |desc: &PipelineLayoutDesc| {
+            device
+                .create_descriptor_set_layout(&desc.sets[0], &[])
+                .expect("Out of memory")
+        };
+
+        (
+            make_set_layout(&toon_layout_desc),
+            make_set_layout(&tone_map_layout_desc),
+            make_set_layout(&bloom_layout_desc),
+        )
+    };
+
+    let mut post_descriptor_pool = unsafe {
+        use gfx_hal::pso::{
+            DescriptorPoolCreateFlags, DescriptorRangeDesc, DescriptorType, ImageDescriptorType,
+        };
+
+        let input_count: usize = POST_SET_INPUTS.iter().map(|inputs| inputs.len()).sum();
+
+        device
+            .create_descriptor_pool(
+                POST_SET_INPUTS.len(),
+                &[
+                    DescriptorRangeDesc {
+                        ty: DescriptorType::Image {
+                            ty: ImageDescriptorType::Sampled {
+                                with_sampler: false,
+                            },
+                        },
+                        count: input_count,
+                    },
+                    DescriptorRangeDesc {
+                        ty: DescriptorType::Sampler,
+                        count: POST_SET_INPUTS.len(),
+                    },
+                ],
+                DescriptorPoolCreateFlags::empty(),
+            )
+            .expect("Out of memory")
+    };
+
+    let post_sets = unsafe {
+        use gfx_hal::pso::DescriptorPool;
+
+        let layouts = [&toon_set_layout, &tone_map_set_layout, &tone_map_set_layout];
+        let bloom_layouts = (0..=BLOOM_TARGETS.len()).map(|_| &bloom_set_layout);
+
+        let sets: Vec<_> = layouts
+            .iter()
+            .copied()
+            .chain(bloom_layouts)
+            .map(|layout| {
+                post_descriptor_pool
+                    .allocate_set(layout)
+                    .expect("Failed to allocate descriptor set")
+            })
+            .collect();
+
+        post::write_post_sets::<backend::Backend>(&device, &sets, &render_targets, &post_sampler);
+
+        sets
+    };
+
+    let render_pass = unsafe { scene::make_render_pass::<backend::Backend>(&device, depth_format) };
+
+    // The toon and bloom passes draw into HDR targets, ready for the passes
+    // after them, and share a render pass, except when going back up the
+    // bloom chain, where each level is blended onto the one above. The tone
+    // mapping pass writes to the swapchain image.
+    let (post_render_pass, bloom_blend_render_pass, tone_map_render_pass) = unsafe {
+        use gfx_hal::image::Layout;
+
+        (
+            post::make_render_pass::<backend::Backend>(
+                &device,
+                HDR_FORMAT,
+                Layout::ShaderReadOnlyOptimal,
+                false,
+            ),
+            post::make_render_pass::<backend::Backend>(
+                &device,
+                HDR_FORMAT,
+                Layout::ShaderReadOnlyOptimal,
+                true,
+            ),
+            post::make_render_pass::<backend::Backend>(
+                &device,
+                surface_color_format,
+                Layout::Present,
+                false,
+            ),
+        )
+    };
+
+    // The shadow pass renders the shadow map, which is always the same image,
+    // so its framebuffer only has to be made once.
+    let (shadow_render_pass, shadow_framebuffer) = unsafe {
+        let render_pass = shadow::make_render_pass::<backend::Backend>(&device, shadow_map_format);
+        let framebuffer =
+            shadow::make_framebuffer::<backend::Backend>(&device, &render_pass, &shadow_map.view);
+
+        (render_pass, framebuffer)
+    };
+
+    // Each pipeline layout uses the shared descriptor set layouts, but gets
+    // the push constant ranges that its own shaders use. The sky's shaders
+    // don't use any, and the shadow's only use them in the vertex shader.
+    let pipeline_layout_desc = |stages: &[reflect::StageInterface]| {
+        let desc = PipelineLayoutDesc::from_stages(stages)
+            .unwrap_or_else(|message| panic!("Failed to build pipeline layout: {}", message));
+
+        PipelineLayoutDesc {
+            sets: set_layout_desc.sets.clone(),
+            push_constants: desc.push_constants,
+        }
+    };
+    let teapot_layout_desc = pipeline_layout_desc(&teapot_stages);
+    let sky_layout_desc = pipeline_layout_desc(&sky_stages);
+    let shadow_layout_desc = pipeline_layout_desc(&shadow_stages);
+
+    // We pair the push constant range each pipeline layout is created with
+    // with the layout of the Rust struct that fills it, so that we can check
+    // both against the shader's push constant block when we create the
+    // pipeline. If we change one without the others, we'll find out then,
+    // instead of getting mysteriously broken rendering.
+    let make_push_constant_layout = |desc: &PipelineLayoutDesc, block| {
+        let (stages, range) = desc.push_constants[0].clone();
+
+        PushConstantLayout::new(stages, range, block)
+    };
+
+    let push_constant_layout =
+        make_push_constant_layout(&teapot_layout_desc, PushConstants::block_layout());
+
+    // The shadow pipeline fills its push constants from the same struct,
+    // but over a range that only the vertex shader can see.
+    let shadow_push_constant_layout =
+        make_push_constant_layout(&shadow_layout_desc, PushConstants::block_layout());
+
+    // The stages to pass when setting push constants, which have to match
+    // the range in the pipeline layout.
+    let push_constant_stages = push_constant_layout.stages;
+    let shadow_push_constant_stages = shadow_push_constant_layout.stages;
+
+    let tone_map_push_constant_layout =
+        make_push_constant_layout(&tone_map_layout_desc, ToneMapConstants::block_layout());
+    let tone_map_push_constant_stages = tone_map_push_constant_layout.stages;
+
+    // Only the first bloom pass has push constants, but they're in the
+    // layout that all the bloom passes share.
+    let bloom_push_constant_layout =
+        make_push_constant_layout(&bloom_layout_desc, BloomConstants::block_layout());
+    let bloom_push_constant_stages = bloom_push_constant_layout.stages;
+
+    let pipeline_layout = unsafe {
+        device
+            .create_pipeline_layout(
+                vec![&descriptor_set_layout, &uniform_set_layout],
+                &teapot_layout_desc.push_constants,
+            )
+            .expect("Out of memory")
+    };
+
+    let sky_pipeline_layout = unsafe {
+        device
+            .create_pipeline_layout(
+                vec![&descriptor_set_layout, &uniform_set_layout],
+                &sky_layout_desc.push_constants,
+            )
+            .expect("Out of memory")
+    };
+
+    let shadow_pipeline_layout = unsafe {
+        device
+            .create_pipeline_layout(
+                vec![&descriptor_set_layout, &uniform_set_layout],
+                &shadow_layout_desc.push_constants,
+            )
+            .expect("Out of memory")
+    };
+
+    let tone_map_pipeline_layout = unsafe {
+        device
+            .create_pipeline_layout(
+                vec![&tone_map_set_layout],
+                &tone_map_layout_desc.push_constants,
+            )
+            .expect("Out of memory")
+    };
+
+    let toon_pipeline_layout = unsafe {
+        device
+            .create_pipeline_layout(vec![&toon_set_layout], &toon_layout_desc.push_constants)
+            .expect("Out of memory")
+    };
+
+    let bloom_pipeline_layout = unsafe {
+        device
+            .create_pipeline_layout(vec![&bloom_set_layout], &bloom_layout_desc.push_constants)
+            .expect("Out of memory")
+    };
+
+    // While working on the shaders or pipeline configs, set `TEAPOT_WATCH`
+    // to reload them whenever they change, instead of having to restart.
+    // This reads them from the source directory, so it's only useful during
+    // development.
+    let watching = std::env::var_os("TEAPOT_WATCH").is_some();
+    let mut shader_watcher = if watching {
+        Some(ShaderWatcher::new(
+            concat!(env!("CARGO_MANIFEST_DIR"), "/src/bin/shaders"),
+            PipelineConfig::shader_settings(&pipeline_configs)
+                .unwrap_or_else(|message| panic!("Failed to load pipeline configs: {}", message)),
+        ))
+    } else {
+        None
+    };
+    let mut config_watcher = if watching {
+        Some(FileWatcher::new(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/assets/pipelines.ron"
+        )))
+    } else {
+        None
+    };
+    let mut material_watcher = if watching {
+        Some(FileWatcher::new(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/assets/materials.ron"
+        )))
+    } else {
+        None
+    };
+
+    let submission_complete_fence = device.create_fence(true).expect("Out of memory");
+    let rendering_complete_semaphore = device.create_semaphore().expect("Out of memory");
+
+    struct Resources<B: gfx_hal::Backend> {
+        instance: B::Instance,
+        surface: B::Surface,
+        device: B::Device,
+        render_passes: RenderPasses<B>,
+        shadow_framebuffer: B::Framebuffer,
+        pipeline_layouts: PipelineLayouts<B>,
+        pipelines: Vec<B::GraphicsPipeline>,
+        pipeline_cache: B::PipelineCache,
+        pipeline_cache_file: PipelineCacheFile,
+        command_pool: B::CommandPool,
+        submission_complete_fence: B::Fence,
+        rendering_complete_semaphore: B::Semaphore,
+        vertex_buffer_memory: B::Memory,
+        vertex_buffer: B::Buffer,
+        face_normal_buffer_memory: B::Memory,
+        face_normal_buffer: B::Buffer,
+        ground_buffer_memory: B::Memory,
+        ground_buffer: B::Buffer,
+        descriptor_set_layouts: Vec<B::DescriptorSetLayout>,
+        descriptor_pools: Vec<B::DescriptorPool>,
+        descriptor_sets: Vec<B::DescriptorSet>,
+        samplers: Vec<B::Sampler>,
+        textures: Vec<Texture<B>>,
+        depth_texture: Texture<B>,
+        render_targets: Vec<Texture<B>>,
+        uniform_ring: UniformRing<B>,
+        light_buffer: LightBuffer<B>,
+    }
+
+    /// The render passes that make up a frame.
+    struct RenderPasses<B: gfx_hal::Backend> {
+        /// Draws the teapot, the ground, and the sky into the HDR target.
+        scene: B::RenderPass,
+
+        /// Draws the teapot's depth into the shadow map.
+        shadow: B::RenderPass,
+
+        /// Tone maps the HDR target into the swapchain image.
+        tone_map: B::RenderPass,
+
+        /// Replaces its target with the output of a post-processing pass,
+        /// like the toon pass or a bloom downsample.
+        post: B::RenderPass,
+
+        /// Adds a bloom upsample onto the level above.
+        bloom_blend: B::RenderPass,
+    }
+
+    impl<B: gfx_hal::Backend> RenderPasses<B> {
+        unsafe fn destroy(self, device: &B::Device) {
+            device.destroy_render_pass(self.scene);
+            device.destroy_render_pass(self.shadow);
+            device.destroy_render_pass(self.tone_map);
+            device.destroy_render_pass(self.post);
+            device.destroy_render_pass(self.bloom_blend);
+        }
+    }
+
+    /// The pipeline layouts, one for each group of pipelines that share
+    /// descriptor sets and push constants.
+    struct PipelineLayouts<B: gfx_hal::Backend> {
+        /// Used by the teapot, the ground, and the face normals.
+        teapot: B::PipelineLayout,
+        sky: B::PipelineLayout,
+        shadow: B::PipelineLayout,
+        tone_map: B::PipelineLayout,
+        toon: B::PipelineLayout,
+
+        /// Used by every bloom pass.
+        bloom: B::PipelineLayout,
+    }
+
+    impl<B: gfx_hal::Backend> PipelineLayouts<B> {
+        unsafe fn destroy(self, device: &B::Device) {
+            device.destroy_pipeline_layout(self.teapot);
+            device.destroy_pipeline_layout(self.sky);
+            device.destroy_pipeline_layout(self.shadow);
+            device.destroy_pipeline_layout(self.tone_map);
+            device.destroy_pipeline_layout(self.toon);
+            device.destroy_pipeline_layout(self.bloom);
+        }
+    }
+
+    struct ResourceHolder<B: gfx_hal::Backend>(ManuallyDrop<Resources<B>>);
+
+    impl<B: gfx_hal::Backend> Drop for ResourceHolder<B> {
+        fn drop(&mut self) {
+            unsafe {
+                let Resources {
+                    instance,
+                    mut surface,
+                    device,
+                    command_pool,
+                    render_passes,
+                    shadow_framebuffer,
+                    pipeline_layouts,
+                    pipelines,
+                    pipeline_cache,
+                    pipeline_cache_file,
+                    submission_complete_fence,
+                    rendering_complete_semaphore,
+                    vertex_buffer_memory,
+                    vertex_buffer,
+                    face_normal_buffer_memory,
+                    face_normal_buffer,
+                    ground_buffer_memory,
+                    ground_buffer,
+                    descriptor_set_layouts,
+                    descriptor_pools,
+                    descriptor_sets: _,
+                    samplers,
+                    textures,
+                    depth_texture,
+                    render_targets,
+                    uniform_ring,
+                    light_buffer,
+                } = ManuallyDrop::take(&mut self.0);
+
+                // Destroying a descriptor pool also frees every descriptor set
+                // allocated from it, so we don't destroy the sets separately.
+                for descriptor_pool in descriptor_pools {
+                    device.destroy_descriptor_pool(descriptor_pool);
+                }
+                for descriptor_set_layout in descriptor_set_layouts {
+                    device.destroy_descriptor_set_layout(descriptor_set_layout);
+                }
+                for sampler in samplers {
+                    device.destroy_sampler(sampler);
+                }
+                device.destroy_framebuffer(shadow_framebuffer);
+                for texture in textures {
+                    texture.destroy(&device);
+                }
+                depth_texture.destroy(&device);
+                for render_target in render_targets {
+                    render_target.destroy(&device);
+                }
+                uniform_ring.destroy(&device);
+                light_buffer.destroy(&device);
+                device.free_memory(vertex_buffer_memory);
+                device.destroy_buffer(vertex_buffer);
+                device.free_memory(face_normal_buffer_memory);
+                device.destroy_buffer(face_normal_buffer);
+                device.free_memory(ground_buffer_memory);
+                device.destroy_buffer(ground_buffer);
+                device.destroy_semaphore(rendering_complete_semaphore);
+                device.destroy_fence(submission_complete_fence);
+                for pipeline in pipelines {
+                    device.destroy_graphics_pipeline(pipeline);
+                }
+                pipeline_cache_file.save::<B>(&device, &pipeline_cache);
+                device.destroy_pipeline_cache(pipeline_cache);
+                pipeline_layouts.destroy(&device);
+                render_passes.destroy(&device);
+                device.destroy_command_pool(command_pool);
+                surface.unconfigure_swapchain(&device);
+                instance.destroy_surface(surface);
+            }
+        }
+    }
+
+    let mut resource_holder: ResourceHolder<backend::Backend> =
+        ResourceHolder(ManuallyDrop::new(Resources {
+            instance,
+            surface,
+            device,
+            command_pool,
+            render_passes: RenderPasses {
+                scene: render_pass,
+                shadow: shadow_render_pass,
+                tone_map: tone_map_render_pass,
+                post: post_render_pass,
+                bloom_blend: bloom_blend_render_pass,
+            },
+            shadow_framebuffer,
+            pipeline_layouts: PipelineLayouts {
+                teapot: pipeline_layout,
+                sky: sky_pipeline_layout,
+                shadow: shadow_pipeline_layout,
+                tone_map: tone_map_pipeline_layout,
+                toon: toon_pipeline_layout,
+                bloom: bloom_pipeline_layout,
+            },
+            pipelines: vec![],
+            pipeline_cache,
+            pipeline_cache_file,
+            submission_complete_fence,
+            rendering_complete_semaphore,
+            vertex_buffer_memory,
+            vertex_buffer,
+            face_normal_buffer_memory,
+            face_normal_buffer,
+            ground_buffer_memory,
+            ground_buffer,
+            descriptor_set_layouts: vec![
+                descriptor_set_layout,
+                uniform_set_layout,
+                tone_map_set_layout,
+                toon_set_layout,
+                bloom_set_layout,
+            ],
+            descriptor_pools: vec![descriptor_pool, post_descriptor_pool],
+            descriptor_sets: vec![descriptor_set, uniform_set]
+                .into_iter()
+                .chain(post_sets)
+                .collect(),
+            samplers: vec![sampler, shadow_sampler, post_sampler],
+            textures: vec![
+                teapot_texture,
+                sky_texture,
+                metallic_roughness_texture,
+                shadow_map,
+            ],
+            depth_texture,
+            render_targets,
+            uniform_ring,
+            light_buffer,
+        }));
+
+    // Make the pipeline at `index` in the `pipeline_list`, using the given
+    // config and shaders. This is used both to make the pipelines in the
+    // first place, and to remake them when they change.
+    let make_listed_pipeline = move |res: &Resources<backend::Backend>,
+                                     index: usize,
+                                     config: &PipelineConfig,
+                                     vertex_spirv: &[u32],
+                                     fragment_spirv: &[u32]| unsafe {
+        let (_, kind, fragment_constants) = &pipeline_list[index];
+        let passes = &res.render_passes;
+        let layouts = &res.pipeline_layouts;
+
+        let (render_pass, pipeline_layout, layout_desc, push_constants) = match kind {
+            PipelineKind::Teapot | PipelineKind::FaceNormals => (
+                &passes.scene,
+                &layouts.teapot,
+                &teapot_layout_desc,
+                Some(&push_constant_layout),
+            ),
+            PipelineKind::Sky => (&passes.scene, &layouts.sky, &sky_layout_desc, None),
+            PipelineKind::Shadow => (
+                &passes.shadow,
+                &layouts.shadow,
+                &shadow_layout_desc,
+                Some(&shadow_push_constant_layout),
+            ),
+            PipelineKind::ToneMap => (
+                &passes.tone_map,
+                &layouts.tone_map,
+                &tone_map_layout_desc,
+                Some(&tone_map_push_constant_layout),
+            ),
+            PipelineKind::Toon => (&passes.post, &layouts.toon, &toon_layout_desc, None),
+            PipelineKind::BloomBright => (
+                &passes.post,
+                &layouts.bloom,
+                &bloom_layout_desc,
+                Some(&bloom_push_constant_layout),
+            ),
+            PipelineKind::Bloom => (&passes.post, &layouts.bloom, &bloom_layout_desc, None),
+        };
+
+        let context = PipelineContext::<backend::Backend> {
+            device: &res.device,
+            render_pass,
+            pipeline_cache: &res.pipeline_cache,
+            pipeline_layout,
+            layout_desc,
+            push_constants,
+            features,
+            config,
+            vertex_spirv,
+            fragment_spirv,
+            fragment_constants,
+        };
+
+        match kind {
+            PipelineKind::Teapot => scene::make_teapot_pipeline(&context),
+            PipelineKind::FaceNormals => scene::make_face_normals_pipeline(&context),
+            PipelineKind::Sky => sky::make_sky_pipeline(&context),
+            PipelineKind::Shadow => shadow::make_shadow_pipeline(&context),
+            PipelineKind::ToneMap
+            | PipelineKind::Toon
+            | PipelineKind::BloomBright
+            | PipelineKind::Bloom => post::make_post_pipeline(&context),
+        }
+    };
+
+    for (index, config) in pipeline_configs.iter().enumerate() {
+        let res: &mut Resources<_> = &mut resource_holder.0;
+
+        let pipeline = pipeline_spirv(&shader_spirv, config)
+            .and_then(|(vertex_spirv, fragment_spirv)| {
+                make_listed_pipeline(res, index, config, vertex_spirv, fragment_spirv)
+            })
+            .unwrap_or_else(|message| {
+                panic!(
+                    "Failed to create {} pipeline: {}",
+                    config_names[index], message
+                )
+            });
+
+        res.pipelines.push(pipeline);
+    }
+
+    // The world has `y` pointing down, so a negative `y` puts the camera a
+    // little above the teapot, looking down at it.
+    let camera = Camera {
+        position: [0.0, -0.5, -1.2],
+        target: [0.0, 0.0, 0.0],
+        fov_y: std::f32::consts::FRAC_PI_3,
+        near: 0.1,
+        far: 10.0,
+    };
+
+    // A white light shining down from above, to the left of the camera,
+    // along with the light from the sky. Remember that `y` points down, so
+    // the light travels in the `+y` direction.
+    let lighting = LightingUniforms {
+        direction: [0.5, 1.0, 0.6, 0.0],
+        color: [3.0, 3.0, 3.0, 1.0],
+        irradiance: sky_irradiance,
+    };
+
+    // The light doesn't move, so neither does its view of the scene. It
+    // covers everything within a unit of the teapot, which is enough to
+    // include the teapot's shadow on the ground.
+    let shadow = {
+        let [x, y, z, _] = lighting.direction;
+
+        ShadowUniforms {
+            view_projection: camera::directional_light_view_projection(
+                [x, y, z],
+                [0.0, 0.0, 0.0],
+                1.0,
+            ),
+        }
+    };
+
+    // The materials to preview, which can be cycled through with `M`, and
+    // adjusted with the bracket keys (roughness) and `-`/`=` (metallic).
+    let load_materials = |source: &str| {
+        material::materials_from_ron(source)
+            .map_err(|error| error.to_string())
+            .and_then(|materials| {
+                if materials.is_empty() {
+                    Err("There must be at least one material".to_owned())
+                } else {
+                    Ok(materials)
+                }
+            })
+    };
+    let mut materials = load_materials(&load_asset(
+        "materials.ron",
+        include_str!("../../../assets/materials.ron"),
+    ))
+    .unwrap_or_else(|message| panic!("Failed to load materials: {}", message));
+    let mut material_index = 0;
+
+    fn print_material(material: &MaterialConfig) {
+        println!(
+            "Material: {} (metallic {:.2}, roughness {:.2})",
+            material.name, material.metallic, material.roughness
+        );
+    }
+
+    let start_time = std::time::Instant::now();
+
+    // The number keys switch between ways of drawing the teapot, which are
+    // handy for seeing what the shaders are doing.
+    let mut view_mode = ViewMode::Pbr;
+    let mut show_face_normals = false;
+
+    // `T` cycles through the tone mapping curves, and the comma and period
+    // keys change the exposure, in stops: each one doubles or halves the
+    // light.
+    let mut tone_mapping = ToneMapping::Aces;
+    let mut exposure_stops: f32 = 0.0;
+
+    // `C` toggles the toon pass, which bands the lighting and outlines the
+    // edges before tone mapping.
+    let mut toon_enabled = false;
+
+    // `O` and `P` lower and raise how bright something has to be before it
+    // blooms, and `K` and `L` turn the bloom down and up.
+    let mut bloom_threshold: f32 = 1.0;
+    let mut bloom_intensity: f32 = 0.2;
+
+    println!(
+        "Keys: 1 = Blinn-Phong, 2 = PBR, 3 = normals, 4 = depth, 5 = wireframe, \
+         N = face normals, M = next material, [ and ] = roughness, - and = = metallic, \
+         T = tone mapping, , and . = exposure, C = toon, O and P = bloom threshold, \
+         K and L = bloom intensity"
+    );
+    print_material(&materials[material_index]);
+
+    let mut should_configure_swapchain = true;
+
+    event_loop.run(move |event, _, control_flow| {
+        use winit::event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent};
+        use winit::event_loop::ControlFlow;
+
+        match event {
+            Event::WindowEvent { event, .. } => match event {
+                WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
+                WindowEvent::Resized(dims) => {
+                    surface_extent = Extent2D {
+                        width: dims.width,
+                        height: dims.height,
+                    };
+                    should_configure_swapchain = true;
+                }
+                WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                    surface_extent = Extent2D {
+                        width: new_inner_size.width,
+                        height: new_inner_size.height,
+                    };
+                    should_configure_swapchain = true;
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            state: ElementState::Pressed,
+                            virtual_keycode: Some(key),
+                            ..
+                        },
+                    ..
+                } => {
+                    let old_mode = view_mode;
+                    let old_tone_mapping = (tone_mapping, exposure_stops);
+                    let old_bloom = (bloom_threshold, bloom_intensity);
+                    let material = &mut materials[material_index];
+                    let old_material = material.clone();
+                    let adjust = |value: &mut f32, change: f32| {
+                        *value = (*value + change).clamp(0.0, 1.0);
+                    };
+
+                    match key {
+                        VirtualKeyCode::Key1 => view_mode = ViewMode::BlinnPhong,
+                        VirtualKeyCode::Key2 => view_mode = ViewMode::Pbr,
+                        VirtualKeyCode::Key3 => view_mode = ViewMode::Normals,
+                        VirtualKeyCode::Key4 => view_mode = ViewMode::Depth,
+                        VirtualKeyCode::Key5 => view_mode = ViewMode::Wireframe,
+                        VirtualKeyCode::N => show_face_normals = !show_face_normals,
+                        VirtualKeyCode::LBracket => adjust(&mut material.roughness, -0.05),
+                        VirtualKeyCode::RBracket => adjust(&mut material.roughness, 0.05),
+                        VirtualKeyCode::Minus => adjust(&mut material.metallic, -0.05),
+                        VirtualKeyCode::Equals => adjust(&mut material.metallic, 0.05),
+                        VirtualKeyCode::M => {
+                            material_index = (material_index + 1) % materials.len();
+                        }
+                        VirtualKeyCode::T => tone_mapping = tone_mapping.next(),
+                        VirtualKeyCode::Comma => exposure_stops -= 0.5,
+                        VirtualKeyCode::Period => exposure_stops += 0.5,
+                        VirtualKeyCode::C => {
+                            toon_enabled = !toon_enabled;
+                            println!("Toon: {}", if toon_enabled { "on" } else { "off" });
+                        }
+                        VirtualKeyCode::O => bloom_threshold = (bloom_threshold - 0.25).max(0.0),
+                        VirtualKeyCode::P => bloom_threshold += 0.25,
+                        VirtualKeyCode::K => bloom_intensity = (bloom_intensity - 0.05).max(0.0),
+                        VirtualKeyCode::L => bloom_intensity += 0.05,
+                        _ => (),
+                    }
+
+                    if view_mode != old_mode {
+                        println!("View mode: {:?}", view_mode);
+                    }
+
+                    if (tone_mapping, exposure_stops) != old_tone_mapping {
+                        println!(
+                            "Tone mapping: {:?}, exposure {:+.1} stops",
+                            tone_mapping, exposure_stops
+                        );
+                    }
+
+                    if (bloom_threshold, bloom_intensity) != old_bloom {
+                        println!(
+                            "Bloom: threshold {:.2}, intensity {:.2}",
+                            bloom_threshold, bloom_intensity
+                        );
+                    }
+
+                    let material = &materials[material_index];
+                    if *material != old_material {
+                        print_material(material);
+                    }
+                }
+                _ => (),
+            },
+            Event::MainEventsCleared => window.request_redraw(),
+            Event::RedrawRequested(_) => {
+                let res: &mut Resources<_> = &mut resource_holder.0;
+
+                unsafe {
+                    use gfx_hal::pool::CommandPool;
+
+                    // We refuse to wait more than a second, to avoid hanging.
+                    let render_timeout_ns = 1_000_000_000;
+
+                    res.device
+                        .wait_for_fence(&res.submission_complete_fence, render_timeout_ns)
+                        .expect("Out of memory or device lost");
+
+                    res.device
+                        .reset_fence(&res.submission_complete_fence)
+                        .expect("Out of memory");
+
+                    res.command_pool.reset(false);
+                }
+
+                // Remake any pipelines whose shaders or configs have changed.
+                // We've just waited for the last frame to finish, so nothing
+                // is using the old pipelines any more. If the new ones don't
+                // work, we keep drawing with the old ones.
+                let mut remake = vec![false; pipeline_configs.len()];
+
+                if let Some(source) = material_watcher.as_mut().and_then(FileWatcher::poll) {
+                    match load_materials(&source) {
+                        Ok(new_materials) => {
+                            materials = new_materials;
+                            material_index = material_index.min(materials.len() - 1);
+                            print_material(&materials[material_index]);
+                        }
+                        Err(message) => eprintln!("Keeping the old materials: {}", message),
+                    }
+                }
+
+                if let Some(source) = config_watcher.as_mut().and_then(FileWatcher::poll) {
+                    let new_configs = pipeline_config::configs_from_ron(&source)
+                        .map_err(|error| error.to_string())
+                        .and_then(|configs| select_configs(&configs, &config_names))
+                        .and_then(|configs| {
+                            let settings = PipelineConfig::shader_settings(&configs)?;
+                            Ok((configs, settings))
+                        });
+
+                    match new_configs {
+                        Ok((new_configs, settings)) => {
+                            for (index, config) in new_configs.into_iter().enumerate() {
+                                if config != pipeline_configs[index] {
+                                    pipeline_configs[index] = config;
+                                    remake[index] = true;
+                                }
+                            }
+
+                            // Any new variants, or shaders with new entry
+                            // points, are compiled by the shader watcher
+                            // below, before the pipelines are made.
+                            if let Some(watcher) = &mut shader_watcher {
+                                watcher.set_settings(settings);
+                            }
+                        }
+                        Err(message) => eprintln!("Keeping the old pipeline configs: {}", message),
+                    }
+                }
+
+                if let Some(watcher) = &mut shader_watcher {
+                    for (name, spirv) in watcher.poll() {
+                        for (index, config) in pipeline_configs.iter().enumerate() {
+                            let (vertex_name, fragment_name) = config.shader_names();
+                            remake[index] |= vertex_name == name || fragment_name == name;
+                        }
+
+                        shader_spirv.insert(name, spirv);
+                    }
+                }
+
+                for index in (0..pipeline_configs.len()).filter(|&index| remake[index]) {
+                    let config = &pipeline_configs[index];
+
+                    let pipeline = pipeline_spirv(&shader_spirv, config).and_then(
+                        |(vertex_spirv, fragment_spirv)| {
+                            make_listed_pipeline(res, index, config, vertex_spirv, fragment_spirv)
+                        },
+                    );
+
+                    match pipeline {
+                        Ok(pipeline) => unsafe {
+                            let old_pipeline =
+                                std::mem::replace(&mut res.pipelines[index], pipeline);
+                            res.device.destroy_graphics_pipeline(old_pipeline);
+                        },
+                        Err(message) => eprintln!(
+                            "Keeping the old {} pipeline: {}",
+                            config_names[index], message
+                        ),
+                    }
+                }
+
+                let render_pass = &res.render_passes.scene;
+                let pipeline_layout = &res.pipeline_layouts.teapot;
+                let pipeline = &res.pipelines[view_mode as usize];
+                let descriptor_set = &res.descriptor_sets[0];
+                let uniform_set = &res.descriptor_sets[1];
+                let sky_pipeline_layout = &res.pipeline_layouts.sky;
+                let sky_pipeline = &res.pipelines[SKY_PIPELINE];
+                let face_normals_pipeline = &res.pipelines[FACE_NORMALS_PIPELINE];
+                let shadow_render_pass = &res.render_passes.shadow;
+                let shadow_framebuffer = &res.shadow_framebuffer;
+                let shadow_pipeline_layout = &res.pipeline_layouts.shadow;
+                let shadow_pipeline = &res.pipelines[SHADOW_PIPELINE];
+                let tone_map_render_pass = &res.render_passes.tone_map;
+                let tone_map_pipeline_layout = &res.pipeline_layouts.tone_map;
+                let tone_map_pipeline = &res.pipelines[TONE_MAP_PIPELINE];
+                let post_render_pass = &res.render_passes.post;
+                let toon_pipeline_layout = &res.pipeline_layouts.toon;
+                let toon_pipeline = &res.pipelines[TOON_PIPELINE];
+                let toon_set = &res.descriptor_sets[TOON_SET];
+                let bloom_blend_render_pass = &res.render_passes.bloom_blend;
+                let bloom_pipeline_layout = &res.pipeline_layouts.bloom;
+                let bloom_bright_pipeline = &res.pipelines[BLOOM_BRIGHT_PIPELINE];
+                let bloom_down_pipeline = &res.pipelines[BLOOM_DOWN_PIPELINE];
+                let bloom_up_pipeline = &res.pipelines[BLOOM_UP_PIPELINE];
+                let bloom_bright_set = &res.descriptor_sets[BLOOM_BRIGHT_SET];
+                let bloom_sets = &res.descriptor_sets[FIRST_BLOOM_SET..];
+
+                // When the toon pass runs, the tone mapping pass reads its
+                // output instead of the scene.
+                let tone_map_set = if toon_enabled {
+                    &res.descriptor_sets[TOON_TONE_MAP_SET]
+                } else {
+                    &res.descriptor_sets[TONE_MAP_SET]
+                };
+
+                if should_configure_swapchain {
+                    use gfx_hal::window::SwapchainConfig;
+
+                    let caps = res.surface.capabilities(&adapter.physical_device);
+
+                    let mut swapchain_config =
+                        SwapchainConfig::from_caps(&caps, surface_color_format, surface_extent);
+
+                    // This seems to fix some fullscreen slowdown on macOS.
+                    if caps.image_count.contains(&3) {
+                        swapchain_config.image_count = 3;
+                    }
+
+                    surface_extent = swapchain_config.extent;
+
+                    unsafe {
+                        res.surface
+                            .configure_swapchain(&res.device, swapchain_config)
+                            .expect("Failed to configure swapchain");
+                    };
+
+                    // The depth buffer and render targets have to match the new
+                    // swapchain size. We've already waited for the last frame
+                    // to finish, so it's safe to destroy the old ones.
+                    unsafe {
+                        let depth_texture = texture::make_depth_texture::<backend::Backend>(
+                            &res.device,
+                            &adapter.physical_device,
+                            depth_format,
+                            surface_extent.width,
+                            surface_extent.height,
+                        );
+
+                        std::mem::replace(&mut res.depth_texture, depth_texture)
+                            .destroy(&res.device);
+
+                        let render_targets = post::make_render_targets::<backend::Backend>(
+                            &res.device,
+                            &adapter.physical_device,
+                            surface_extent,
+                        );
+
+                        for render_target in
+                            std::mem::replace(&mut res.render_targets, render_targets)
+                        {
+                            render_target.destroy(&res.device);
+                        }
+
+                        post::write_post_sets::<backend::Backend>(
+                            &res.device,
+                            &res.descriptor_sets[FIRST_POST_SET..],
+                            &res.render_targets,
+                            &res.samplers[2],
+                        );
+                    }
+
+                    should_configure_swapchain = false;
+                }
+
+                let surface_image = unsafe {
+                    // We refuse to wait more than a second, to avoid hanging.
+                    let acquire_timeout_ns = 1_000_000_000;
+
+                    match res.surface.acquire_image(acquire_timeout_ns) {
+                        Ok((image, _)) => image,
+                        Err(_) => {
+                            should_configure_swapchain = true;
+                            return;
+                        }
+                    }
+                };
+
+                // The scene is drawn into the HDR and normal-depth targets,
+                // the toon pass (if it's on) draws into its own target, the
+                // bloom passes draw into theirs, and then the tone mapping
+                // pass draws into the swapchain image.
+                let (scene_framebuffer, toon_framebuffer, bloom_framebuffers, framebuffer) = unsafe {
+                    use std::borrow::Borrow;
+
+                    use gfx_hal::image::Extent;
+
+                    let extent = Extent {
+                        width: surface_extent.width,
+                        height: surface_extent.height,
+                        depth: 1,
+                    };
+
+                    let scene_framebuffer = res
+                        .device
+                        .create_framebuffer(
+                            render_pass,
+                            vec![
+                                &res.render_targets[HDR_TARGET].view,
+                                &res.render_targets[NORMAL_DEPTH_TARGET].view,
+                                &res.depth_texture.view,
+                            ],
+                            extent,
+                        )
+                        .unwrap();
+
+                    let toon_framebuffer = res
+                        .device
+                        .create_framebuffer(
+                            post_render_pass,
+                            vec![&res.render_targets[TOON_TARGET].view],
+                            extent,
+                        )
+                        .unwrap();
+
+                    // Render passes that only differ in how they load their
+                    // attachments are compatible, so going back up the bloom
+                    // chain can use the same framebuffers as going down.
+                    let bloom_framebuffers: Vec<_> = BLOOM_TARGETS
+                        .iter()
+                        .map(|&target| {
+                            let target_extent = post::render_target_extent(target, surface_extent);
+
+                            res.device
+                                .create_framebuffer(
+                                    post_render_pass,
+                                    vec![&res.render_targets[target].view],
+                                    Extent {
+                                        width: target_extent.width,
+                                        height: target_extent.height,
+                                        depth: 1,
+                                    },
+                                )
+                                .unwrap()
+                        })
+                        .collect();
+
+                    let framebuffer = res
+                        .device
+                        .create_framebuffer(
+                            tone_map_render_pass,
+                            vec![surface_image.borrow()],
+                            extent,
+                        )
+                        .unwrap();
+
+                    (scene_framebuffer, toon_framebuffer, bloom_framebuffers, framebuffer)
+                };
+
+                let viewport = {
+                    use gfx_hal::pso::{Rect, Viewport};
+
+                    Viewport {
+                        rect: Rect {
+                            x: 0,
+                            y: 0,
+                            w: surface_extent.width as i16,
+                            h: surface_extent.height as i16,
+                        },
+                        depth: 0.0..1.0,
+                    }
+                };
+
+                /// Create a matrix that positions, scales, and rotates.
+                fn make_transform(translate: [f32; 3], angle: f32, scale: f32) -> [[f32; 4]; 4] {
+                    let c = angle.cos() * scale;
+                    let s = angle.sin() * scale;
+                    let [dx, dy, dz] = translate;
+
+                    [
+                        [c, 0., s, 0.],
+                        [0., scale, 0., 0.],
+                        [-s, 0., c, 0.],
+                        [dx, dy, dz, 1.],
+                    ]
+                }
+
+                let angle = start_time.elapsed().as_secs_f32();
+
+                // Three colored point lights circle the teapot, and a spot
+                // light shines down on it from above.
+                let lights: Vec<Light> = {
+                    let orbit = |offset: f32| {
+                        let orbit_angle = angle * 0.7 + offset;
+                        [orbit_angle.cos() * 0.6, -0.2, orbit_angle.sin() * 0.6]
+                    };
+                    let third = 2.0 * std::f32::consts::FRAC_PI_3;
+
+                    vec![
+                        Light::point(orbit(0.0), [3.0, 0.6, 0.3], 2.0),
+                        Light::point(orbit(third), [0.3, 3.0, 0.6], 2.0),
+                        Light::point(orbit(2.0 * third), [0.6, 0.9, 3.0], 2.0),
+                        Light::spot(
+                            [0.0, -1.0, 0.0],
+                            [0.0, 1.0, 0.0],
+                            [4.5, 4.2, 3.6],
+                            3.0,
+                            0.25,
+                            0.4,
+                        ),
+                    ]
+                };
+
+                // We've waited for the last frame to finish, so the GPU is
+                // done reading the lights it used.
+                let light_count = unsafe { res.light_buffer.write(&res.device, &lights) };
+
+                let make_push_constants = |transform| PushConstants {
+                    transform,
+                    normal_matrix: camera::normal_matrix(transform),
+                    light_count,
+                };
+
+                let teapots = &[(
+                    make_push_constants(make_transform([0., 0., 0.], angle, 1.0)),
+                    MaterialUniforms::from(&materials[material_index]),
+                )];
+
+                // The ground is drawn with the teapot's pipeline, but it
+                // doesn't move, and it's always a rough grey surface.
+                let ground_draw = (
+                    make_push_constants(make_transform([0., 0., 0.], 0.0, 1.0)),
+                    MaterialUniforms {
+                        base_color: [0.5, 0.5, 0.5, 1.0],
+                        metallic: 0.0,
+                        roughness: 0.8,
+                        texture_flags: 0,
+                    },
+                );
+
+                let camera_uniforms = {
+                    let aspect_ratio = surface_extent.width as f32 / surface_extent.height as f32;
+                    let [scale_x, scale_y] = camera.view_scale(aspect_ratio);
+                    let basis = camera.basis();
+                    let [px, py, pz] = camera.position;
+                    let scaled =
+                        |v: [f32; 3], scale: f32| [v[0] * scale, v[1] * scale, v[2] * scale, 0.];
+
+                    CameraUniforms {
+                        view_projection: camera.view_projection(aspect_ratio),
+                        position: [px, py, pz, 1.],
+                        view_right: scaled(basis.right, scale_x),
+                        view_down: scaled(basis.down, scale_y),
+                        view_forward: scaled(basis.forward, 1.0),
+                    }
+                };
+
+                // Write this frame's uniform data into the ring, keeping the
+                // offset of each block so we can bind it later.
+                let (camera_offset, lighting_offset, shadow_offset, material_offsets) = unsafe {
+                    let ring = &mut res.uniform_ring;
+
+                    ring.begin_frame();
+
+                    let camera_offset = ring.push(&camera_uniforms);
+                    let lighting_offset = ring.push(&lighting);
+                    let shadow_offset = ring.push(&shadow);
+                    let material_offsets: Vec<_> = teapots
+                        .iter()
+                        .chain(std::iter::once(&ground_draw))
+                        .map(|(_, material)| ring.push(material))
+                        .collect();
+
+                    ring.flush(&res.device);
+
+                    (
+                        camera_offset,
+                        lighting_offset,
+                        shadow_offset,
+                        material_offsets,
+                    )
+                };
+
+                unsafe {
+                    use gfx_hal::command::{
+                        ClearColor, ClearDepthStencil, ClearValue, CommandBuffer,
+                        CommandBufferFlags, SubpassContents,
+                    };
+                    command_buffer.begin_primary(CommandBufferFlags::ONE_TIME_SUBMIT);
+
+                    // First, the shadow pass draws the depth of each teapot
+                    // from the light's point of view. Only the teapots cast
+                    // shadows: the ground is flat, so it can't shadow itself.
+                    let shadow_viewport = {
+                        use gfx_hal::pso::{Rect, Viewport};
+
+                        Viewport {
+                            rect: Rect {
+                                x: 0,
+                                y: 0,
+                                w: SHADOW_MAP_SIZE as i16,
+                                h: SHADOW_MAP_SIZE as i16,
+                            },
+                            depth: 0.0..1.0,
+                        }
+                    };
+
+                    command_buffer.set_viewports(0, &[shadow_viewport.clone()]);
+                    command_buffer.set_scissors(0, &[shadow_viewport.rect]);
+
+                    command_buffer.begin_render_pass(
+                        shadow_render_pass,
+                        shadow_framebuffer,
+                        shadow_viewport.rect,
+                        &[ClearValue {
+                            depth_stencil: ClearDepthStencil {
+                                depth: 1.0,
+                                stencil: 0,
+                            },
+                        }],
+                        SubpassContents::Inline,
+                    );
+
+                    command_buffer.bind_graphics_pipeline(shadow_pipeline);
+                    command_buffer.bind_vertex_buffers(
+                        0,
+                        vec![(&res.vertex_buffer, gfx_hal::buffer::SubRange::WHOLE)],
+                    );
+
+                    // The shadow shaders only use the shadow's uniform
+                    // buffer, so the other offsets don't matter.
+                    command_buffer.bind_graphics_descriptor_sets(
+                        shadow_pipeline_layout,
+                        1,
+                        vec![uniform_set],
+                        &[0, 0, 0, shadow_offset],
+                    );
+
+                    for (teapot, _) in teapots.iter() {
+                        command_buffer.push_graphics_constants(
+                            shadow_pipeline_layout,
+                            shadow_push_constant_stages,
+                            0,
+                            teapot.as_words(),
+                        );
+
+                        let vertex_count = mesh.len() as u32;
+                        command_buffer.draw(0..vertex_count, 0..1);
+                    }
+
+                    command_buffer.end_render_pass();
+
+                    // Then the main pass draws the scene, using the shadow
+                    // map to work out which parts the light reaches.
+                    command_buffer.set_viewports(0, &[viewport.clone()]);
+                    command_buffer.set_scissors(0, &[viewport.rect]);
+
+                    command_buffer.begin_render_pass(
+                        render_pass,
+                        &scene_framebuffer,
+                        viewport.rect,
+                        &[
+                            ClearValue {
+                                color: ClearColor {
+                                    float32: [0.0, 0.0, 0.0, 1.0],
+                                },
+                            },
+                            ClearValue {
+                                color: ClearColor {
+                                    float32: [0.0, 0.0, 0.0, 0.0],
+                                },
+                            },
+                            ClearValue {
+                                depth_stencil: ClearDepthStencil {
+                                    depth: 1.0,
+                                    stencil: 0,
+                                },
+                            },
+                        ],
+                        SubpassContents::Inline,
+                    );
+
+                    command_buffer.bind_graphics_pipeline(pipeline);
+
+                    // This makes the texture and sampler in our descriptor
+                    // set available to the shaders. The `0` is the index of
+                    // the first set we're binding, which corresponds to the
+                    // `set = 0` in the shader.
+                    command_buffer.bind_graphics_descriptor_sets(
+                        pipeline_layout,
+                        0,
+                        vec![descriptor_set],
+                        &[],
+                    );
+
+                    // Each thing to draw: its vertex buffer, how many
+                    // vertices it has, and its push constants and material.
+                    let draws = teapots
+                        .iter()
+                        .map(|draw| (&res.vertex_buffer, mesh.len(), draw))
+                        .chain(std::iter::once((
+                            &res.ground_buffer,
+                            ground.len(),
+                            &ground_draw,
+                        )));
+
+                    for ((buffer, vertex_count, (push_constants, _)), &material_offset) in
+                        draws.zip(&material_offsets)
+                    {
+                        command_buffer.bind_vertex_buffers(
+                            0,
+                            vec![(buffer, gfx_hal::buffer::SubRange::WHOLE)],
+                        );
+
+                        // The dynamic offsets are given in binding order:
+                        // the camera, the material, the lighting, and the
+                        // shadow.
+                        command_buffer.bind_graphics_descriptor_sets(
+                            pipeline_layout,
+                            1,
+                            vec![uniform_set],
+                            &[
+                                camera_offset,
+                                material_offset,
+                                lighting_offset,
+                                shadow_offset,
+                            ],
+                        );
+
+                        command_buffer.push_graphics_constants(
+                            pipeline_layout,
+                            push_constant_stages,
+                            0,
+                            push_constants.as_words(),
+                        );
+
+                        command_buffer.draw(0..vertex_count as u32, 0..1);
+                    }
+
+                    // The face normals are drawn with the same transforms as
+                    // the teapots. The uniform set is still bound from the
+                    // ground, which is fine: they only use the camera.
+                    if show_face_normals {
+                        command_buffer.bind_graphics_pipeline(face_normals_pipeline);
+                        command_buffer.bind_vertex_buffers(
+                            0,
+                            vec![(&res.face_normal_buffer, gfx_hal::buffer::SubRange::WHOLE)],
+                        );
+
+                        for (teapot, _) in teapots.iter() {
+                            command_buffer.push_graphics_constants(
+                                pipeline_layout,
+                                push_constant_stages,
+                                0,
+                                teapot.as_words(),
+                            );
+
+                            let vertex_count = face_normals.len() as u32;
+                            command_buffer.draw(0..vertex_count, 0..1);
+                        }
+                    }
+
+                    // The sky doesn't use the material, lighting, or shadow,
+                    // but every dynamic buffer in the set still needs an
+                    // offset, so we just point them at the start of the
+                    // buffer.
+                    command_buffer.bind_graphics_pipeline(sky_pipeline);
+                    command_buffer.bind_graphics_descriptor_sets(
+                        sky_pipeline_layout,
+                        0,
+                        vec![descriptor_set, uniform_set],
+                        &[camera_offset, 0, 0, 0],
+                    );
+                    command_buffer.draw(0..3, 0..1);
+
+                    command_buffer.end_render_pass();
+
+                    // If it's on, the toon pass bands the lighting and draws
+                    // outlines where the normal or depth changes sharply.
+                    if toon_enabled {
+                        command_buffer.begin_render_pass(
+                            post_render_pass,
+                            &toon_framebuffer,
+                            viewport.rect,
+                            &[],
+                            SubpassContents::Inline,
+                        );
+
+                        command_buffer.bind_graphics_pipeline(toon_pipeline);
+                        command_buffer.bind_graphics_descriptor_sets(
+                            toon_pipeline_layout,
+                            0,
+                            vec![toon_set],
+                            &[],
+                        );
+                        command_buffer.draw(0..3, 0..1);
+
+                        command_buffer.end_render_pass();
+                    }
+
+                    // Then the bloom passes. The first picks out the bright
+                    // parts of the scene into the largest bloom target. Each
+                    // pass down the chain blurs that into the next smaller
+                    // target, and each pass back up blends it onto the next
+                    // larger one. Each level is read through its own set.
+                    let bloom_constants = BloomConstants {
+                        threshold: bloom_threshold,
+                    };
+                    let bloom_levels = BLOOM_TARGETS.len();
+
+                    let bloom_passes = std::iter::once((
+                        post_render_pass,
+                        bloom_bright_pipeline,
+                        bloom_bright_set,
+                        0,
+                    ))
+                    .chain((1..bloom_levels).map(|level| {
+                        (
+                            post_render_pass,
+                            bloom_down_pipeline,
+                            &bloom_sets[level - 1],
+                            level,
+                        )
+                    }))
+                    .chain((0..bloom_levels - 1).rev().map(|level| {
+                        (
+                            bloom_blend_render_pass,
+                            bloom_up_pipeline,
+                            &bloom_sets[level + 1],
+                            level,
+                        )
+                    }));
+
+                    for (bloom_render_pass, bloom_pipeline, bloom_set, level) in bloom_passes {
+                        use gfx_hal::pso::{Rect, Viewport};
+
+                        let target_extent =
+                            post::render_target_extent(BLOOM_TARGETS[level], surface_extent);
+                        let level_viewport = Viewport {
+                            rect: Rect {
+                                x: 0,
+                                y: 0,
+                                w: target_extent.width as i16,
+                                h: target_extent.height as i16,
+                            },
+                            depth: 0.0..1.0,
+                        };
+
+                        command_buffer.set_viewports(0, &[level_viewport.clone()]);
+                        command_buffer.set_scissors(0, &[level_viewport.rect]);
+
+                        command_buffer.begin_render_pass(
+                            bloom_render_pass,
+                            &bloom_framebuffers[level],
+                            level_viewport.rect,
+                            &[],
+                            SubpassContents::Inline,
+                        );
+
+                        command_buffer.bind_graphics_pipeline(bloom_pipeline);
+                        command_buffer.bind_graphics_descriptor_sets(
+                            bloom_pipeline_layout,
+                            0,
+                            vec![bloom_set],
+                            &[],
+                        );
+                        command_buffer.push_graphics_constants(
+                            bloom_pipeline_layout,
+                            bloom_push_constant_stages,
+                            0,
+                            bloom_constants.as_words(),
+                        );
+                        command_buffer.draw(0..3, 0..1);
+
+                        command_buffer.end_render_pass();
+                    }
+
+                    // Finally, the tone mapping pass adds the bloom to the
+                    // HDR scene, and brings it into the range of the screen.
+                    // It covers the whole screen again.
+                    command_buffer.set_viewports(0, &[viewport.clone()]);
+                    command_buffer.set_scissors(0, &[viewport.rect]);
+
+                    let tone_map_constants = ToneMapConstants {
+                        exposure: exposure_stops.exp2(),
+                        tone_mapping: tone_mapping as u32,
+                        bloom_intensity,
+                    };
+
+                    command_buffer.begin_render_pass(
+                        tone_map_render_pass,
+                        &framebuffer,
+                        viewport.rect,
+                        &[],
+                        SubpassContents::Inline,
+                    );
+
+                    command_buffer.bind_graphics_pipeline(tone_map_pipeline);
+                    command_buffer.bind_graphics_descriptor_sets(
+                        tone_map_pipeline_layout,
+                        0,
+                        vec![tone_map_set],
+                        &[],
+                    );
+                    command_buffer.push_graphics_constants(
+                        tone_map_pipeline_layout,
+                        tone_map_push_constant_stages,
+                        0,
+                        tone_map_constants.as_words(),
+                    );
+                    command_buffer.draw(0..3, 0..1);
+
+                    command_buffer.end_render_pass();
+                    command_buffer.finish();
+                }
+
+                unsafe {
+                    use gfx_hal::queue::{CommandQueue, Submission};
+
+                    let submission = Submission {
+                        command_buffers: vec![&command_buffer],
+                        wait_semaphores: None,
+                        signal_semaphores: vec![&res.rendering_complete_semaphore],
+                    };
+
+                    queue_group.queues[0].submit(submission, Some(&res.submission_complete_fence));
+
+                    let result = queue_group.queues[0].present(
+                        &mut res.surface,
+                        surface_image,
+                        Some(&res.rendering_complete_semaphore),
+                    );
+
+                    should_configure_swapchain |= result.is_err();
+
+                    res.device.destroy_framebuffer(scene_framebuffer);
+                    res.device.destroy_framebuffer(toon_framebuffer);
+                    for bloom_framebuffer in bloom_framebuffers {
+                        res.device.destroy_framebuffer(bloom_framebuffer);
+                    }
+                    res.device.destroy_framebuffer(framebuffer);
+                }
+            }
+            _ => (),
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use gfx_hal::pso::ShaderStageFlags;
+    use gfx_hal_tutorials::{
+        pipeline_config,
+        pipeline_config::PipelineConfig,
+        reflect::{self, BlockLayout, PipelineLayoutDesc, PushConstantLayout, StageInterface},
+        shader_data::ShaderData,
+        shaders,
+    };
+
+    use crate::post::{BloomConstants, ToneMapConstants};
+    use crate::scene::PushConstants;
+
+    /// The pipeline layouts made in `main`: the pipelines whose shaders each
+    /// one is built from, and then every pipeline made with it.
+    const LAYOUTS: &[(&[&str], &[&str])] = &[
+        (&["teapot"], &["teapot", "teapot_wireframe", "face_normals"]),
+        (&["sky"], &["sky"]),
+        (&["shadow"], &["shadow"]),
+        (&["tone_map"], &["tone_map"]),
+        (&["toon"], &["toon"]),
+        (
+            &["bloom_bright", "bloom_down", "bloom_up"],
+            &["bloom_bright", "bloom_down", "bloom_up"],
+        ),
+    ];
+
+    /// The struct that fills the push constants in each layout that has
+    /// them, by the first pipeline the layout is built from.
+    fn push_constant_block(layout_source: &str) -> Option<BlockLayout> {
+        match layout_source {
+            "teapot" | "shadow" => Some(PushConstants::block_layout()),
+            "tone_map" => Some(ToneMapConstants::block_layout()),
+            "bloom_bright" => Some(BloomConstants::block_layout()),
+            _ => None,
+        }
+    }
+
+    fn config_spirv(config: &PipelineConfig) -> Vec<(ShaderStageFlags, &'static [u32])> {
+        let spirv = |name: &str| {
+            let &(_, spirv) = shaders::ALL
+                .iter()
+                .find(|&&(shader_name, _)| shader_name == name)
+                .unwrap_or_else(|| panic!("There's no shader called `{}`", name));
+
+            spirv
+        };
+
+        let (vertex_name, fragment_name) = config.shader_names();
+        vec![
+            (ShaderStageFlags::VERTEX, spirv(&vertex_name)),
+            (ShaderStageFlags::FRAGMENT, spirv(&fragment_name)),
+        ]
+    }
+
+    fn reflect_config(config: &PipelineConfig) -> Vec<StageInterface> {
+        config_spirv(config)
+            .into_iter()
+            .map(|(stage, spirv)| StageInterface::reflect(stage, spirv).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn every_pipeline_fits_its_layout() {
+        let configs =
+            pipeline_config::configs_from_ron(include_str!("../../../assets/pipelines.ron"))
+                .unwrap();
+
+        for name in configs.keys() {
+            assert!(
+                LAYOUTS
+                    .iter()
+                    .any(|(_, pipelines)| pipelines.contains(&name.as_str())),
+                "`{}` isn't made with any layout",
+                name
+            );
+        }
+
+        for &(sources, pipelines) in LAYOUTS {
+            let source_stages: Vec<_> = sources
+                .iter()
+                .flat_map(|&name| reflect_config(&configs[name]))
+                .collect();
+            let layout = PipelineLayoutDesc::from_stages(&source_stages).unwrap();
+
+            for &name in pipelines {
+                let desc =
+                    PipelineLayoutDesc::from_stages(&reflect_config(&configs[name])).unwrap();
+
+                if let Err(message) = desc.fits_within(&layout) {
+                    panic!(
+                        "`{}` doesn't fit the `{}` layout: {}",
+                        name, sources[0], message
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn push_constant_structs_match_their_ranges() {
+        let configs =
+            pipeline_config::configs_from_ron(include_str!("../../../assets/pipelines.ron"))
+                .unwrap();
+
+        for &(sources, pipelines) in LAYOUTS {
+            let source_stages: Vec<_> = sources
+                .iter()
+                .flat_map(|&name| reflect_config(&configs[name]))
+                .collect();
+            let layout = PipelineLayoutDesc::from_stages(&source_stages).unwrap();
+
+            // This is how `main` pairs each struct with its range.
+            let push_constants = push_constant_block(sources[0]).map(|block| {
+                let (stages, range) = layout.push_constants[0].clone();
+                PushConstantLayout::new(stages, range, block)
+            });
+
+            for &name in pipelines {
+                for (stage, spirv) in config_spirv(&configs[name]) {
+                    if let Err(message) =
+                        reflect::check_push_constants(stage, spirv, push_constants.as_ref())
+                    {
+                        panic!("`{}` doesn't match its push constants: {}", name, message);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,166 @@
+//! Making the graphics pipelines that every pass draws with.
+
+use gfx_hal::{
+    device::Device,
+    pso::{AttributeDesc, VertexBufferDesc},
+    Backend, Features,
+};
+use gfx_hal_tutorials::{
+    pipeline_config::PipelineConfig,
+    reflect,
+    reflect::{PipelineLayoutDesc, PushConstantLayout},
+    specialization::SpecConstants,
+};
+
+/// Everything a pipeline is made from, apart from its vertex layout and
+/// color attachments, which depend on which pass it's for.
+pub struct PipelineContext<'a, B: Backend> {
+    pub device: &'a B::Device,
+    pub render_pass: &'a B::RenderPass,
+    pub pipeline_cache: &'a B::PipelineCache,
+    pub pipeline_layout: &'a B::PipelineLayout,
+
+    /// Describes the `pipeline_layout`.
+    pub layout_desc: &'a PipelineLayoutDesc,
+
+    /// Describes the Rust struct that fills the layout's push constant
+    /// range, if it has one.
+    pub push_constants: Option<&'a PushConstantLayout>,
+
+    /// The features the device was opened with, so the config can fall back
+    /// to something supported if it needs to.
+    pub features: Features,
+
+    pub config: &'a PipelineConfig,
+    pub vertex_spirv: &'a [u32],
+    pub fragment_spirv: &'a [u32],
+
+    /// Picks which variant of the fragment shader to use, so one shader can
+    /// make several different pipelines.
+    pub fragment_constants: &'a SpecConstants,
+}
+
+impl<'a, B: Backend> PipelineContext<'a, B> {
+    /// Create a pipeline with the given vertex layout.
+    ///
+    /// The pipeline draws to `color_attachments` color attachments: the
+    /// first is blended as the config says, and any others hold data rather
+    /// than colors, so they're written as they are. If the shaders don't fit
+    /// the layout, the vertex shader has inputs that the attributes don't
+    /// supply, or the specialization constants don't match the shaders, this
+    /// returns an error instead of a pipeline.
+    pub unsafe fn make_pipeline(
+        &self,
+        vertex_buffers: &[VertexBufferDesc],
+        attributes: &[AttributeDesc],
+        color_attachments: usize,
+    ) -> Result<B::GraphicsPipeline, String> {
+        use gfx_hal::pass::Subpass;
+        use gfx_hal::pso::{
+            ColorBlendDesc, ColorMask, EntryPoint, GraphicsPipelineDesc, PrimitiveAssemblerDesc,
+            ShaderStageFlags,
+        };
+        use reflect::StageInterface;
+
+        let PipelineContext {
+            device,
+            config,
+            vertex_spirv,
+            fragment_spirv,
+            ..
+        } = *self;
+
+        // None of the vertex shaders have specialization constants.
+        let vertex_constants = &SpecConstants::new();
+
+        let stages = [
+            StageInterface::reflect(ShaderStageFlags::VERTEX, vertex_spirv)?,
+            StageInterface::reflect(ShaderStageFlags::FRAGMENT, fragment_spirv)?,
+        ];
+        PipelineLayoutDesc::from_stages(&stages)?.fits_within(self.layout_desc)?;
+
+        for &(stage, spirv) in &[
+            (ShaderStageFlags::VERTEX, vertex_spirv),
+            (ShaderStageFlags::FRAGMENT, fragment_spirv),
+        ] {
+            reflect::check_push_constants(stage, spirv, self.push_constants)
+                .map_err(|message| format!("Push constant layout mismatch: {}", message))?;
+        }
+
+        for &(stage, spirv, constants) in &[
+            (ShaderStageFlags::VERTEX, vertex_spirv, vertex_constants),
+            (
+                ShaderStageFlags::FRAGMENT,
+                fragment_spirv,
+                self.fragment_constants,
+            ),
+        ] {
+            reflect::check_specialization(stage, spirv, constants)?;
+        }
+
+        reflect::check_vertex_inputs(vertex_spirv, attributes)
+            .map_err(|message| format!("Vertex layout mismatch: {}", message))?;
+
+        // The entry points are usually called `main`, but not always in HLSL.
+        let vertex_entry = reflect::entry_point_name(vertex_spirv)?;
+        let fragment_entry = reflect::entry_point_name(fragment_spirv)?;
+
+        let vertex_shader_module = device
+            .create_shader_module(vertex_spirv)
+            .expect("Failed to create vertex shader module");
+
+        let fragment_shader_module = device
+            .create_shader_module(fragment_spirv)
+            .expect("Failed to create fragment shader module");
+
+        let (vs_entry, fs_entry) = (
+            EntryPoint {
+                entry: &vertex_entry,
+                module: &vertex_shader_module,
+                specialization: vertex_constants.specialization(),
+            },
+            EntryPoint {
+                entry: &fragment_entry,
+                module: &fragment_shader_module,
+                specialization: self.fragment_constants.specialization(),
+            },
+        );
+        let primitive_assembler = PrimitiveAssemblerDesc::Vertex {
+            buffers: vertex_buffers,
+            attributes,
+            input_assembler: config.input_assembler(),
+            vertex: vs_entry,
+            tessellation: None,
+            geometry: None,
+        };
+        let mut pipeline_desc = GraphicsPipelineDesc::new(
+            primitive_assembler,
+            config.rasterizer(self.features),
+            Some(fs_entry),
+            self.pipeline_layout,
+            Subpass {
+                index: 0,
+                main_pass: self.render_pass,
+            },
+        );
+
+        pipeline_desc.depth_stencil = config.depth_stencil();
+        for index in 0..color_attachments {
+            pipeline_desc.blender.targets.push(if index == 0 {
+                config.color_blend()
+            } else {
+                ColorBlendDesc {
+                    mask: ColorMask::ALL,
+                    blend: None,
+                }
+            });
+        }
+
+        let pipeline = device.create_graphics_pipeline(&pipeline_desc, Some(self.pipeline_cache));
+
+        device.destroy_shader_module(vertex_shader_module);
+        device.destroy_shader_module(fragment_shader_module);
+
+        pipeline.map_err(|error| format!("Failed to create graphics pipeline: {:?}", error))
+    }
+}
@@ -0,0 +1,266 @@
+//! The post-processing passes: toon shading, bloom, and tone mapping.
+//!
+//! The scene isn't drawn straight into the swapchain image. Instead, it's
+//! drawn into an HDR target, whose colors can go far beyond `1.0`, so
+//! bright highlights keep their detail. Post-processing passes then work
+//! on that, ending with the tone mapping pass, which maps the colors into
+//! the range the screen can show and writes them to the swapchain.
+//!
+//! Alongside its color, the scene writes the normal and depth of each
+//! pixel into a second target, so the toon pass can find the edges of
+//! things. The toon pass then draws into a third target.
+//!
+//! The bloom passes blur the brightest parts of the scene through a
+//! chain of smaller targets, each half the size of the one before, like
+//! the levels of a mipmap. Blurring a small target is much cheaper than
+//! blurring a big one by as much, and adding up every level gives a glow
+//! that's bright close in and fades out gently.
+
+use gfx_hal::{device::Device, format::Format, image::Layout, window::Extent2D, Backend};
+use gfx_hal_tutorials::{shader_data::ShaderData, texture, texture::Texture};
+
+use crate::pipeline::PipelineContext;
+
+/// The push constants for the tone mapping pass.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, ShaderData)]
+#[shader_data(std430)]
+pub struct ToneMapConstants {
+    /// How much to scale the scene's light by. This is a plain multiplier,
+    /// not a number of stops.
+    pub exposure: f32,
+
+    /// Which `ToneMapping` curve to use.
+    pub tone_mapping: u32,
+
+    /// How much of the bloom to add to the scene.
+    pub bloom_intensity: f32,
+}
+
+/// The push constants for the first bloom pass.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, ShaderData)]
+#[shader_data(std430)]
+pub struct BloomConstants {
+    /// How bright a color has to be, in any channel, before it blooms.
+    pub threshold: f32,
+}
+
+/// The curves for mapping the HDR scene into the range of colors the screen
+/// can show, cycled with `T`. The values match the `TONE_MAP_*` constants in
+/// `tone_map.frag`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToneMapping {
+    /// Clip anything brighter than white, as if there were no HDR target.
+    Clamp = 0,
+    Reinhard = 1,
+    Aces = 2,
+}
+
+impl ToneMapping {
+    const ALL: [ToneMapping; 3] = [ToneMapping::Clamp, ToneMapping::Reinhard, ToneMapping::Aces];
+
+    pub fn next(self) -> ToneMapping {
+        ToneMapping::ALL[(self as usize + 1) % ToneMapping::ALL.len()]
+    }
+}
+
+/// The format of every render target.
+///
+/// Every device can render to and sample `Rgba16Sfloat` images, so we
+/// don't need a fallback.
+pub const HDR_FORMAT: Format = Format::Rgba16Sfloat;
+
+// The render targets, in the order they're kept in `Resources`.
+pub const HDR_TARGET: usize = 0;
+pub const NORMAL_DEPTH_TARGET: usize = 1;
+pub const TOON_TARGET: usize = 2;
+pub const BLOOM_TARGETS: [usize; 5] = [3, 4, 5, 6, 7];
+const RENDER_TARGET_COUNT: usize = 8;
+
+/// The size of the render target at `index`, given the size of the
+/// swapchain. Each bloom target is half the size of the one before, and
+/// the first is half the size of the swapchain.
+pub fn render_target_extent(index: usize, extent: Extent2D) -> Extent2D {
+    let halvings = match BLOOM_TARGETS.iter().position(|&target| target == index) {
+        Some(level) => level + 1,
+        None => 0,
+    };
+
+    Extent2D {
+        width: (extent.width >> halvings).max(1),
+        height: (extent.height >> halvings).max(1),
+    }
+}
+
+/// Make every render target, to fit the swapchain. Like the depth buffer,
+/// the targets are recreated along with the swapchain.
+pub unsafe fn make_render_targets<B: Backend>(
+    device: &B::Device,
+    physical_device: &B::PhysicalDevice,
+    extent: Extent2D,
+) -> Vec<Texture<B>> {
+    (0..RENDER_TARGET_COUNT)
+        .map(|index| {
+            let target_extent = render_target_extent(index, extent);
+
+            texture::make_render_target::<B>(
+                device,
+                physical_device,
+                HDR_FORMAT,
+                target_extent.width,
+                target_extent.height,
+            )
+        })
+        .collect()
+}
+
+// Each post-processing pass reads its inputs through a descriptor set of
+// its own. They come after the scene's two sets in `descriptor_sets`:
+// one for the toon pass, two for the tone mapping pass, depending on
+// whether the toon pass ran before it, and one for the first bloom pass.
+// Then there's one for reading each bloom target, which the bloom passes
+// use to go both down and back up the chain.
+pub const FIRST_POST_SET: usize = 2;
+pub const TOON_SET: usize = FIRST_POST_SET;
+pub const TONE_MAP_SET: usize = FIRST_POST_SET + 1;
+pub const TOON_TONE_MAP_SET: usize = FIRST_POST_SET + 2;
+pub const BLOOM_BRIGHT_SET: usize = FIRST_POST_SET + 3;
+pub const FIRST_BLOOM_SET: usize = FIRST_POST_SET + 4;
+
+// The render targets each post-processing set reads, in binding order.
+// The bloom is taken from the scene itself, before any toon shading, and
+// the tone mapping pass adds the largest level of it back in.
+pub const POST_SET_INPUTS: [&[usize]; 9] = [
+    &[HDR_TARGET, NORMAL_DEPTH_TARGET],
+    &[HDR_TARGET, BLOOM_TARGETS[0]],
+    &[TOON_TARGET, BLOOM_TARGETS[0]],
+    &[HDR_TARGET],
+    &[BLOOM_TARGETS[0]],
+    &[BLOOM_TARGETS[1]],
+    &[BLOOM_TARGETS[2]],
+    &[BLOOM_TARGETS[3]],
+    &[BLOOM_TARGETS[4]],
+];
+
+/// Point each post-processing descriptor set at its render targets.
+///
+/// The first input goes in binding 0, followed by the `post_sampler` in
+/// binding 1, and any other inputs from binding 2 on. The targets are
+/// recreated when the window is resized, so the sets have to be written
+/// again then too.
+pub unsafe fn write_post_sets<B: Backend>(
+    device: &B::Device,
+    sets: &[B::DescriptorSet],
+    render_targets: &[Texture<B>],
+    sampler: &B::Sampler,
+) {
+    use gfx_hal::pso::{Descriptor, DescriptorSetWrite};
+
+    for (set, inputs) in sets.iter().zip(POST_SET_INPUTS.iter()) {
+        let input = |index: &usize| {
+            Descriptor::Image(&render_targets[*index].view, Layout::ShaderReadOnlyOptimal)
+        };
+
+        device.write_descriptor_sets(vec![DescriptorSetWrite {
+            set,
+            binding: 0,
+            array_offset: 0,
+            descriptors: vec![input(&inputs[0]), Descriptor::Sampler(sampler)],
+        }]);
+
+        if inputs.len() > 1 {
+            device.write_descriptor_sets(vec![DescriptorSetWrite {
+                set,
+                binding: 2,
+                array_offset: 0,
+                descriptors: inputs[1..].iter().map(input).collect::<Vec<_>>(),
+            }]);
+        }
+    }
+}
+
+/// Make a render pass for a post-processing pass, which draws a single
+/// triangle over the whole of its `format` target.
+///
+/// The triangle covers every pixel, so there's no need to clear the
+/// target first. If it ends up in `ShaderReadOnlyOptimal`, another pass
+/// is going to sample it, so that pass waits for it to be drawn.
+///
+/// If `blend` is set, the pass blends onto what's already in the target,
+/// which another pass has left ready for sampling. So the target is
+/// loaded first, and the pass waits for the ones before it to finish
+/// drawing to it and reading from it.
+pub unsafe fn make_render_pass<B: Backend>(
+    device: &B::Device,
+    format: Format,
+    final_layout: Layout,
+    blend: bool,
+) -> B::RenderPass {
+    use gfx_hal::image::Access;
+    use gfx_hal::memory::Dependencies;
+    use gfx_hal::pass::{
+        Attachment, AttachmentLoadOp, AttachmentOps, AttachmentStoreOp, SubpassDependency,
+        SubpassDesc,
+    };
+    use gfx_hal::pso::PipelineStage;
+
+    let (load_op, initial_layout) = if blend {
+        (AttachmentLoadOp::Load, Layout::ShaderReadOnlyOptimal)
+    } else {
+        (AttachmentLoadOp::DontCare, Layout::Undefined)
+    };
+
+    let color_attachment = Attachment {
+        format: Some(format),
+        samples: 1,
+        ops: AttachmentOps::new(load_op, AttachmentStoreOp::Store),
+        stencil_ops: AttachmentOps::DONT_CARE,
+        layouts: initial_layout..final_layout,
+    };
+
+    let subpass = SubpassDesc {
+        colors: &[(0, Layout::ColorAttachmentOptimal)],
+        depth_stencil: None,
+        inputs: &[],
+        resolves: &[],
+        preserves: &[],
+    };
+
+    let mut dependencies = vec![];
+
+    if blend {
+        dependencies.push(SubpassDependency {
+            passes: None..Some(0),
+            stages: (PipelineStage::COLOR_ATTACHMENT_OUTPUT | PipelineStage::FRAGMENT_SHADER)
+                ..PipelineStage::COLOR_ATTACHMENT_OUTPUT,
+            accesses: Access::COLOR_ATTACHMENT_WRITE
+                ..(Access::COLOR_ATTACHMENT_READ | Access::COLOR_ATTACHMENT_WRITE),
+            flags: Dependencies::empty(),
+        });
+    }
+
+    if final_layout == Layout::ShaderReadOnlyOptimal {
+        dependencies.push(SubpassDependency {
+            passes: Some(0)..None,
+            stages: PipelineStage::COLOR_ATTACHMENT_OUTPUT..PipelineStage::FRAGMENT_SHADER,
+            accesses: Access::COLOR_ATTACHMENT_WRITE..Access::SHADER_READ,
+            flags: Dependencies::empty(),
+        });
+    }
+
+    device
+        .create_render_pass(&[color_attachment], &[subpass], &dependencies)
+        .expect("Out of memory")
+}
+
+/// Create a pipeline for a post-processing pass, which draws a single
+/// triangle over the whole screen with `fullscreen.vert`.
+///
+/// Like the sky, it has no vertex buffer. Post-processing passes don't
+/// share a layout, so each one has its own push constants, if any.
+pub unsafe fn make_post_pipeline<B: Backend>(
+    context: &PipelineContext<B>,
+) -> Result<B::GraphicsPipeline, String> {
+    context.make_pipeline(&[], &[], 1)
+}
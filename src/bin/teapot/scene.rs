@@ -0,0 +1,215 @@
+//! The main pass, which draws the teapot and the ground into the HDR target.
+//!
+//! The sky is drawn in the same render pass, but it has a module of its own.
+
+use gfx_hal::{device::Device, format::Format, Backend};
+use gfx_hal_tutorials::{
+    material::MaterialConfig,
+    mesh::{LineVertex, Vertex},
+    shader_data::ShaderData,
+    vertex_layout::VertexLayout,
+};
+
+use crate::pipeline::PipelineContext;
+use crate::post::HDR_FORMAT;
+
+/// A struct representing the data that we want to supply in push constants.
+///
+/// The `repr(C)` attribute is required to ensure that the memory layout is
+/// what we expect. Without it, no specific layout is guaranteed. Deriving
+/// `ShaderData` checks that the layout matches GLSL's rules, which for push
+/// constants are `std430`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, ShaderData)]
+#[shader_data(std430)]
+pub struct PushConstants {
+    pub transform: [[f32; 4]; 4],
+
+    /// Transforms the normals. See `camera::normal_matrix`.
+    pub normal_matrix: [[f32; 4]; 3],
+
+    /// How many lights there are in the light buffer.
+    pub light_count: u32,
+}
+
+/// Camera data shared by every draw in a frame, stored in a uniform buffer.
+///
+/// Every field is a `vec4` or `mat4` so that the `repr(C)` layout matches
+/// the `std140` layout of the uniform block in the shaders.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, ShaderData)]
+#[shader_data(std140)]
+pub struct CameraUniforms {
+    pub view_projection: [[f32; 4]; 4],
+    pub position: [f32; 4],
+
+    /// The camera's axes, with `view_right` and `view_down` scaled so that
+    /// they reach the edges of the screen at a distance of `1.0`. The sky
+    /// shader uses these to work out the direction of each pixel.
+    pub view_right: [f32; 4],
+    pub view_down: [f32; 4],
+    pub view_forward: [f32; 4],
+}
+
+/// Material data for a single draw, stored in a uniform buffer. See
+/// `MaterialConfig` for what each field means.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, ShaderData)]
+#[shader_data(std140)]
+pub struct MaterialUniforms {
+    pub base_color: [f32; 4],
+    pub metallic: f32,
+    pub roughness: f32,
+    pub texture_flags: u32,
+}
+
+impl From<&MaterialConfig> for MaterialUniforms {
+    fn from(config: &MaterialConfig) -> Self {
+        MaterialUniforms {
+            base_color: config.base_color,
+            metallic: config.metallic,
+            roughness: config.roughness,
+            texture_flags: config.texture_flags(),
+        }
+    }
+}
+
+/// Lighting data shared by every draw in a frame, stored in a uniform
+/// buffer.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, ShaderData)]
+#[shader_data(std140)]
+pub struct LightingUniforms {
+    /// The direction the directional light travels in.
+    pub direction: [f32; 4],
+
+    /// The directional light's color, multiplied by its brightness.
+    pub color: [f32; 4],
+
+    /// The diffuse light from the sky, from `ibl::irradiance_sh`.
+    pub irradiance: [[f32; 4]; 9],
+}
+
+/// The ways the teapot can be drawn, picked with the number keys.
+///
+/// Each one is a separate pipeline, made from the same shaders with a
+/// different `VIEW_MODE` specialization constant. The values match the
+/// `VIEW_*` constants in `teapot.frag`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ViewMode {
+    BlinnPhong = 0,
+    Pbr = 1,
+    Normals = 2,
+    Depth = 3,
+    Wireframe = 4,
+}
+
+impl ViewMode {
+    pub const ALL: [ViewMode; 5] = [
+        ViewMode::BlinnPhong,
+        ViewMode::Pbr,
+        ViewMode::Normals,
+        ViewMode::Depth,
+        ViewMode::Wireframe,
+    ];
+}
+
+/// The scene draws its color into the first attachment, and the normal and
+/// depth of each pixel into the second, for the toon pass to find edges with.
+pub const COLOR_ATTACHMENTS: usize = 2;
+
+/// Make the render pass that draws the scene into the HDR and normal-depth
+/// targets, with a `depth_format` depth buffer.
+pub unsafe fn make_render_pass<B: Backend>(
+    device: &B::Device,
+    depth_format: Format,
+) -> B::RenderPass {
+    use gfx_hal::image::{Access, Layout};
+    use gfx_hal::memory::Dependencies;
+    use gfx_hal::pass::{
+        Attachment, AttachmentLoadOp, AttachmentOps, AttachmentStoreOp, SubpassDependency,
+        SubpassDesc,
+    };
+    use gfx_hal::pso::PipelineStage;
+
+    // The scene pipelines blend with what's already in the color
+    // attachments, so they're cleared first rather than left undefined.
+    // Afterwards, they're left ready for the post-processing passes to
+    // sample.
+    let color_attachment = Attachment {
+        format: Some(HDR_FORMAT),
+        samples: 1,
+        ops: AttachmentOps::new(AttachmentLoadOp::Clear, AttachmentStoreOp::Store),
+        stencil_ops: AttachmentOps::DONT_CARE,
+        layouts: Layout::Undefined..Layout::ShaderReadOnlyOptimal,
+    };
+    let normal_depth_attachment = color_attachment.clone();
+
+    // We only need the depth buffer during the render pass, so its
+    // contents don't need to be stored afterwards.
+    let depth_attachment = Attachment {
+        format: Some(depth_format),
+        samples: 1,
+        ops: AttachmentOps::new(AttachmentLoadOp::Clear, AttachmentStoreOp::DontCare),
+        stencil_ops: AttachmentOps::DONT_CARE,
+        layouts: Layout::Undefined..Layout::DepthStencilAttachmentOptimal,
+    };
+
+    let subpass = SubpassDesc {
+        colors: &[
+            (0, Layout::ColorAttachmentOptimal),
+            (1, Layout::ColorAttachmentOptimal),
+        ],
+        depth_stencil: Some(&(2, Layout::DepthStencilAttachmentOptimal)),
+        inputs: &[],
+        resolves: &[],
+        preserves: &[],
+    };
+
+    // The post-processing passes have to wait for the scene to be
+    // drawn before they read it.
+    let dependency = SubpassDependency {
+        passes: Some(0)..None,
+        stages: PipelineStage::COLOR_ATTACHMENT_OUTPUT..PipelineStage::FRAGMENT_SHADER,
+        accesses: Access::COLOR_ATTACHMENT_WRITE..Access::SHADER_READ,
+        flags: Dependencies::empty(),
+    };
+
+    device
+        .create_render_pass(
+            &[color_attachment, normal_depth_attachment, depth_attachment],
+            &[subpass],
+            &[dependency],
+        )
+        .expect("Out of memory")
+}
+
+/// Create the pipeline for drawing teapots.
+///
+/// Compared to part 3, our vertices now have a third attribute: the `uv`
+/// texture coordinates. Rather than writing out the offset and format of
+/// each attribute by hand, we get them from the `Vertex` struct itself,
+/// so they can't fall out of sync with it.
+pub unsafe fn make_teapot_pipeline<B: Backend>(
+    context: &PipelineContext<B>,
+) -> Result<B::GraphicsPipeline, String> {
+    context.make_pipeline(
+        &[Vertex::vertex_buffer(0)],
+        &Vertex::attributes(0),
+        COLOR_ATTACHMENTS,
+    )
+}
+
+/// Create the pipeline for drawing the teapot's face normals.
+///
+/// Each line is positioned by the same transform as the teapot, so this
+/// uses the teapot's pipeline layout and push constants.
+pub unsafe fn make_face_normals_pipeline<B: Backend>(
+    context: &PipelineContext<B>,
+) -> Result<B::GraphicsPipeline, String> {
+    context.make_pipeline(
+        &[LineVertex::vertex_buffer(0)],
+        &LineVertex::attributes(0),
+        COLOR_ATTACHMENTS,
+    )
+}
@@ -0,0 +1,133 @@
+//! The shadow pass, which draws the scene's depth from the directional
+//! light's point of view into the shadow map.
+//!
+//! Anything further from the light than the depth stored there is in shadow.
+//! Unlike the depth buffer, the shadow map doesn't depend on the size of the
+//! window, so everything here is only made once.
+
+use gfx_hal::{adapter::PhysicalDevice, device::Device, format::Format, Backend};
+use gfx_hal_tutorials::{mesh::Vertex, shader_data::ShaderData, vertex_layout::VertexLayout};
+
+use crate::pipeline::PipelineContext;
+
+/// The width and height of the shadow map.
+pub const SHADOW_MAP_SIZE: u32 = 2048;
+
+/// The directional light's view of the scene, for shadow mapping, stored in
+/// a uniform buffer.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, ShaderData)]
+#[shader_data(std140)]
+pub struct ShadowUniforms {
+    pub view_projection: [[f32; 4]; 4],
+}
+
+/// Make the sampler that the main pass reads the shadow map with.
+///
+/// The shadow map is read with a comparison sampler, which compares the
+/// depths with the one we give it, then filters the results. Filtering
+/// the depths themselves wouldn't make sense. Not every device can filter
+/// every depth format, so we fall back to nearest filtering.
+pub unsafe fn make_sampler<B: Backend>(
+    device: &B::Device,
+    physical_device: &B::PhysicalDevice,
+    format: Format,
+) -> B::Sampler {
+    use gfx_hal::format::ImageFeature;
+    use gfx_hal::image::{Filter, SamplerDesc, WrapMode};
+    use gfx_hal::pso::Comparison;
+
+    let filter = if physical_device
+        .format_properties(Some(format))
+        .optimal_tiling
+        .contains(ImageFeature::SAMPLED_LINEAR)
+    {
+        Filter::Linear
+    } else {
+        Filter::Nearest
+    };
+
+    let mut desc = SamplerDesc::new(filter, WrapMode::Clamp);
+    desc.comparison = Some(Comparison::LessEqual);
+
+    device
+        .create_sampler(&desc)
+        .expect("Failed to create shadow sampler")
+}
+
+/// Make the render pass that renders the shadow map. It has no color
+/// attachment, just depth, which is stored at the end so the main pass can
+/// sample it.
+pub unsafe fn make_render_pass<B: Backend>(device: &B::Device, format: Format) -> B::RenderPass {
+    use gfx_hal::image::{Access, Layout};
+    use gfx_hal::memory::Dependencies;
+    use gfx_hal::pass::{
+        Attachment, AttachmentLoadOp, AttachmentOps, AttachmentStoreOp, SubpassDependency,
+        SubpassDesc,
+    };
+    use gfx_hal::pso::PipelineStage;
+
+    // The render pass leaves the shadow map ready for sampling, so we
+    // don't need a separate barrier to change its layout.
+    let depth_attachment = Attachment {
+        format: Some(format),
+        samples: 1,
+        ops: AttachmentOps::new(AttachmentLoadOp::Clear, AttachmentStoreOp::Store),
+        stencil_ops: AttachmentOps::DONT_CARE,
+        layouts: Layout::Undefined..Layout::ShaderReadOnlyOptimal,
+    };
+
+    let subpass = SubpassDesc {
+        colors: &[],
+        depth_stencil: Some(&(0, Layout::DepthStencilAttachmentOptimal)),
+        inputs: &[],
+        resolves: &[],
+        preserves: &[],
+    };
+
+    // The main pass's fragment shaders have to wait for the shadow map's
+    // depth to be written before they read it.
+    let dependency = SubpassDependency {
+        passes: Some(0)..None,
+        stages: PipelineStage::LATE_FRAGMENT_TESTS..PipelineStage::FRAGMENT_SHADER,
+        accesses: Access::DEPTH_STENCIL_ATTACHMENT_WRITE..Access::SHADER_READ,
+        flags: Dependencies::empty(),
+    };
+
+    device
+        .create_render_pass(&[depth_attachment], &[subpass], &[dependency])
+        .expect("Out of memory")
+}
+
+/// Make the framebuffer for drawing into the `shadow_map`. The shadow map is
+/// always the same image, so this only has to be done once.
+pub unsafe fn make_framebuffer<B: Backend>(
+    device: &B::Device,
+    render_pass: &B::RenderPass,
+    shadow_map: &B::ImageView,
+) -> B::Framebuffer {
+    use gfx_hal::image::Extent;
+
+    device
+        .create_framebuffer(
+            render_pass,
+            vec![shadow_map],
+            Extent {
+                width: SHADOW_MAP_SIZE,
+                height: SHADOW_MAP_SIZE,
+                depth: 1,
+            },
+        )
+        .expect("Out of memory")
+}
+
+/// Create the pipeline for drawing teapots into the shadow map.
+///
+/// It takes the same vertices as the teapot pipeline, though its vertex
+/// shader only reads their positions. The shadow render pass has no
+/// color attachment, so the pipeline has no color targets either.
+pub unsafe fn make_shadow_pipeline<B: Backend>(
+    context: &PipelineContext<B>,
+) -> Result<B::GraphicsPipeline, String> {
+    context.make_pipeline(&[Vertex::vertex_buffer(0)], &Vertex::attributes(0), 0)
+}
@@ -0,0 +1,56 @@
+//! The sky, which is drawn behind the teapot in the main pass, and lights it.
+
+use gfx_hal::Backend;
+use gfx_hal_tutorials::{cube, ibl, texture, texture::Texture};
+
+use crate::pipeline::PipelineContext;
+use crate::scene;
+
+/// Upload the sky texture, and work out the diffuse lighting it gives.
+///
+/// The sky image is an equirectangular projection of the whole sky. We
+/// convert it into the six faces of a cube map, which can be sampled with
+/// a direction: by the sky shader to draw the sky itself, and by the
+/// teapot shader for reflections.
+///
+/// The sky also lights the teapot, so we work out its diffuse lighting
+/// from the same image.
+pub unsafe fn upload_sky<B: Backend>(
+    device: &B::Device,
+    physical_device: &B::PhysicalDevice,
+    command_pool: &mut B::CommandPool,
+    queue: &mut B::CommandQueue,
+) -> (Texture<B>, [[f32; 4]; 9]) {
+    use gfx_hal::format::Format;
+
+    let png_data = include_bytes!("../../../assets/sky.png");
+    let pixels = image::load_from_memory(png_data)
+        .expect("Failed to decode texture")
+        .to_rgba8();
+
+    // Each face covers a quarter of the horizon.
+    let face_size = pixels.width() / 4;
+    let faces = cube::faces_from_equirect(&pixels, face_size);
+
+    let texture = texture::upload_cube_texture::<B>(
+        device,
+        physical_device,
+        command_pool,
+        queue,
+        &faces,
+        Format::Rgba8Srgb,
+    );
+
+    (texture, ibl::irradiance_sh(&pixels))
+}
+
+/// Create the pipeline for drawing the sky.
+///
+/// The sky has no vertex buffer: its vertex shader makes a full-screen
+/// triangle out of thin air. How it's depth tested against the teapot is
+/// described in `assets/pipelines.ron`.
+pub unsafe fn make_sky_pipeline<B: Backend>(
+    context: &PipelineContext<B>,
+) -> Result<B::GraphicsPipeline, String> {
+    context.make_pipeline(&[], &[], scene::COLOR_ATTACHMENTS)
+}
@@ -0,0 +1,92 @@
+//! Buffer and memory helpers.
+
+use gfx_hal::{adapter::PhysicalDevice, device::Device, memory::Properties, Backend, MemoryTypeId};
+
+/// Find a memory type that is both allowed by `type_mask` and has all of
+/// the requested `properties`.
+///
+/// The `type_mask` comes from the `Requirements` of a buffer or image. If
+/// the nth bit in the mask is `1`, then the nth memory type of the physical
+/// device is supported.
+pub fn find_memory_type<B: Backend>(
+    physical_device: &B::PhysicalDevice,
+    type_mask: u32,
+    properties: Properties,
+) -> MemoryTypeId {
+    physical_device
+        .memory_properties()
+        .memory_types
+        .iter()
+        .enumerate()
+        .find(|(id, mem_type)| {
+            let type_supported = type_mask & (1_u32 << id) != 0;
+            type_supported && mem_type.properties.contains(properties)
+        })
+        .map(|(id, _ty)| MemoryTypeId(id))
+        .expect("No compatible memory type available")
+}
+
+/// Create an empty buffer with the given size and properties.
+///
+/// This is the same as the `make_buffer` function from part 3. The `usage`
+/// parameter defines how the buffer should be treated (vertex buffer,
+/// transfer source, etc). The `properties` specify the kind of memory that
+/// should be used to store this buffer (CPU visible, device local, etc).
+pub unsafe fn make_buffer<B: Backend>(
+    device: &B::Device,
+    physical_device: &B::PhysicalDevice,
+    buffer_len: usize,
+    usage: gfx_hal::buffer::Usage,
+    properties: Properties,
+) -> (B::Memory, B::Buffer) {
+    let mut buffer = device
+        .create_buffer(buffer_len as u64, usage)
+        .expect("Failed to create buffer");
+
+    let req = device.get_buffer_requirements(&buffer);
+    let memory_type = find_memory_type::<B>(physical_device, req.type_mask, properties);
+
+    let buffer_memory = device
+        .allocate_memory(memory_type, req.size)
+        .expect("Failed to allocate buffer memory");
+
+    device
+        .bind_buffer_memory(&buffer_memory, 0, &mut buffer)
+        .expect("Failed to bind buffer memory");
+
+    (buffer_memory, buffer)
+}
+
+/// Create a CPU-visible buffer and fill it with the contents of `data`.
+pub unsafe fn make_filled_buffer<B: Backend, T: Copy>(
+    device: &B::Device,
+    physical_device: &B::PhysicalDevice,
+    data: &[T],
+    usage: gfx_hal::buffer::Usage,
+) -> (B::Memory, B::Buffer) {
+    use gfx_hal::memory::Segment;
+
+    let buffer_len = std::mem::size_of_val(data);
+
+    let (memory, buffer) = make_buffer::<B>(
+        device,
+        physical_device,
+        buffer_len,
+        usage,
+        Properties::CPU_VISIBLE,
+    );
+
+    let mapped_memory = device
+        .map_memory(&memory, Segment::ALL)
+        .expect("Failed to map memory");
+
+    std::ptr::copy_nonoverlapping(data.as_ptr() as *const u8, mapped_memory, buffer_len);
+
+    device
+        .flush_mapped_memory_ranges(vec![(&memory, Segment::ALL)])
+        .expect("Out of memory");
+
+    device.unmap_memory(&memory);
+
+    (memory, buffer)
+}
@@ -0,0 +1,182 @@
+//! A simple perspective camera.
+//!
+//! The teapot mesh has `y` pointing down, to match Vulkan's clip space, so
+//! the world does too. That means "up" is `-y`, and the projection doesn't
+//! need to flip anything.
+//!
+//! Matrices are column-major, like in GLSL: `matrix[3]` is the translation.
+
+/// A camera positioned at `position`, looking at `target`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Camera {
+    pub position: [f32; 3],
+    pub target: [f32; 3],
+
+    /// The vertical field of view, in radians.
+    pub fov_y: f32,
+    pub near: f32,
+    pub far: f32,
+}
+
+/// The camera's axes in world space.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CameraBasis {
+    pub right: [f32; 3],
+    pub down: [f32; 3],
+    pub forward: [f32; 3],
+}
+
+impl Camera {
+    /// The directions the camera sees as right, down, and forward.
+    pub fn basis(&self) -> CameraBasis {
+        let forward = normalize(sub(self.target, self.position));
+        let right = normalize(cross([0.0, 1.0, 0.0], forward));
+        let down = cross(forward, right);
+
+        CameraBasis {
+            right,
+            down,
+            forward,
+        }
+    }
+
+    /// A matrix that transforms world space into the camera's view space:
+    /// `x` right, `y` down, and `z` forward.
+    pub fn view(&self) -> [[f32; 4]; 4] {
+        let CameraBasis {
+            right,
+            down,
+            forward,
+        } = self.basis();
+        let p = self.position;
+
+        [
+            [right[0], down[0], forward[0], 0.],
+            [right[1], down[1], forward[1], 0.],
+            [right[2], down[2], forward[2], 0.],
+            [-dot(right, p), -dot(down, p), -dot(forward, p), 1.],
+        ]
+    }
+
+    /// A perspective projection from view space into clip space, with depth
+    /// going from `0.0` at the near plane to `1.0` at the far plane.
+    pub fn projection(&self, aspect_ratio: f32) -> [[f32; 4]; 4] {
+        let f = 1.0 / (self.fov_y / 2.0).tan();
+        let depth_scale = self.far / (self.far - self.near);
+
+        [
+            [f / aspect_ratio, 0., 0., 0.],
+            [0., f, 0., 0.],
+            [0., 0., depth_scale, 1.],
+            [0., 0., -self.near * depth_scale, 0.],
+        ]
+    }
+
+    /// The view and projection matrices combined.
+    pub fn view_projection(&self, aspect_ratio: f32) -> [[f32; 4]; 4] {
+        mat4_mul(self.projection(aspect_ratio), self.view())
+    }
+
+    /// How far the edges of the screen are from its center, horizontally and
+    /// vertically, at a distance of `1.0` in front of the camera.
+    pub fn view_scale(&self, aspect_ratio: f32) -> [f32; 2] {
+        let scale_y = (self.fov_y / 2.0).tan();
+        [scale_y * aspect_ratio, scale_y]
+    }
+}
+
+/// A view-projection matrix for a directional light travelling in
+/// `direction`, which covers everything within `radius` of `center`.
+///
+/// A directional light's rays are all parallel, so unlike the camera's, its
+/// projection is orthographic. Depth goes from `0.0` at the near side of the
+/// sphere to `1.0` at the far side.
+pub fn directional_light_view_projection(
+    direction: [f32; 3],
+    center: [f32; 3],
+    radius: f32,
+) -> [[f32; 4]; 4] {
+    let forward = normalize(direction);
+
+    // Only the light's view is used, so its field of view doesn't matter.
+    let light = Camera {
+        position: sub(
+            center,
+            [
+                forward[0] * 2.0 * radius,
+                forward[1] * 2.0 * radius,
+                forward[2] * 2.0 * radius,
+            ],
+        ),
+        target: center,
+        fov_y: 0.0,
+        near: radius,
+        far: 3.0 * radius,
+    };
+
+    let depth_scale = 1.0 / (light.far - light.near);
+    let projection = [
+        [1.0 / radius, 0., 0., 0.],
+        [0., 1.0 / radius, 0., 0.],
+        [0., 0., depth_scale, 0.],
+        [0., 0., -light.near * depth_scale, 1.],
+    ];
+
+    mat4_mul(projection, light.view())
+}
+
+/// Multiply two column-major matrices.
+pub fn mat4_mul(a: [[f32; 4]; 4], b: [[f32; 4]; 4]) -> [[f32; 4]; 4] {
+    let mut result = [[0.0; 4]; 4];
+
+    for (column, b_column) in result.iter_mut().zip(&b) {
+        for (row, value) in column.iter_mut().enumerate() {
+            *value = (0..4).map(|i| a[i][row] * b_column[i]).sum();
+        }
+    }
+
+    result
+}
+
+/// The matrix for transforming normals by `transform`: the inverse
+/// transpose of its upper 3x3 part.
+///
+/// Normals have to stay perpendicular to their surface, which the transform
+/// itself doesn't guarantee if it scales unevenly. The result is a `mat3` in
+/// GLSL, whose columns are each padded out to a `vec4`.
+pub fn normal_matrix(transform: [[f32; 4]; 4]) -> [[f32; 4]; 3] {
+    let column = |i: usize| [transform[i][0], transform[i][1], transform[i][2]];
+    let [a, b, c] = [column(0), column(1), column(2)];
+
+    // The rows of the inverse are these cross products divided by the
+    // determinant, so they're the columns of the inverse transpose.
+    let det = dot(a, cross(b, c));
+    let padded = |v: [f32; 3]| [v[0] / det, v[1] / det, v[2] / det, 0.0];
+
+    [
+        padded(cross(b, c)),
+        padded(cross(c, a)),
+        padded(cross(a, b)),
+    ]
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn normalize(v: [f32; 3]) -> [f32; 3] {
+    let length = dot(v, v).sqrt();
+    [v[0] / length, v[1] / length, v[2] / length]
+}
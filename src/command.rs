@@ -0,0 +1,39 @@
+//! Helpers for recording and submitting commands outside the render loop.
+
+use gfx_hal::{
+    command::CommandBuffer, device::Device, pool::CommandPool, queue::CommandQueue, Backend,
+};
+
+/// Record some commands into a fresh command buffer, submit them, and wait
+/// for them to finish.
+///
+/// This is handy for one-off work like uploading textures, where we don't
+/// mind stalling because it only happens during initialization.
+pub unsafe fn submit_and_wait<B, F>(
+    device: &B::Device,
+    command_pool: &mut B::CommandPool,
+    queue: &mut B::CommandQueue,
+    record: F,
+) where
+    B: Backend,
+    F: FnOnce(&mut B::CommandBuffer),
+{
+    use gfx_hal::command::{CommandBufferFlags, Level};
+
+    let mut command_buffer = command_pool.allocate_one(Level::Primary);
+
+    command_buffer.begin_primary(CommandBufferFlags::ONE_TIME_SUBMIT);
+    record(&mut command_buffer);
+    command_buffer.finish();
+
+    let fence = device.create_fence(false).expect("Out of memory");
+
+    queue.submit_without_semaphores(Some(&command_buffer), Some(&fence));
+
+    device
+        .wait_for_fence(&fence, !0)
+        .expect("Out of memory or device lost");
+
+    device.destroy_fence(fence);
+    command_pool.free(Some(command_buffer));
+}
@@ -0,0 +1,113 @@
+//! Cube map faces.
+//!
+//! A cube map is an image with six square layers, one for each face of a
+//! cube. Sampling it with a direction vector picks the face that direction
+//! points at, which makes it a good fit for skies and reflections.
+
+use image::{Rgba, RgbaImage};
+
+/// A face of a cube map.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Face {
+    PositiveX,
+    NegativeX,
+    PositiveY,
+    NegativeY,
+    PositiveZ,
+    NegativeZ,
+}
+
+/// The order of the faces in a cube map's layers.
+pub const FACES: [Face; 6] = [
+    Face::PositiveX,
+    Face::NegativeX,
+    Face::PositiveY,
+    Face::NegativeY,
+    Face::PositiveZ,
+    Face::NegativeZ,
+];
+
+impl Face {
+    /// The direction (with `y` pointing up) that the given point on this face
+    /// is sampled with.
+    ///
+    /// The `s` and `t` coordinates are in the range `-1.0..1.0`, with `t`
+    /// pointing down the image. The result isn't normalized.
+    pub fn direction(self, s: f32, t: f32) -> [f32; 3] {
+        match self {
+            Face::PositiveX => [1.0, -t, -s],
+            Face::NegativeX => [-1.0, -t, s],
+            Face::PositiveY => [s, 1.0, t],
+            Face::NegativeY => [s, -1.0, -t],
+            Face::PositiveZ => [s, -t, 1.0],
+            Face::NegativeZ => [-s, -t, -1.0],
+        }
+    }
+}
+
+/// Convert an equirectangular image into the six faces of a cube map, each
+/// `face_size` pixels square.
+///
+/// The horizontal axis of the image is the longitude, with `+z` in the
+/// middle, and the vertical axis is the latitude, with straight up at the
+/// top.
+pub fn faces_from_equirect(equirect: &RgbaImage, face_size: u32) -> [RgbaImage; 6] {
+    let make_face = |face: Face| {
+        RgbaImage::from_fn(face_size, face_size, |x, y| {
+            // We sample at the center of each pixel.
+            let s = (x as f32 + 0.5) / face_size as f32 * 2.0 - 1.0;
+            let t = (y as f32 + 0.5) / face_size as f32 * 2.0 - 1.0;
+            let [dx, dy, dz] = face.direction(s, t);
+            let length = (dx * dx + dy * dy + dz * dz).sqrt();
+
+            let u = 0.5 + dx.atan2(dz) / (2.0 * std::f32::consts::PI);
+            let v = 0.5 - (dy / length).asin() / std::f32::consts::PI;
+
+            sample_bilinear(equirect, u, v)
+        })
+    };
+
+    [
+        make_face(FACES[0]),
+        make_face(FACES[1]),
+        make_face(FACES[2]),
+        make_face(FACES[3]),
+        make_face(FACES[4]),
+        make_face(FACES[5]),
+    ]
+}
+
+/// Sample an image with bilinear filtering, wrapping horizontally and
+/// clamping vertically.
+fn sample_bilinear(pixels: &RgbaImage, u: f32, v: f32) -> Rgba<u8> {
+    let (width, height) = pixels.dimensions();
+
+    let x = u * width as f32 - 0.5;
+    let y = (v * height as f32 - 0.5).max(0.0).min((height - 1) as f32);
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+
+    let texel = |x: f32, y: f32| {
+        let x = (x as i64).rem_euclid(width as i64) as u32;
+        let y = (y as u32).min(height - 1);
+        pixels.get_pixel(x, y).0
+    };
+
+    let corners = [
+        (texel(x0, y0), (1.0 - fx) * (1.0 - fy)),
+        (texel(x0 + 1.0, y0), fx * (1.0 - fy)),
+        (texel(x0, y0 + 1.0), (1.0 - fx) * fy),
+        (texel(x0 + 1.0, y0 + 1.0), fx * fy),
+    ];
+
+    let mut result = [0; 4];
+    for (channel, value) in result.iter_mut().enumerate() {
+        let sum: f32 = corners
+            .iter()
+            .map(|(texel, weight)| texel[channel] as f32 * weight)
+            .sum();
+        *value = sum.round() as u8;
+    }
+
+    Rgba(result)
+}
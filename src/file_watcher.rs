@@ -0,0 +1,62 @@
+//! Noticing when a single file changes on disk.
+//!
+//! This works the same way as `ShaderWatcher`, by checking the file's
+//! modification time every so often, but it's for files that are simply
+//! read rather than compiled, like `assets/pipelines.ron`.
+
+use std::path::{Path, PathBuf};
+use std::time::{Instant, SystemTime};
+
+use crate::shader_watcher::POLL_INTERVAL;
+
+/// Watches a file, and reads it again when it changes.
+pub struct FileWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    last_poll: Instant,
+}
+
+impl FileWatcher {
+    /// Start watching the file at `path`. Only changes made after this are
+    /// picked up.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+
+        FileWatcher {
+            modified: modified_time(&path),
+            path,
+            last_poll: Instant::now(),
+        }
+    }
+
+    /// If the file has changed since the last call, return its new contents.
+    ///
+    /// If the file can't be read, the error is printed and this returns
+    /// `None`, so that the caller can carry on with the old contents.
+    pub fn poll(&mut self) -> Option<String> {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return None;
+        }
+        self.last_poll = Instant::now();
+
+        let modified = modified_time(&self.path);
+        if modified.is_none() || modified == self.modified {
+            return None;
+        }
+        self.modified = modified;
+
+        match std::fs::read_to_string(&self.path) {
+            Ok(contents) => Some(contents),
+            Err(error) => {
+                eprintln!("Failed to read {}: {}", self.path.display(), error);
+                None
+            }
+        }
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
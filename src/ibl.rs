@@ -0,0 +1,97 @@
+//! Image-based lighting.
+//!
+//! Rather than lighting the teapot with a handful of lights, we can light it
+//! with the whole sky. Every direction in the sky image is a light, so the
+//! light arriving at a surface is an integral over the hemisphere above it.
+//!
+//! For diffuse lighting, that integral is very smooth: it barely changes
+//! between nearby normals. That means it can be stored in just a few
+//! spherical harmonic coefficients, which we compute once on the CPU. The
+//! shader evaluates them to get the diffuse light for any normal.
+
+use image::RgbaImage;
+
+/// Compute the spherical harmonic coefficients of the diffuse light from an
+/// equirectangular sky image, laid out like the one for
+/// `cube::faces_from_equirect`.
+///
+/// There are nine coefficients, for bands 0 to 2, each with a color in
+/// `rgb`. They're in the order that `sky_irradiance` in `lighting.glsl`
+/// expects.
+///
+/// The coefficients are for world space, with `y` pointing down, so they can
+/// be evaluated with world space normals directly. They already include the
+/// convolution with the cosine lobe, and the division by pi from the
+/// Lambertian BRDF, so the shader can multiply the result by the diffuse
+/// color and be done.
+pub fn irradiance_sh(equirect: &RgbaImage) -> [[f32; 4]; 9] {
+    use std::f32::consts::PI;
+
+    // The image is sRGB-encoded, but light adds up linearly, so we decode
+    // every value first.
+    let mut srgb_to_linear = [0.0; 256];
+    for (value, linear) in srgb_to_linear.iter_mut().enumerate() {
+        let value = value as f32 / 255.0;
+        *linear = if value <= 0.04045 {
+            value / 12.92
+        } else {
+            ((value + 0.055) / 1.055).powf(2.4)
+        };
+    }
+
+    let (width, height) = equirect.dimensions();
+    let mut coefficients = [[0.0; 4]; 9];
+
+    for (x, y, pixel) in equirect.enumerate_pixels() {
+        let longitude = ((x as f32 + 0.5) / width as f32 - 0.5) * 2.0 * PI;
+        let latitude = (0.5 - (y as f32 + 0.5) / height as f32) * PI;
+
+        // The image has `y` pointing up, so we flip it to get world space.
+        let direction = [
+            latitude.cos() * longitude.sin(),
+            -latitude.sin(),
+            latitude.cos() * longitude.cos(),
+        ];
+
+        // Pixels near the poles cover a smaller part of the sphere.
+        let solid_angle = latitude.cos() * (2.0 * PI / width as f32) * (PI / height as f32);
+
+        for (coefficient, basis) in coefficients.iter_mut().zip(&sh_basis(direction)) {
+            for channel in 0..3 {
+                coefficient[channel] +=
+                    srgb_to_linear[pixel[channel] as usize] * basis * solid_angle;
+            }
+        }
+    }
+
+    // Convolving with the cosine lobe scales each band by a constant. The
+    // band 0 constant is pi, which cancels out the division by pi.
+    let band_scales = [1.0, 2.0 / 3.0, 1.0 / 4.0];
+    let bands = [0, 1, 1, 1, 2, 2, 2, 2, 2];
+
+    for (coefficient, &band) in coefficients.iter_mut().zip(&bands) {
+        for value in coefficient.iter_mut() {
+            *value *= band_scales[band];
+        }
+    }
+
+    coefficients
+}
+
+/// The first nine real spherical harmonic basis functions, evaluated for a
+/// unit `direction`.
+fn sh_basis(direction: [f32; 3]) -> [f32; 9] {
+    let [x, y, z] = direction;
+
+    [
+        0.282_095,
+        0.488_603 * y,
+        0.488_603 * z,
+        0.488_603 * x,
+        1.092_548 * x * y,
+        1.092_548 * y * z,
+        0.315_392 * (3.0 * z * z - 1.0),
+        1.092_548 * x * z,
+        0.546_274 * (x * x - y * y),
+    ]
+}
@@ -0,0 +1,43 @@
+//! Shared code for the teapot demo.
+//!
+//! The numbered tutorial parts keep everything inside a single `main`
+//! function so they can be read from top to bottom. Once we start juggling
+//! textures, descriptor sets, and multiple pipelines, that gets unwieldy, so
+//! the reusable pieces live here instead.
+
+// Nearly every function here is `unsafe` for the same reason as the gfx-hal
+// calls inside it: the caller must pass in valid objects from the same
+// device, and not destroy anything the GPU is still using.
+#![allow(clippy::missing_safety_doc)]
+
+// The derive macros refer to everything through `::gfx_hal_tutorials`, so
+// this crate needs to be able to refer to itself by that name to use them.
+extern crate self as gfx_hal_tutorials;
+
+// Re-exported for the derive macros, so that their output doesn't depend on
+// which version of gfx-hal the calling crate uses.
+#[doc(hidden)]
+pub use gfx_hal;
+
+pub mod buffer;
+pub mod camera;
+pub mod command;
+pub mod cube;
+pub mod file_watcher;
+pub mod ibl;
+pub mod light;
+pub mod material;
+pub mod mesh;
+pub mod pipeline_cache;
+pub mod pipeline_config;
+pub mod reflect;
+pub mod ring;
+pub mod sampler;
+pub mod shader_compiler;
+pub mod shader_data;
+pub mod shader_watcher;
+pub mod shaders;
+pub mod specialization;
+pub mod spirv;
+pub mod texture;
+pub mod vertex_layout;
@@ -0,0 +1,152 @@
+//! Point and spot lights.
+//!
+//! A uniform buffer has to have a fixed size, so it can only hold a fixed
+//! number of lights. Instead, we keep the lights in a storage buffer, which
+//! the shader can treat as an array of any length. We tell the shader how
+//! many lights there are with a push constant.
+
+use gfx_hal::{device::Device, memory::Properties, memory::Segment, Backend};
+
+use crate::buffer::make_buffer;
+use crate::shader_data::ShaderData;
+
+/// A point or spot light, laid out like the `Light` struct in
+/// `lighting.glsl`.
+///
+/// Each `vec3` is followed by a `float` to fill out its 16 bytes, which also
+/// keeps the whole struct a multiple of 16 bytes, as `std430` arrays of
+/// structs require.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, ShaderData)]
+#[shader_data(std430)]
+pub struct Light {
+    pub position: [f32; 3],
+
+    /// The distance at which the light fades out completely.
+    pub range: f32,
+
+    /// The light's color, multiplied by its brightness.
+    pub color: [f32; 3],
+
+    /// The cosine of the angle from the spot light's direction at which it
+    /// starts to fade out.
+    pub inner_cone_cos: f32,
+
+    /// The direction a spot light points in. Unused for point lights.
+    pub direction: [f32; 3],
+
+    /// The cosine of the angle from the spot light's direction at which it
+    /// has faded out completely.
+    pub outer_cone_cos: f32,
+}
+
+impl Light {
+    /// A light that shines equally in every direction.
+    pub fn point(position: [f32; 3], color: [f32; 3], range: f32) -> Self {
+        // A cone whose edges are both directly behind the light covers every
+        // direction, so a point light is just a spot light that's wide open.
+        Light {
+            position,
+            range,
+            color,
+            inner_cone_cos: -1.0,
+            direction: [0.0, 0.0, 0.0],
+            outer_cone_cos: -1.0,
+        }
+    }
+
+    /// A light that shines in a cone around `direction`, fading out between
+    /// `inner_angle` and `outer_angle` (in radians) from its center.
+    pub fn spot(
+        position: [f32; 3],
+        direction: [f32; 3],
+        color: [f32; 3],
+        range: f32,
+        inner_angle: f32,
+        outer_angle: f32,
+    ) -> Self {
+        let [x, y, z] = direction;
+        let length = (x * x + y * y + z * z).sqrt();
+
+        Light {
+            position,
+            range,
+            color,
+            inner_cone_cos: inner_angle.cos(),
+            direction: [x / length, y / length, z / length],
+            outer_cone_cos: outer_angle.cos(),
+        }
+    }
+}
+
+/// A persistently-mapped storage buffer with room for a fixed number of
+/// lights.
+///
+/// Unlike the `UniformRing`, there's only one copy of the lights, so they
+/// must only be written after waiting for the last frame that used them.
+pub struct LightBuffer<B: Backend> {
+    memory: B::Memory,
+    buffer: B::Buffer,
+    mapped_memory: *mut Light,
+    capacity: usize,
+}
+
+impl<B: Backend> LightBuffer<B> {
+    /// Create a buffer with room for `capacity` lights.
+    pub unsafe fn new(
+        device: &B::Device,
+        physical_device: &B::PhysicalDevice,
+        capacity: usize,
+    ) -> Self {
+        use gfx_hal::buffer::Usage;
+
+        let (memory, buffer) = make_buffer::<B>(
+            device,
+            physical_device,
+            capacity * std::mem::size_of::<Light>(),
+            Usage::STORAGE,
+            Properties::CPU_VISIBLE,
+        );
+
+        // Like the uniform ring, we map the memory once and leave it mapped.
+        let mapped_memory = device
+            .map_memory(&memory, Segment::ALL)
+            .expect("Failed to map memory") as *mut Light;
+
+        LightBuffer {
+            memory,
+            buffer,
+            mapped_memory,
+            capacity,
+        }
+    }
+
+    /// The buffer to point the storage buffer descriptor at.
+    pub fn buffer(&self) -> &B::Buffer {
+        &self.buffer
+    }
+
+    /// Copy `lights` into the buffer and make them visible to the GPU.
+    /// Returns how many were copied, for the shader's light count.
+    ///
+    /// If there are more lights than there's room for, the extra ones are
+    /// left out.
+    pub unsafe fn write(&mut self, device: &B::Device, lights: &[Light]) -> u32 {
+        let count = lights.len().min(self.capacity);
+
+        std::ptr::copy_nonoverlapping(lights.as_ptr(), self.mapped_memory, count);
+
+        device
+            .flush_mapped_memory_ranges(vec![(&self.memory, Segment::ALL)])
+            .expect("Out of memory");
+
+        count as u32
+    }
+
+    /// Unmap and destroy the buffer, and free its memory.
+    pub unsafe fn destroy(self, device: &B::Device) {
+        device.unmap_memory(&self.memory);
+        device.destroy_buffer(self.buffer);
+        device.free_memory(self.memory);
+    }
+}
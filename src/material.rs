@@ -0,0 +1,104 @@
+//! Material settings.
+//!
+//! The teapot is shaded with the metallic-roughness model, which describes a
+//! surface with just a base color and two numbers: how metallic it is, and
+//! how rough it is. The materials to preview are listed in a RON file,
+//! `assets/materials.ron`, so new ones can be tried out without recompiling.
+
+use serde::{Deserialize, Serialize};
+
+/// A serializable description of a material.
+///
+/// Any fields missing from a config file take a default value: a white,
+/// non-metallic surface of medium roughness, with no textures. Only the name
+/// is required.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MaterialConfig {
+    pub name: String,
+
+    /// The color of the surface, in linear RGBA. For dielectrics, this is
+    /// the diffuse color. For metals, it's the color of reflections.
+    #[serde(default = "default_base_color")]
+    pub base_color: [f32; 4],
+
+    /// `0.0` for dielectrics (like plastic or porcelain) and `1.0` for
+    /// metals. Values in between are mostly useful for blending between the
+    /// two, as in a texture.
+    #[serde(default)]
+    pub metallic: f32,
+
+    /// `0.0` for a perfect mirror, up to `1.0` for a completely matte
+    /// surface.
+    #[serde(default = "default_roughness")]
+    pub roughness: f32,
+
+    /// Whether to multiply the base color by the teapot's texture.
+    #[serde(default)]
+    pub base_color_texture: bool,
+
+    /// Whether to multiply the metallic and roughness by the teapot's
+    /// metallic-roughness texture. As in glTF, roughness is read from the
+    /// texture's green channel, and metallic from its blue channel.
+    #[serde(default)]
+    pub metallic_roughness_texture: bool,
+}
+
+fn default_base_color() -> [f32; 4] {
+    [1.0, 1.0, 1.0, 1.0]
+}
+
+fn default_roughness() -> f32 {
+    0.5
+}
+
+/// Parse a list of material configs from a RON string.
+pub fn materials_from_ron(source: &str) -> ron::Result<Vec<MaterialConfig>> {
+    ron::de::from_str(source)
+}
+
+impl MaterialConfig {
+    /// Bit flags for the textures this material uses, matching the
+    /// `TEXTURE_*` constants in the teapot's fragment shader.
+    pub fn texture_flags(&self) -> u32 {
+        let mut flags = 0;
+
+        if self.base_color_texture {
+            flags |= 1;
+        }
+
+        if self.metallic_roughness_texture {
+            flags |= 2;
+        }
+
+        flags
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_fields_use_defaults() {
+        let materials = materials_from_ron(r#"[(name: "Plain")]"#).unwrap();
+
+        assert_eq!(
+            materials,
+            vec![MaterialConfig {
+                name: "Plain".to_owned(),
+                base_color: [1.0, 1.0, 1.0, 1.0],
+                metallic: 0.0,
+                roughness: 0.5,
+                base_color_texture: false,
+                metallic_roughness_texture: false,
+            }]
+        );
+        assert_eq!(materials[0].texture_flags(), 0);
+    }
+
+    #[test]
+    fn shipped_materials_parse() {
+        let materials = materials_from_ron(include_str!("../assets/materials.ron")).unwrap();
+        assert!(!materials.is_empty());
+    }
+}
@@ -0,0 +1,118 @@
+//! Mesh loading.
+
+use crate::vertex_layout::VertexLayout;
+
+/// A single vertex with a position, normal, and texture coordinates.
+///
+/// The `repr(C)` attribute is required to ensure that the memory layout is
+/// what we expect. Without it, no specific layout is guaranteed. Deriving
+/// `VertexLayout` describes the fields as vertex attributes 0, 1, and 2.
+#[derive(Debug, Clone, Copy, VertexLayout)]
+#[repr(C)]
+pub struct Vertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub uv: [f32; 2],
+}
+
+/// The vertex format of the serialized mesh files in the `assets` directory.
+#[derive(serde::Deserialize)]
+struct MeshVertex {
+    position: [f32; 3],
+    normal: [f32; 3],
+}
+
+/// Deserialize a mesh like `teapot_mesh.bin` and generate texture
+/// coordinates for it.
+///
+/// The mesh files don't contain texture coordinates, so we project each
+/// vertex onto a cylinder around the Y axis instead: `u` goes once around
+/// the mesh, and `v` goes from the top of the mesh to the bottom.
+pub fn deserialize(binary_mesh_data: &[u8]) -> Vec<Vertex> {
+    use std::f32::consts::PI;
+
+    let mesh: Vec<MeshVertex> =
+        bincode::deserialize(binary_mesh_data).expect("Failed to deserialize mesh");
+
+    let (min_y, max_y) = mesh.iter().fold((f32::MAX, f32::MIN), |(min, max), v| {
+        (min.min(v.position[1]), max.max(v.position[1]))
+    });
+    let height = (max_y - min_y).max(f32::EPSILON);
+
+    mesh.into_iter()
+        .map(|MeshVertex { position, normal }| {
+            let [x, y, z] = position;
+            let u = 0.5 + z.atan2(x) / (2.0 * PI);
+            let v = (max_y - y) / height;
+
+            Vertex {
+                position,
+                normal,
+                uv: [u, v],
+            }
+        })
+        .collect()
+}
+
+/// A vertex of a line, with nothing but a position.
+#[derive(Debug, Clone, Copy, VertexLayout)]
+#[repr(C)]
+pub struct LineVertex {
+    pub position: [f32; 3],
+}
+
+/// Make a line sticking out of the middle of each triangle in a mesh, along
+/// its face normal, for drawing with a `LineList` topology.
+///
+/// The direction of the face normal depends on the winding order of the
+/// triangle, so we flip it if necessary to point the same way as the vertex
+/// normals.
+pub fn face_normal_lines(vertices: &[Vertex], length: f32) -> Vec<LineVertex> {
+    fn add(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+        [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+    }
+
+    fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+        [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+    }
+
+    fn scale(a: [f32; 3], s: f32) -> [f32; 3] {
+        [a[0] * s, a[1] * s, a[2] * s]
+    }
+
+    fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+        a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+    }
+
+    fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+        [
+            a[1] * b[2] - a[2] * b[1],
+            a[2] * b[0] - a[0] * b[2],
+            a[0] * b[1] - a[1] * b[0],
+        ]
+    }
+
+    vertices
+        .chunks_exact(3)
+        .flat_map(|triangle| {
+            let (a, b, c) = (triangle[0], triangle[1], triangle[2]);
+            let normal = cross(sub(b.position, a.position), sub(c.position, a.position));
+            let vertex_normals = add(add(a.normal, b.normal), c.normal);
+
+            let normal_length = dot(normal, normal).sqrt().max(f32::EPSILON);
+            let sign = if dot(normal, vertex_normals) < 0.0 {
+                -1.0
+            } else {
+                1.0
+            };
+
+            let center = scale(add(add(a.position, b.position), c.position), 1.0 / 3.0);
+            let tip = add(center, scale(normal, sign * length / normal_length));
+
+            vec![
+                LineVertex { position: center },
+                LineVertex { position: tip },
+            ]
+        })
+        .collect()
+}
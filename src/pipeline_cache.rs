@@ -0,0 +1,170 @@
+//! Keeping compiled pipelines between runs.
+//!
+//! Creating a pipeline means compiling its shaders for the GPU, which can be
+//! slow. A pipeline cache lets the driver reuse earlier work, and by saving
+//! its contents to disk on exit, we can reuse work from earlier runs too.
+//!
+//! The cache data only makes sense to the driver and GPU that produced it.
+//! Drivers are supposed to reject data they don't recognize, but not all of
+//! them do so gracefully, so we write our own header in front of it. If the
+//! header doesn't match the current adapter and driver, the file is ignored
+//! and we start with an empty cache.
+
+use std::path::PathBuf;
+
+use gfx_hal::{adapter::AdapterInfo, device::Device, Backend};
+use serde::{Deserialize, Serialize};
+
+/// Identifies the version of our file format.
+const MAGIC: [u8; 8] = *b"GHTPIPE1";
+
+/// Describes the adapter and driver that a cache file was written for.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct CacheHeader {
+    magic: [u8; 8],
+    backend: String,
+    adapter_name: String,
+    vendor: u64,
+    device: u64,
+
+    /// The data of an empty pipeline cache. On Vulkan, this is the driver's
+    /// own header, which includes a UUID that changes with the driver
+    /// version, so it catches driver updates as well.
+    driver_fingerprint: Vec<u8>,
+}
+
+/// The contents of a cache file.
+#[derive(Serialize, Deserialize)]
+struct CacheFile {
+    header: CacheHeader,
+
+    /// A checksum of the data, to catch files that were only partly written.
+    checksum: u64,
+    data: Vec<u8>,
+}
+
+/// A pipeline cache file in the user's cache directory, for one adapter.
+pub struct PipelineCacheFile {
+    path: Option<PathBuf>,
+    header: CacheHeader,
+}
+
+impl PipelineCacheFile {
+    /// The pipeline cache file for the app called `app_name`, running on the
+    /// adapter described by `adapter_info`.
+    ///
+    /// If there's no cache directory on this system, the cache still works,
+    /// but it's never loaded or saved.
+    pub unsafe fn new<B: Backend>(
+        device: &B::Device,
+        adapter_info: &AdapterInfo,
+        app_name: &str,
+    ) -> Self {
+        let empty_cache = device.create_pipeline_cache(None).expect("Out of memory");
+        let driver_fingerprint = device
+            .get_pipeline_cache_data(&empty_cache)
+            .expect("Out of memory");
+        device.destroy_pipeline_cache(empty_cache);
+
+        let file_name: String = app_name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+            .collect();
+
+        PipelineCacheFile {
+            path: dirs::cache_dir().map(|dir| {
+                dir.join("gfx-hal-tutorials")
+                    .join(format!("{}-pipelines.bin", file_name))
+            }),
+            header: CacheHeader {
+                magic: MAGIC,
+                backend: std::any::type_name::<B>().to_owned(),
+                adapter_name: adapter_info.name.clone(),
+                vendor: adapter_info.vendor as u64,
+                device: adapter_info.device as u64,
+                driver_fingerprint,
+            },
+        }
+    }
+
+    /// Create a pipeline cache, filled with the contents of the file if
+    /// there is one, and it was written for the same adapter and driver.
+    pub unsafe fn load<B: Backend>(&self, device: &B::Device) -> B::PipelineCache {
+        let data = self.read().unwrap_or_else(|message| {
+            eprintln!("Starting with an empty pipeline cache: {}", message);
+            None
+        });
+
+        device
+            .create_pipeline_cache(data.as_deref())
+            .expect("Out of memory")
+    }
+
+    /// Read the data from the file. Returns `None` if there's no file yet,
+    /// and an error if there's a file we can't use.
+    fn read(&self) -> Result<Option<Vec<u8>>, String> {
+        let path = match &self.path {
+            Some(path) if path.exists() => path,
+            _ => return Ok(None),
+        };
+
+        let bytes = std::fs::read(path)
+            .map_err(|error| format!("Failed to read {}: {}", path.display(), error))?;
+
+        let file: CacheFile = bincode::deserialize(&bytes)
+            .map_err(|_| format!("{} is not a pipeline cache", path.display()))?;
+
+        if file.header != self.header {
+            return Err(format!(
+                "{} was written for a different adapter or driver",
+                path.display()
+            ));
+        }
+
+        if file.checksum != checksum(&file.data) {
+            return Err(format!("{} is corrupted", path.display()));
+        }
+
+        Ok(Some(file.data))
+    }
+
+    /// Write the contents of `cache` to the file.
+    ///
+    /// Failing to save the cache only makes the next run a little slower,
+    /// so errors are printed rather than panicking.
+    pub unsafe fn save<B: Backend>(&self, device: &B::Device, cache: &B::PipelineCache) {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return,
+        };
+
+        let data = device
+            .get_pipeline_cache_data(cache)
+            .expect("Out of memory");
+
+        let file = CacheFile {
+            header: self.header.clone(),
+            checksum: checksum(&data),
+            data,
+        };
+        let bytes = bincode::serialize(&file).expect("Failed to serialize pipeline cache");
+
+        // Writing to a temporary file and then renaming it means that the
+        // file is never left half-written, even if we're interrupted.
+        let temp_path = path.with_extension("tmp");
+        let result = std::fs::create_dir_all(path.parent().unwrap())
+            .and_then(|()| std::fs::write(&temp_path, bytes))
+            .and_then(|()| std::fs::rename(&temp_path, path));
+
+        if let Err(error) = result {
+            eprintln!("Failed to save {}: {}", path.display(), error);
+        }
+    }
+}
+
+/// A 64-bit FNV-1a hash, which is plenty to spot a damaged file.
+fn checksum(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
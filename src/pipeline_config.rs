@@ -0,0 +1,472 @@
+//! Pipeline settings.
+//!
+//! Like samplers, the fixed-function state of each pipeline (which shaders
+//! it uses, how triangles are rasterized, depth testing, and blending) is
+//! described in a RON file, `assets/pipelines.ron`, rather than in code.
+//! That way it can be tweaked without recompiling, and even while the
+//! program is running.
+//!
+//! The parts of a pipeline that have to agree with the Rust code, like the
+//! vertex layout and push constants, are still set up in code.
+//!
+//! A config can also give macros to define when compiling its shaders, and
+//! the names of their entry points. The build script compiles a variant of
+//! the shaders for each set of defines, so changing either of these does
+//! need a rebuild, unless the shaders are being watched.
+
+use std::collections::BTreeMap;
+
+use gfx_hal::pso::{
+    BlendState, ColorBlendDesc, ColorMask, Comparison, DepthBias, DepthStencilDesc, DepthTest,
+    Face, FrontFace, InputAssemblerDesc, PolygonMode, Primitive, Rasterizer, State,
+};
+use gfx_hal::Features;
+use serde::{Deserialize, Serialize};
+
+use crate::shader_compiler::{
+    constant_name, default_entry_point, pipeline_variants, variant_suffix, ShaderSettings,
+    ShaderVariant,
+};
+
+/// How vertices are assembled into primitives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Topology {
+    PointList,
+    LineList,
+    LineStrip,
+    TriangleList,
+    TriangleStrip,
+}
+
+impl From<Topology> for Primitive {
+    fn from(topology: Topology) -> Primitive {
+        match topology {
+            Topology::PointList => Primitive::PointList,
+            Topology::LineList => Primitive::LineList,
+            Topology::LineStrip => Primitive::LineStrip,
+            Topology::TriangleList => Primitive::TriangleList,
+            Topology::TriangleStrip => Primitive::TriangleStrip,
+        }
+    }
+}
+
+/// How triangles are filled in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FillMode {
+    /// Fill the whole triangle.
+    Fill,
+
+    /// Only draw the edges. If the device doesn't support this, it falls
+    /// back to `Fill`.
+    Line,
+
+    /// Only draw the corners. If the device doesn't support this, it falls
+    /// back to `Fill`.
+    Point,
+}
+
+/// Which sides of triangles are culled (not drawn).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CullMode {
+    None,
+    Front,
+    Back,
+    Both,
+}
+
+impl From<CullMode> for Face {
+    fn from(mode: CullMode) -> Face {
+        match mode {
+            CullMode::None => Face::NONE,
+            CullMode::Front => Face::FRONT,
+            CullMode::Back => Face::BACK,
+            CullMode::Both => Face::all(),
+        }
+    }
+}
+
+/// Which winding order counts as the front of a triangle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Winding {
+    Clockwise,
+    CounterClockwise,
+}
+
+impl From<Winding> for FrontFace {
+    fn from(winding: Winding) -> FrontFace {
+        match winding {
+            Winding::Clockwise => FrontFace::Clockwise,
+            Winding::CounterClockwise => FrontFace::CounterClockwise,
+        }
+    }
+}
+
+/// Rasterizer settings.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RasterizerConfig {
+    pub fill: FillMode,
+    pub cull: CullMode,
+    pub front_face: Winding,
+
+    /// The width of lines in pixels. Widths other than `1.0` are ignored if
+    /// the device doesn't support them.
+    pub line_width: f32,
+
+    /// An offset added to the depth of each fragment, or `None` for no
+    /// offset. This is mostly useful for shadow maps.
+    pub depth_bias: Option<DepthBiasConfig>,
+}
+
+impl Default for RasterizerConfig {
+    fn default() -> Self {
+        RasterizerConfig {
+            fill: FillMode::Fill,
+            cull: CullMode::Back,
+            front_face: Winding::CounterClockwise,
+            line_width: 1.0,
+            depth_bias: None,
+        }
+    }
+}
+
+/// An offset to the depth of each fragment.
+///
+/// A shadow map only stores one depth per texel, so a surface tested
+/// against its own shadow map is in shadow about half the time, giving
+/// "shadow acne". Pushing the depths in the shadow map a little further
+/// away avoids that.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DepthBiasConfig {
+    /// A constant offset, in units of the smallest representable change in
+    /// depth.
+    pub constant: f32,
+
+    /// An offset which grows with the slope of the triangle, for surfaces
+    /// that are nearly edge-on to the view.
+    pub slope: f32,
+}
+
+/// A comparison used for depth testing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CompareOp {
+    Never,
+    Less,
+    Equal,
+    LessEqual,
+    Greater,
+    NotEqual,
+    GreaterEqual,
+    Always,
+}
+
+impl From<CompareOp> for Comparison {
+    fn from(op: CompareOp) -> Comparison {
+        match op {
+            CompareOp::Never => Comparison::Never,
+            CompareOp::Less => Comparison::Less,
+            CompareOp::Equal => Comparison::Equal,
+            CompareOp::LessEqual => Comparison::LessEqual,
+            CompareOp::Greater => Comparison::Greater,
+            CompareOp::NotEqual => Comparison::NotEqual,
+            CompareOp::GreaterEqual => Comparison::GreaterEqual,
+            CompareOp::Always => Comparison::Always,
+        }
+    }
+}
+
+/// Depth test settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DepthConfig {
+    /// A fragment is drawn if this comparison between its depth and the
+    /// depth already in the depth buffer passes.
+    pub compare: CompareOp,
+
+    /// Whether to write the depth of fragments that pass.
+    pub write: bool,
+}
+
+/// How a fragment's color is combined with the color already there.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BlendMode {
+    /// Overwrite the existing color.
+    Replace,
+
+    /// Blend using the fragment's alpha.
+    Alpha,
+
+    /// Blend using the fragment's alpha, where the fragment's color has
+    /// already been multiplied by it.
+    PremultipliedAlpha,
+
+    /// Add the colors together.
+    Add,
+
+    /// Multiply the colors together.
+    Multiply,
+}
+
+impl From<BlendMode> for BlendState {
+    fn from(mode: BlendMode) -> BlendState {
+        match mode {
+            BlendMode::Replace => BlendState::REPLACE,
+            BlendMode::Alpha => BlendState::ALPHA,
+            BlendMode::PremultipliedAlpha => BlendState::PREMULTIPLIED_ALPHA,
+            BlendMode::Add => BlendState::ADD,
+            BlendMode::Multiply => BlendState::MULTIPLY,
+        }
+    }
+}
+
+/// A serializable description of a pipeline's shaders and fixed-function
+/// state.
+///
+/// Any fields missing from a config file take their value from the
+/// `Default` implementation, except for the shaders, which are required.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PipelineConfig {
+    /// The file names of the shaders in `src/bin/shaders`, like
+    /// `"teapot.vert"`.
+    pub vertex_shader: String,
+    pub fragment_shader: String,
+
+    /// The names of the shaders' entry points. These default to `main`, but
+    /// HLSL shaders often use something else, like `vs_main`.
+    #[serde(default = "default_entry_point")]
+    pub vertex_entry_point: String,
+    #[serde(default = "default_entry_point")]
+    pub fragment_entry_point: String,
+
+    #[serde(default = "default_topology")]
+    pub topology: Topology,
+
+    #[serde(default)]
+    pub rasterizer: RasterizerConfig,
+
+    /// Depth test settings, or `None` to disable depth testing.
+    #[serde(default)]
+    pub depth: Option<DepthConfig>,
+
+    /// How to blend into the color attachment, or `None` to disable
+    /// blending.
+    #[serde(default)]
+    pub blend: Option<BlendMode>,
+
+    /// Macros to define when compiling the shaders, like `{"UPSAMPLE": "1"}`.
+    /// Pipelines with the same shaders and defines share a variant.
+    #[serde(default)]
+    pub defines: BTreeMap<String, String>,
+}
+
+fn default_topology() -> Topology {
+    Topology::TriangleList
+}
+
+/// Every pipeline's config, by name.
+pub type PipelineConfigs = BTreeMap<String, PipelineConfig>;
+
+/// Parse a set of named pipeline configs from a RON string.
+pub fn configs_from_ron(source: &str) -> ron::Result<PipelineConfigs> {
+    ron::de::from_str(source)
+}
+
+impl PipelineConfig {
+    /// The optional device features that a config can make use of. These
+    /// should be requested when opening the device, if they're supported.
+    pub fn wanted_features() -> Features {
+        Features::NON_FILL_POLYGON_MODE | Features::LINE_WIDTH
+    }
+
+    /// The names of the vertex and fragment shaders' constants in `shaders`,
+    /// including the variant's suffix if there are any defines. For example,
+    /// `("FULLSCREEN_VERT", "BLOOM_RESAMPLE_FRAG_UPSAMPLE")`.
+    pub fn shader_names(&self) -> (String, String) {
+        let suffix = variant_suffix(&self.defines);
+
+        (
+            constant_name(&self.vertex_shader, &suffix),
+            constant_name(&self.fragment_shader, &suffix),
+        )
+    }
+
+    /// The shader variants this config needs compiled, if it has defines.
+    pub fn shader_variants(&self) -> Vec<ShaderVariant> {
+        pipeline_variants(&self.vertex_shader, &self.fragment_shader, &self.defines)
+    }
+
+    /// The settings for compiling the shaders used by some configs. This
+    /// fails if two configs give the same shader different entry points.
+    pub fn shader_settings<'a>(
+        configs: impl IntoIterator<Item = &'a PipelineConfig>,
+    ) -> Result<ShaderSettings, String> {
+        let mut settings = ShaderSettings::default();
+        for config in configs {
+            let shaders = [
+                (
+                    config.vertex_shader.as_str(),
+                    config.vertex_entry_point.as_str(),
+                ),
+                (
+                    config.fragment_shader.as_str(),
+                    config.fragment_entry_point.as_str(),
+                ),
+            ];
+            settings.add_pipeline(shaders, &config.defines)?;
+        }
+
+        Ok(settings)
+    }
+
+    pub fn input_assembler(&self) -> InputAssemblerDesc {
+        InputAssemblerDesc::new(self.topology.into())
+    }
+
+    /// Create a `Rasterizer` from this config.
+    ///
+    /// The `features` should be the ones the device was opened with. Any
+    /// settings which need a feature that isn't enabled are replaced with
+    /// the closest setting that is supported.
+    pub fn rasterizer(&self, features: Features) -> Rasterizer {
+        let polygon_mode = match self.rasterizer.fill {
+            _ if !features.contains(Features::NON_FILL_POLYGON_MODE) => PolygonMode::Fill,
+            FillMode::Fill => PolygonMode::Fill,
+            FillMode::Line => PolygonMode::Line,
+            FillMode::Point => PolygonMode::Point,
+        };
+
+        let line_width = if features.contains(Features::LINE_WIDTH) {
+            self.rasterizer.line_width
+        } else {
+            1.0
+        };
+
+        Rasterizer {
+            polygon_mode,
+            cull_face: self.rasterizer.cull.into(),
+            front_face: self.rasterizer.front_face.into(),
+            line_width: State::Static(line_width),
+            depth_bias: self.rasterizer.depth_bias.map(|bias| {
+                State::Static(DepthBias {
+                    const_factor: bias.constant,
+                    clamp: 0.0,
+                    slope_factor: bias.slope,
+                })
+            }),
+            ..Rasterizer::FILL
+        }
+    }
+
+    pub fn depth_stencil(&self) -> DepthStencilDesc {
+        DepthStencilDesc {
+            depth: self.depth.map(|depth| DepthTest {
+                fun: depth.compare.into(),
+                write: depth.write,
+            }),
+            depth_bounds: false,
+            stencil: None,
+        }
+    }
+
+    pub fn color_blend(&self) -> ColorBlendDesc {
+        ColorBlendDesc {
+            mask: ColorMask::ALL,
+            blend: self.blend.map(BlendState::from),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_fields_use_defaults() {
+        let configs = configs_from_ron(
+            r#"{
+                "plain": (
+                    vertex_shader: "plain.vert",
+                    fragment_shader: "plain.frag",
+                ),
+                "lines": (
+                    vertex_shader: "lines.vert",
+                    fragment_shader: "lines.frag",
+                    topology: LineList,
+                    blend: Some(Add),
+                ),
+            }"#,
+        )
+        .unwrap();
+
+        let plain = &configs["plain"];
+        assert_eq!(plain.vertex_shader, "plain.vert");
+        assert_eq!(plain.vertex_entry_point, "main");
+        assert_eq!(plain.fragment_entry_point, "main");
+        assert_eq!(plain.topology, Topology::TriangleList);
+        assert_eq!(plain.rasterizer, RasterizerConfig::default());
+        assert_eq!(plain.depth, None);
+        assert_eq!(plain.blend, None);
+
+        let lines = &configs["lines"];
+        assert_eq!(lines.topology, Topology::LineList);
+        assert_eq!(lines.blend, Some(BlendMode::Add));
+    }
+
+    #[test]
+    fn shader_names_include_the_variant() {
+        let configs = configs_from_ron(
+            r#"{
+                "down": (
+                    vertex_shader: "fullscreen.vert",
+                    fragment_shader: "bloom_resample.frag",
+                ),
+                "up": (
+                    vertex_shader: "fullscreen.vert",
+                    fragment_shader: "bloom_resample.frag",
+                    defines: { "UPSAMPLE": "1" },
+                ),
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            configs["down"].shader_names(),
+            (
+                "FULLSCREEN_VERT".to_owned(),
+                "BLOOM_RESAMPLE_FRAG".to_owned()
+            )
+        );
+        assert_eq!(
+            configs["up"].shader_names(),
+            (
+                "FULLSCREEN_VERT_UPSAMPLE".to_owned(),
+                "BLOOM_RESAMPLE_FRAG_UPSAMPLE".to_owned()
+            )
+        );
+        assert!(configs["down"].shader_variants().is_empty());
+        assert_eq!(configs["up"].shader_variants().len(), 2);
+    }
+
+    #[test]
+    fn shaders_are_required() {
+        let result = configs_from_ron(r#"{ "broken": (vertex_shader: "broken.vert") }"#);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn shipped_configs_parse() {
+        let configs = configs_from_ron(include_str!("../assets/pipelines.ron")).unwrap();
+        assert!(configs.contains_key("teapot"));
+
+        let shader_dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("src/bin/shaders");
+        for (name, config) in &configs {
+            for shader in &[&config.vertex_shader, &config.fragment_shader] {
+                assert!(
+                    shader_dir.join(shader).is_file(),
+                    "`{}` uses missing shader `{}`",
+                    name,
+                    shader
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,1355 @@
+//! Checking Rust types against shaders, using SPIR-V reflection.
+//!
+//! The layout of a push constant block is written twice: once as a `repr(C)`
+//! struct in Rust, and once as a block in GLSL. If the two drift apart, the
+//! shader silently reads garbage. Instead, we reflect the compiled SPIR-V and
+//! compare it to the Rust struct's `ShaderData::block_layout` when creating
+//! the pipeline.
+//!
+//! The same goes for pipeline layouts: the shaders already say which
+//! descriptors and push constants they use, so rather than describing them
+//! again by hand, we build the layout from the shaders with
+//! `PipelineLayoutDesc::from_stages`.
+//!
+//! Specialization constants are only identified by number, so we also check
+//! that the values given for them match what the shader declares.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::ops::Range;
+
+use gfx_hal::{
+    format::Format,
+    pso::{
+        AttributeDesc, BufferDescriptorFormat, BufferDescriptorType, DescriptorArrayIndex,
+        DescriptorBinding, DescriptorSetLayoutBinding, DescriptorType, ImageDescriptorType,
+        Location, ShaderStageFlags,
+    },
+};
+
+use crate::specialization::{SpecConstantType, SpecConstants};
+use crate::spirv::{decoration, dim, storage_class, Module, Type};
+
+/// The layout of a single member of a block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockMember {
+    pub name: String,
+    pub offset: u32,
+    pub size: u32,
+}
+
+/// The layout of a struct or shader block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockLayout {
+    pub name: String,
+    pub size: u32,
+    pub members: Vec<BlockMember>,
+}
+
+/// A push constant range in a pipeline layout, along with the layout of the
+/// Rust struct that fills it.
+#[derive(Debug, Clone)]
+pub struct PushConstantLayout {
+    pub stages: ShaderStageFlags,
+    pub range: Range<u32>,
+    pub block: BlockLayout,
+}
+
+impl PushConstantLayout {
+    /// The push constant range that a pipeline layout was created with, and
+    /// the layout of the struct that fills it. The range should be the one
+    /// actually passed to `create_pipeline_layout`, so that the struct can be
+    /// checked against it.
+    pub fn new(stages: ShaderStageFlags, range: Range<u32>, block: BlockLayout) -> Self {
+        PushConstantLayout {
+            stages,
+            range,
+            block,
+        }
+    }
+}
+
+/// Find the push constant block used by some compiled SPIR-V, if any.
+pub fn push_constant_block(spirv: &[u32]) -> Result<Option<BlockLayout>, String> {
+    let module = Module::parse(spirv)?;
+
+    // There can only be one push constant block per entry point.
+    let variable = module
+        .variables
+        .iter()
+        .find(|variable| variable.storage_class == storage_class::PUSH_CONSTANT);
+
+    Ok(variable.map(|variable| block_layout_of(&module, module.pointee(variable.pointer_type))))
+}
+
+/// Describe the layout of a struct type in a SPIR-V module.
+fn block_layout_of(module: &Module, struct_id: u32) -> BlockLayout {
+    let members = match module.types.get(&struct_id) {
+        Some(Type::Struct { members }) => members.as_slice(),
+        _ => &[],
+    };
+
+    BlockLayout {
+        name: module.name(struct_id).to_owned(),
+        size: module.size_of(struct_id, None),
+        members: members
+            .iter()
+            .enumerate()
+            .map(|(index, &member_type)| {
+                let index = index as u32;
+                let offset = module.member_decoration(struct_id, index, decoration::OFFSET);
+                let stride = module.member_decoration(struct_id, index, decoration::MATRIX_STRIDE);
+
+                BlockMember {
+                    name: module.member_name(struct_id, index).to_owned(),
+                    offset: offset.unwrap_or(0),
+                    size: module.size_of(member_type, stride),
+                }
+            })
+            .collect(),
+    }
+}
+
+/// Check that the push constant block in a shader stage matches the Rust
+/// layout and range that will be used to fill it.
+///
+/// On failure, returns a description of the problem, including a
+/// member-by-member comparison if the layouts differ.
+pub fn check_push_constants(
+    stage: ShaderStageFlags,
+    spirv: &[u32],
+    layout: Option<&PushConstantLayout>,
+) -> Result<(), String> {
+    let shader_block = match push_constant_block(spirv)? {
+        Some(block) => block,
+        None => return Ok(()),
+    };
+
+    let layout = layout.ok_or_else(|| {
+        format!(
+            "{:?} shader uses push constant block `{}`, but the pipeline has no push constants",
+            stage, shader_block.name
+        )
+    })?;
+
+    if !layout.stages.contains(stage) {
+        return Err(format!(
+            "{:?} shader uses push constant block `{}`, but the push constant range is only \
+             visible to {:?}",
+            stage, shader_block.name, layout.stages
+        ));
+    }
+
+    let range_len = layout.range.end - layout.range.start;
+    let mut problems = vec![];
+
+    if layout.block.size != range_len {
+        problems.push(format!(
+            "`{}` is {} bytes, but the push constant range {:?} is {} bytes",
+            layout.block.name, layout.block.size, layout.range, range_len
+        ));
+    }
+
+    if let Some(member) = shader_block
+        .members
+        .iter()
+        .find(|member| member.offset + member.size > layout.range.end)
+    {
+        problems.push(format!(
+            "shader member `{}` ends at byte {}, past the end of the push constant range {:?}",
+            member.name,
+            member.offset + member.size,
+            layout.range
+        ));
+    }
+
+    // The struct is pushed at the start of the range, so its members'
+    // offsets are relative to that.
+    let rust_block = BlockLayout {
+        members: layout
+            .block
+            .members
+            .iter()
+            .map(|member| BlockMember {
+                offset: layout.range.start + member.offset,
+                ..member.clone()
+            })
+            .collect(),
+        ..layout.block.clone()
+    };
+
+    let members_match = rust_block.members.len() == shader_block.members.len()
+        && rust_block
+            .members
+            .iter()
+            .zip(&shader_block.members)
+            .all(|(rust, shader)| rust.offset == shader.offset && rust.size == shader.size);
+
+    if !members_match {
+        problems.push("member layouts differ".to_owned());
+    }
+
+    if problems.is_empty() {
+        return Ok(());
+    }
+
+    let mut message = format!(
+        "{:?} shader push constant block `{}` doesn't match Rust struct `{}`:\n",
+        stage, shader_block.name, layout.block.name
+    );
+
+    for problem in &problems {
+        writeln!(message, "  - {}", problem).unwrap();
+    }
+
+    if !members_match {
+        message.push_str(&layout_diff(&rust_block, &shader_block));
+    }
+
+    Err(message)
+}
+
+/// Lay out two blocks side by side, marking members that don't match.
+fn layout_diff(rust: &BlockLayout, shader: &BlockLayout) -> String {
+    let describe = |member: Option<&BlockMember>| match member {
+        Some(member) => format!(
+            "{} (offset {}, size {})",
+            member.name, member.offset, member.size
+        ),
+        None => "-".to_owned(),
+    };
+
+    let count = rust.members.len().max(shader.members.len());
+    let mut diff = format!("\n    {:<36} {}\n", "rust", "shader");
+
+    for index in 0..count {
+        let rust_member = rust.members.get(index);
+        let shader_member = shader.members.get(index);
+
+        let matches = match (rust_member, shader_member) {
+            (Some(a), Some(b)) => a.offset == b.offset && a.size == b.size,
+            _ => false,
+        };
+
+        writeln!(
+            diff,
+            "  {} {:<36} {}",
+            if matches { " " } else { "!" },
+            describe(rust_member),
+            describe(shader_member)
+        )
+        .unwrap();
+    }
+
+    diff
+}
+
+/// A descriptor used by a shader stage.
+#[derive(Debug, Clone)]
+pub struct Descriptor {
+    pub name: String,
+    pub set: u32,
+    pub binding: DescriptorBinding,
+    pub ty: DescriptorType,
+    pub count: DescriptorArrayIndex,
+}
+
+/// An input to a vertex shader.
+#[derive(Debug, Clone)]
+pub struct VertexInput {
+    pub name: String,
+    pub location: Location,
+
+    /// The format the shader expects, if it's one we know how to describe.
+    pub format: Option<Format>,
+}
+
+/// The resources that a single shader stage uses.
+#[derive(Debug, Clone)]
+pub struct StageInterface {
+    pub stage: ShaderStageFlags,
+    pub vertex_inputs: Vec<VertexInput>,
+    pub descriptors: Vec<Descriptor>,
+    pub push_constants: Option<BlockLayout>,
+}
+
+impl StageInterface {
+    /// Find the resources used by some compiled SPIR-V.
+    ///
+    /// Vertex inputs are only collected for the vertex stage.
+    pub fn reflect(stage: ShaderStageFlags, spirv: &[u32]) -> Result<Self, String> {
+        let module = Module::parse(spirv)?;
+        let mut interface = StageInterface {
+            stage,
+            vertex_inputs: vec![],
+            descriptors: vec![],
+            push_constants: push_constant_block(spirv)?,
+        };
+
+        for variable in &module.variables {
+            let type_id = module.pointee(variable.pointer_type);
+            let name = module.name(variable.id).to_owned();
+
+            match variable.storage_class {
+                storage_class::INPUT if stage == ShaderStageFlags::VERTEX => {
+                    // Built-ins like `gl_VertexIndex` don't come from a buffer.
+                    if let Some(location) = module.decoration(variable.id, decoration::LOCATION) {
+                        interface.vertex_inputs.push(VertexInput {
+                            name,
+                            location,
+                            format: vertex_format(&module, type_id),
+                        });
+                    }
+                }
+                storage_class::UNIFORM_CONSTANT
+                | storage_class::UNIFORM
+                | storage_class::STORAGE_BUFFER => {
+                    let (element, count) = match module.types.get(&type_id) {
+                        Some(Type::Array { element, length }) => {
+                            (*element, module.constants.get(length).copied().unwrap_or(1))
+                        }
+                        _ => (type_id, 1),
+                    };
+
+                    let ty = descriptor_type(&module, variable.storage_class, element).ok_or_else(
+                        || {
+                            format!(
+                                "{:?} shader uses `{}`, which has an unknown type",
+                                stage, name
+                            )
+                        },
+                    )?;
+
+                    interface.descriptors.push(Descriptor {
+                        name,
+                        set: module
+                            .decoration(variable.id, decoration::DESCRIPTOR_SET)
+                            .unwrap_or(0),
+                        binding: module
+                            .decoration(variable.id, decoration::BINDING)
+                            .unwrap_or(0),
+                        ty,
+                        count: count as DescriptorArrayIndex,
+                    });
+                }
+                _ => (),
+            }
+        }
+
+        interface.vertex_inputs.sort_by_key(|input| input.location);
+
+        Ok(interface)
+    }
+}
+
+/// Work out which kind of descriptor a variable needs.
+fn descriptor_type(module: &Module, storage_class: u32, type_id: u32) -> Option<DescriptorType> {
+    let buffer = |ty| DescriptorType::Buffer {
+        ty,
+        format: BufferDescriptorFormat::Structured {
+            dynamic_offset: false,
+        },
+    };
+
+    // A `readonly` buffer block is usually compiled with each member marked
+    // as non-writable, rather than the block itself.
+    let read_only = module.has_decoration(type_id, decoration::NON_WRITABLE)
+        || match module.types.get(&type_id) {
+            Some(Type::Struct { members }) => (0..members.len() as u32).all(|member| {
+                module.has_member_decoration(type_id, member, decoration::NON_WRITABLE)
+            }),
+            _ => false,
+        };
+
+    match (storage_class, module.types.get(&type_id)?) {
+        (storage_class::UNIFORM, Type::Struct { .. })
+            if module.has_decoration(type_id, decoration::BUFFER_BLOCK) =>
+        {
+            Some(buffer(BufferDescriptorType::Storage { read_only }))
+        }
+        (storage_class::UNIFORM, Type::Struct { .. }) => {
+            Some(buffer(BufferDescriptorType::Uniform))
+        }
+        (storage_class::STORAGE_BUFFER, Type::Struct { .. }) => {
+            Some(buffer(BufferDescriptorType::Storage { read_only }))
+        }
+        (storage_class::UNIFORM_CONSTANT, Type::Sampler) => Some(DescriptorType::Sampler),
+        (storage_class::UNIFORM_CONSTANT, Type::SampledImage { .. }) => {
+            Some(DescriptorType::Image {
+                ty: ImageDescriptorType::Sampled { with_sampler: true },
+            })
+        }
+        (storage_class::UNIFORM_CONSTANT, Type::Image { dim, sampled }) => {
+            // A `sampled` of 1 means the image is read through a sampler,
+            // and 2 means it's a storage image.
+            let sampled = *sampled == 1;
+
+            Some(match *dim {
+                dim::SUBPASS_DATA => DescriptorType::InputAttachment,
+                dim::BUFFER => DescriptorType::Buffer {
+                    ty: if sampled {
+                        BufferDescriptorType::Uniform
+                    } else {
+                        BufferDescriptorType::Storage { read_only: false }
+                    },
+                    format: BufferDescriptorFormat::Texel,
+                },
+                _ if sampled => DescriptorType::Image {
+                    ty: ImageDescriptorType::Sampled {
+                        with_sampler: false,
+                    },
+                },
+                _ => DescriptorType::Image {
+                    ty: ImageDescriptorType::Storage { read_only: false },
+                },
+            })
+        }
+        _ => None,
+    }
+}
+
+/// The vertex attribute format matching a shader input type, if there is
+/// one.
+fn vertex_format(module: &Module, type_id: u32) -> Option<Format> {
+    let (component, count) = match module.types.get(&type_id)? {
+        Type::Vector { component, count } => (*component, *count),
+        _ => (type_id, 1),
+    };
+
+    let format = match (module.types.get(&component)?, count) {
+        (Type::Float { width: 32 }, 1) => Format::R32Sfloat,
+        (Type::Float { width: 32 }, 2) => Format::Rg32Sfloat,
+        (Type::Float { width: 32 }, 3) => Format::Rgb32Sfloat,
+        (Type::Float { width: 32 }, 4) => Format::Rgba32Sfloat,
+        (
+            Type::Int {
+                width: 32,
+                signed: true,
+            },
+            1,
+        ) => Format::R32Sint,
+        (
+            Type::Int {
+                width: 32,
+                signed: true,
+            },
+            2,
+        ) => Format::Rg32Sint,
+        (
+            Type::Int {
+                width: 32,
+                signed: true,
+            },
+            3,
+        ) => Format::Rgb32Sint,
+        (
+            Type::Int {
+                width: 32,
+                signed: true,
+            },
+            4,
+        ) => Format::Rgba32Sint,
+        (
+            Type::Int {
+                width: 32,
+                signed: false,
+            },
+            1,
+        ) => Format::R32Uint,
+        (
+            Type::Int {
+                width: 32,
+                signed: false,
+            },
+            2,
+        ) => Format::Rg32Uint,
+        (
+            Type::Int {
+                width: 32,
+                signed: false,
+            },
+            3,
+        ) => Format::Rgb32Uint,
+        (
+            Type::Int {
+                width: 32,
+                signed: false,
+            },
+            4,
+        ) => Format::Rgba32Uint,
+        _ => return None,
+    };
+
+    Some(format)
+}
+
+/// The number of components in a vertex attribute format, or `None` for
+/// formats that can't feed a 32-bit input. That includes 64-bit formats,
+/// which take up two locations and are only read by `double` inputs.
+fn component_count(format: Format) -> Option<u32> {
+    use gfx_hal::format::SurfaceType::*;
+
+    match format.base_format().0 {
+        R8 | R16 | R32 => Some(1),
+        R8_G8 | R16_G16 | R32_G32 => Some(2),
+        R8_G8_B8 | B8_G8_R8 | R16_G16_B16 | R32_G32_B32 | B10_G11_R11 => Some(3),
+        R8_G8_B8_A8 | B8_G8_R8_A8 | A8_B8_G8_R8 | A2_R10_G10_B10 | A2_B10_G10_R10
+        | R16_G16_B16_A16 | R32_G32_B32_A32 => Some(4),
+        _ => None,
+    }
+}
+
+/// Returns true if a vertex attribute in `attribute` can be read by a
+/// shader input expecting `expected`.
+///
+/// The channel types have to match, except that normalized formats like
+/// `Rgba8Unorm` are read as floats, so they can feed a float input. The
+/// attribute can have fewer components than the input: the missing ones are
+/// filled in with zero, or one for alpha, so an `Rgb32Sfloat` attribute can
+/// feed a `vec4`.
+fn format_compatible(attribute: Format, expected: Format) -> bool {
+    use gfx_hal::format::ChannelType;
+
+    let attribute_channel = attribute.base_format().1;
+    let expected_channel = expected.base_format().1;
+
+    let normalized = matches!(attribute_channel, ChannelType::Unorm | ChannelType::Snorm);
+    let channels_match = attribute_channel == expected_channel
+        || (normalized && expected_channel == ChannelType::Sfloat);
+
+    match (component_count(attribute), component_count(expected)) {
+        (Some(provided), Some(required)) => channels_match && provided <= required,
+        _ => false,
+    }
+}
+
+/// Check that every input of a vertex shader is supplied by one of the
+/// attributes, in a compatible format.
+pub fn check_vertex_inputs(spirv: &[u32], attributes: &[AttributeDesc]) -> Result<(), String> {
+    let interface = StageInterface::reflect(ShaderStageFlags::VERTEX, spirv)?;
+    let mut problems = vec![];
+
+    for input in &interface.vertex_inputs {
+        let attribute = attributes
+            .iter()
+            .find(|attribute| attribute.location == input.location);
+
+        match (attribute, input.format) {
+            (None, _) => problems.push(format!(
+                "input `{}` at location {} has no attribute",
+                input.name, input.location
+            )),
+            (Some(attribute), Some(format))
+                if !format_compatible(attribute.element.format, format) =>
+            {
+                problems.push(format!(
+                    "input `{}` at location {} expects {:?}, but the attribute is {:?}",
+                    input.name, input.location, format, attribute.element.format
+                ))
+            }
+            _ => (),
+        }
+    }
+
+    if problems.is_empty() {
+        return Ok(());
+    }
+
+    let mut message = "Vertex shader inputs don't match the vertex attributes:\n".to_owned();
+    for problem in &problems {
+        writeln!(message, "  - {}", problem).unwrap();
+    }
+
+    Err(message)
+}
+
+/// The name of the entry point in some compiled SPIR-V. GLSL shaders always
+/// call it `main`, but HLSL shaders can call it anything.
+pub fn entry_point_name(spirv: &[u32]) -> Result<String, String> {
+    let module = Module::parse(spirv)?;
+
+    match module.entry_points.as_slice() {
+        [entry_point] => Ok(entry_point.name.clone()),
+        entry_points => Err(format!(
+            "Expected one entry point in the shader, but found {}",
+            entry_points.len()
+        )),
+    }
+}
+
+/// Check that every specialization constant given a value is declared in
+/// the shader stage with the same `constant_id`, and the same type.
+pub fn check_specialization(
+    stage: ShaderStageFlags,
+    spirv: &[u32],
+    constants: &SpecConstants,
+) -> Result<(), String> {
+    let module = Module::parse(spirv)?;
+    let mut problems = vec![];
+
+    for (id, ty) in constants.types() {
+        let shader_constant = module
+            .spec_constants
+            .iter()
+            .find(|constant| module.decoration(constant.id, decoration::SPEC_ID) == Some(id));
+
+        let shader_constant = match shader_constant {
+            Some(constant) => constant,
+            None => {
+                problems.push(format!("there's no constant with constant_id {}", id));
+                continue;
+            }
+        };
+
+        let shader_type = match module.types.get(&shader_constant.result_type) {
+            Some(Type::Bool) => Some(SpecConstantType::Bool),
+            Some(Type::Int {
+                width: 32,
+                signed: true,
+            }) => Some(SpecConstantType::Int),
+            Some(Type::Int {
+                width: 32,
+                signed: false,
+            }) => Some(SpecConstantType::Uint),
+            Some(Type::Float { width: 32 }) => Some(SpecConstantType::Float),
+            _ => None,
+        };
+
+        if shader_type != Some(ty) {
+            let shader_type = shader_type
+                .map(|shader_type| format!("{:?}", shader_type))
+                .unwrap_or_else(|| "an unsupported type".to_owned());
+
+            problems.push(format!(
+                "constant `{}` with constant_id {} is {} in the shader, but was given {:?}",
+                module.name(shader_constant.id),
+                id,
+                shader_type,
+                ty
+            ));
+        }
+    }
+
+    if problems.is_empty() {
+        return Ok(());
+    }
+
+    let mut message = format!("{:?} shader specialization constants don't match:\n", stage);
+    for problem in &problems {
+        writeln!(message, "  - {}", problem).unwrap();
+    }
+
+    Err(message)
+}
+
+/// The descriptor set layouts and push constant ranges for a pipeline
+/// layout, built from the shader stages that use it.
+#[derive(Debug, Clone, Default)]
+pub struct PipelineLayoutDesc {
+    /// The bindings in each descriptor set, indexed by set number. Sets that
+    /// no stage uses are left empty.
+    pub sets: Vec<Vec<DescriptorSetLayoutBinding>>,
+
+    /// The push constant ranges, and which stages can see them.
+    pub push_constants: Vec<(ShaderStageFlags, Range<u32>)>,
+}
+
+impl PipelineLayoutDesc {
+    /// Merge the resources used by each stage into a single layout.
+    ///
+    /// Two stages can share a binding, in which case it's made visible to
+    /// both. If they disagree about what's in it, that's an error. Push
+    /// constants are merged into a single range visible to every stage that
+    /// uses them, and any overlapping members have to agree too.
+    pub fn from_stages(stages: &[StageInterface]) -> Result<Self, String> {
+        let mut bindings: BTreeMap<(u32, DescriptorBinding), (DescriptorSetLayoutBinding, &str)> =
+            BTreeMap::new();
+        let mut problems = vec![];
+
+        for interface in stages {
+            for descriptor in &interface.descriptors {
+                let key = (descriptor.set, descriptor.binding);
+
+                match bindings.get_mut(&key) {
+                    Some((existing, _))
+                        if existing.ty == descriptor.ty && existing.count == descriptor.count =>
+                    {
+                        existing.stage_flags |= interface.stage;
+                    }
+                    Some((existing, name)) => problems.push(format!(
+                        "set {} binding {} is `{}` ({:?} x{}) in {:?}, but `{}` ({:?} x{}) in {:?}",
+                        descriptor.set,
+                        descriptor.binding,
+                        name,
+                        existing.ty,
+                        existing.count,
+                        existing.stage_flags,
+                        descriptor.name,
+                        descriptor.ty,
+                        descriptor.count,
+                        interface.stage
+                    )),
+                    None => {
+                        let binding = DescriptorSetLayoutBinding {
+                            binding: descriptor.binding,
+                            ty: descriptor.ty,
+                            count: descriptor.count,
+                            stage_flags: interface.stage,
+                            immutable_samplers: false,
+                        };
+                        bindings.insert(key, (binding, &descriptor.name));
+                    }
+                }
+            }
+        }
+
+        let mut desc = PipelineLayoutDesc::default();
+
+        for ((set, _), (binding, _)) in bindings {
+            let set = set as usize;
+            if desc.sets.len() <= set {
+                desc.sets.resize(set + 1, vec![]);
+            }
+            desc.sets[set].push(binding);
+        }
+
+        let blocks: Vec<(ShaderStageFlags, &BlockLayout)> = stages
+            .iter()
+            .filter_map(|interface| Some((interface.stage, interface.push_constants.as_ref()?)))
+            .collect();
+
+        for (index, (stage_a, block_a)) in blocks.iter().enumerate() {
+            for (stage_b, block_b) in &blocks[index + 1..] {
+                for a in &block_a.members {
+                    let conflict = block_b.members.iter().find(|b| {
+                        let overlaps = a.offset < b.offset + b.size && b.offset < a.offset + a.size;
+                        overlaps && (a.offset != b.offset || a.size != b.size)
+                    });
+
+                    if let Some(b) = conflict {
+                        problems.push(format!(
+                            "push constant `{}` (offset {}, size {}) in {:?} overlaps `{}` \
+                             (offset {}, size {}) in {:?}",
+                            a.name, a.offset, a.size, stage_a, b.name, b.offset, b.size, stage_b
+                        ));
+                    }
+                }
+            }
+        }
+
+        if !blocks.is_empty() {
+            let stages = blocks
+                .iter()
+                .fold(ShaderStageFlags::empty(), |stages, (stage, _)| {
+                    stages | *stage
+                });
+            let start = blocks
+                .iter()
+                .flat_map(|(_, block)| block.members.iter().map(|member| member.offset))
+                .min()
+                .unwrap_or(0);
+            let end = blocks
+                .iter()
+                .map(|(_, block)| block.size)
+                .max()
+                .unwrap_or(0);
+
+            desc.push_constants.push((stages, start..end));
+        }
+
+        if problems.is_empty() {
+            return Ok(desc);
+        }
+
+        let mut message = "Shader stages conflict:\n".to_owned();
+        for problem in &problems {
+            writeln!(message, "  - {}", problem).unwrap();
+        }
+
+        Err(message)
+    }
+
+    /// Make every uniform buffer in `set` a dynamic uniform buffer.
+    ///
+    /// Reflection can't tell whether we want to bind a uniform buffer with
+    /// a dynamic offset, so we have to say so.
+    pub fn make_uniforms_dynamic(&mut self, set: usize) {
+        for binding in self.sets.get_mut(set).into_iter().flatten() {
+            if let DescriptorType::Buffer {
+                ty: BufferDescriptorType::Uniform,
+                format: BufferDescriptorFormat::Structured { dynamic_offset },
+            } = &mut binding.ty
+            {
+                *dynamic_offset = true;
+            }
+        }
+    }
+
+    /// Check that everything in this layout is also in `layout`, so that a
+    /// pipeline needing this layout can be created with `layout` instead.
+    ///
+    /// This is useful when a shader is reloaded, and we want to keep using
+    /// the pipeline layout and descriptor sets we already have, or when
+    /// several pipelines share a layout built from one of them. Whether
+    /// uniform buffers are dynamic is ignored, because reflection can't tell.
+    ///
+    /// Each push constant range only has to lie inside one of the layout's,
+    /// and be visible to the same stages or fewer. A pipeline whose shaders
+    /// use fewer push constants than the layout has still fits.
+    pub fn fits_within(&self, layout: &PipelineLayoutDesc) -> Result<(), String> {
+        let mut problems = vec![];
+
+        for (set, bindings) in self.sets.iter().enumerate() {
+            for binding in bindings {
+                let existing = layout
+                    .sets
+                    .get(set)
+                    .and_then(|bindings| bindings.iter().find(|b| b.binding == binding.binding));
+
+                match existing {
+                    Some(existing)
+                        if ignore_dynamic(existing.ty) == ignore_dynamic(binding.ty)
+                            && existing.count == binding.count
+                            && existing.stage_flags.contains(binding.stage_flags) => {}
+                    Some(existing) => problems.push(format!(
+                        "set {} binding {} is {:?} x{} in {:?}, but the layout has {:?} x{} in \
+                         {:?}",
+                        set,
+                        binding.binding,
+                        binding.ty,
+                        binding.count,
+                        binding.stage_flags,
+                        existing.ty,
+                        existing.count,
+                        existing.stage_flags
+                    )),
+                    None => problems.push(format!(
+                        "set {} binding {} isn't in the layout",
+                        set, binding.binding
+                    )),
+                }
+            }
+        }
+
+        for (stages, range) in &self.push_constants {
+            let covered = layout
+                .push_constants
+                .iter()
+                .any(|(layout_stages, layout_range)| {
+                    layout_stages.contains(*stages)
+                        && layout_range.start <= range.start
+                        && range.end <= layout_range.end
+                });
+
+            if !covered {
+                problems.push(format!(
+                    "push constants {:?} in {:?} aren't covered by the layout's {:?}",
+                    range, stages, layout.push_constants
+                ));
+            }
+        }
+
+        if problems.is_empty() {
+            return Ok(());
+        }
+
+        let mut message = "Shaders don't fit the pipeline layout:\n".to_owned();
+        for problem in &problems {
+            writeln!(message, "  - {}", problem).unwrap();
+        }
+
+        Err(message)
+    }
+}
+
+/// The same descriptor type, but never dynamic.
+fn ignore_dynamic(ty: DescriptorType) -> DescriptorType {
+    match ty {
+        DescriptorType::Buffer {
+            ty,
+            format: BufferDescriptorFormat::Structured { .. },
+        } => DescriptorType::Buffer {
+            ty,
+            format: BufferDescriptorFormat::Structured {
+                dynamic_offset: false,
+            },
+        },
+        ty => ty,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compile(kind: shaderc::ShaderKind, source: &str) -> Vec<u32> {
+        let mut compiler = shaderc::Compiler::new().unwrap();
+        compiler
+            .compile_into_spirv(source, kind, "test.glsl", "main", None)
+            .unwrap_or_else(|error| panic!("{}", error))
+            .as_binary()
+            .to_vec()
+    }
+
+    fn reflect_vertex(source: &str) -> StageInterface {
+        let spirv = compile(shaderc::ShaderKind::Vertex, source);
+        StageInterface::reflect(ShaderStageFlags::VERTEX, &spirv).unwrap()
+    }
+
+    fn reflect_fragment(source: &str) -> StageInterface {
+        let spirv = compile(shaderc::ShaderKind::Fragment, source);
+        StageInterface::reflect(ShaderStageFlags::FRAGMENT, &spirv).unwrap()
+    }
+
+    fn block(name: &str, members: &[(&str, u32, u32)]) -> BlockLayout {
+        let members: Vec<BlockMember> = members
+            .iter()
+            .map(|&(name, offset, size)| BlockMember {
+                name: name.to_owned(),
+                offset,
+                size,
+            })
+            .collect();
+
+        BlockLayout {
+            name: name.to_owned(),
+            size: members.iter().map(|m| m.offset + m.size).max().unwrap_or(0),
+            members,
+        }
+    }
+
+    fn attribute(location: u32, format: Format) -> AttributeDesc {
+        AttributeDesc {
+            location,
+            binding: 0,
+            element: gfx_hal::pso::Element { format, offset: 0 },
+        }
+    }
+
+    // A vertex shader with a bit of everything: inputs of a few types, a
+    // uniform buffer, and a push constant block that the fragment shader
+    // below also uses.
+    const VERTEX: &str = "
+        #version 450
+        layout(location = 0) in vec3 position;
+        layout(location = 2) in uint index;
+        layout(location = 1) in ivec2 cell;
+        layout(set = 0, binding = 0) uniform Camera { mat4 view_projection; } camera;
+        layout(push_constant) uniform PushConstants {
+            mat4 transform;
+            vec3 tint;
+            float scale;
+        } push_constants;
+        void main() {
+            gl_Position = camera.view_projection * push_constants.transform
+                * vec4(position * push_constants.scale, float(index + cell.x));
+        }
+    ";
+
+    const FRAGMENT: &str = "
+        #version 450
+        layout(set = 0, binding = 0) uniform Camera { mat4 view_projection; } camera;
+        layout(set = 0, binding = 1) uniform texture2D textures[3];
+        layout(set = 0, binding = 2) uniform sampler texture_sampler;
+        layout(set = 1, binding = 0) uniform sampler2D combined;
+        layout(set = 1, binding = 1) readonly buffer Lights { vec4 colors[]; } lights;
+        layout(push_constant) uniform PushConstants {
+            mat4 transform;
+            vec3 tint;
+            float scale;
+        } push_constants;
+        layout(location = 0) out vec4 color;
+        void main() {
+            color = texture(sampler2D(textures[1], texture_sampler), vec2(0.0))
+                + texture(combined, vec2(0.0))
+                + lights.colors[0]
+                + camera.view_projection[0]
+                + vec4(push_constants.tint, 1.0);
+        }
+    ";
+
+    const WITH_PUSH_CONSTANTS: &str = "
+        #version 450
+        layout(push_constant) uniform Constants { float threshold; } constants;
+        layout(location = 0) out vec4 color;
+        void main() { color = vec4(constants.threshold); }
+    ";
+
+    const WITHOUT_PUSH_CONSTANTS: &str = "
+        #version 450
+        layout(location = 0) out vec4 color;
+        void main() { color = vec4(1.0); }
+    ";
+
+    #[test]
+    fn stage_without_push_constants_fits_layout_with_them() {
+        let layout = PipelineLayoutDesc::from_stages(&[
+            reflect_fragment(WITH_PUSH_CONSTANTS),
+            reflect_fragment(WITHOUT_PUSH_CONSTANTS),
+        ])
+        .unwrap();
+        assert_eq!(
+            layout.push_constants,
+            vec![(ShaderStageFlags::FRAGMENT, 0..4)]
+        );
+
+        let desc =
+            PipelineLayoutDesc::from_stages(&[reflect_fragment(WITHOUT_PUSH_CONSTANTS)]).unwrap();
+        assert!(desc.push_constants.is_empty());
+        assert!(desc.fits_within(&layout).is_ok());
+
+        // It doesn't work the other way around.
+        let desc =
+            PipelineLayoutDesc::from_stages(&[reflect_fragment(WITH_PUSH_CONSTANTS)]).unwrap();
+        assert!(desc.fits_within(&PipelineLayoutDesc::default()).is_err());
+    }
+
+    #[test]
+    fn push_constant_block_is_laid_out_std430() {
+        let spirv = compile(shaderc::ShaderKind::Vertex, VERTEX);
+        let block = push_constant_block(&spirv).unwrap().unwrap();
+
+        assert_eq!(
+            block,
+            self::block(
+                "PushConstants",
+                &[("transform", 0, 64), ("tint", 64, 12), ("scale", 76, 4)]
+            )
+        );
+    }
+
+    #[test]
+    fn no_push_constant_block() {
+        let spirv = compile(shaderc::ShaderKind::Fragment, WITHOUT_PUSH_CONSTANTS);
+        assert_eq!(push_constant_block(&spirv).unwrap(), None);
+    }
+
+    #[test]
+    fn vertex_inputs_are_sorted_with_formats() {
+        let interface = reflect_vertex(VERTEX);
+        let inputs: Vec<_> = interface
+            .vertex_inputs
+            .iter()
+            .map(|input| (input.name.as_str(), input.location, input.format))
+            .collect();
+
+        // `gl_VertexIndex` and friends aren't vertex inputs.
+        assert_eq!(
+            inputs,
+            vec![
+                ("position", 0, Some(Format::Rgb32Sfloat)),
+                ("cell", 1, Some(Format::Rg32Sint)),
+                ("index", 2, Some(Format::R32Uint)),
+            ]
+        );
+    }
+
+    #[test]
+    fn descriptors_have_the_right_types() {
+        let interface = reflect_fragment(FRAGMENT);
+        let mut descriptors: Vec<_> = interface
+            .descriptors
+            .iter()
+            .map(|d| ((d.set, d.binding), d.ty, d.count))
+            .collect();
+        descriptors.sort_by_key(|&(key, _, _)| key);
+
+        let uniform = DescriptorType::Buffer {
+            ty: BufferDescriptorType::Uniform,
+            format: BufferDescriptorFormat::Structured {
+                dynamic_offset: false,
+            },
+        };
+        let storage = DescriptorType::Buffer {
+            ty: BufferDescriptorType::Storage { read_only: true },
+            format: BufferDescriptorFormat::Structured {
+                dynamic_offset: false,
+            },
+        };
+        let image = DescriptorType::Image {
+            ty: ImageDescriptorType::Sampled {
+                with_sampler: false,
+            },
+        };
+        let combined = DescriptorType::Image {
+            ty: ImageDescriptorType::Sampled { with_sampler: true },
+        };
+
+        assert_eq!(
+            descriptors,
+            vec![
+                ((0, 0), uniform, 1),
+                ((0, 1), image, 3),
+                ((0, 2), DescriptorType::Sampler, 1),
+                ((1, 0), combined, 1),
+                ((1, 1), storage, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn stages_are_merged() {
+        let desc =
+            PipelineLayoutDesc::from_stages(&[reflect_vertex(VERTEX), reflect_fragment(FRAGMENT)])
+                .unwrap();
+
+        let stages = |set: usize, binding: u32| {
+            desc.sets[set]
+                .iter()
+                .find(|b| b.binding == binding)
+                .unwrap()
+                .stage_flags
+        };
+
+        assert_eq!(desc.sets.len(), 2);
+        assert_eq!(desc.sets[0].len(), 3);
+        assert_eq!(desc.sets[1].len(), 2);
+        assert_eq!(
+            stages(0, 0),
+            ShaderStageFlags::VERTEX | ShaderStageFlags::FRAGMENT
+        );
+        assert_eq!(stages(0, 1), ShaderStageFlags::FRAGMENT);
+        assert_eq!(
+            desc.push_constants,
+            vec![(ShaderStageFlags::VERTEX | ShaderStageFlags::FRAGMENT, 0..80)]
+        );
+    }
+
+    #[test]
+    fn stage_without_push_constants_is_left_out_of_the_range() {
+        let desc = PipelineLayoutDesc::from_stages(&[
+            reflect_vertex(VERTEX),
+            reflect_fragment(WITHOUT_PUSH_CONSTANTS),
+        ])
+        .unwrap();
+
+        assert_eq!(desc.push_constants, vec![(ShaderStageFlags::VERTEX, 0..80)]);
+    }
+
+    #[test]
+    fn conflicting_bindings_are_an_error() {
+        let other = "
+            #version 450
+            layout(set = 0, binding = 0) uniform sampler2D not_a_camera;
+            layout(location = 0) out vec4 color;
+            void main() { color = texture(not_a_camera, vec2(0.0)); }
+        ";
+        let result =
+            PipelineLayoutDesc::from_stages(&[reflect_vertex(VERTEX), reflect_fragment(other)]);
+
+        assert!(result.unwrap_err().contains("set 0 binding 0 is `camera`"));
+    }
+
+    #[test]
+    fn conflicting_push_constants_are_an_error() {
+        let other = "
+            #version 450
+            layout(push_constant) uniform Other { vec2 offset; float scale; } other;
+            layout(location = 0) out vec4 color;
+            void main() { color = vec4(other.offset, other.scale, 1.0); }
+        ";
+        let result =
+            PipelineLayoutDesc::from_stages(&[reflect_vertex(VERTEX), reflect_fragment(other)]);
+
+        assert!(result.unwrap_err().contains("overlaps"));
+    }
+
+    #[test]
+    fn fits_within_checks_bindings() {
+        let vertex = PipelineLayoutDesc::from_stages(&[reflect_vertex(VERTEX)]).unwrap();
+        let mut layout =
+            PipelineLayoutDesc::from_stages(&[reflect_vertex(VERTEX), reflect_fragment(FRAGMENT)])
+                .unwrap();
+
+        // Uniform buffers can be made dynamic without breaking anything.
+        layout.make_uniforms_dynamic(0);
+        assert!(vertex.fits_within(&layout).is_ok());
+
+        // But the fragment shader's bindings aren't in the vertex layout,
+        // and its push constants are visible to fewer stages.
+        let problems = layout.fits_within(&vertex).unwrap_err();
+        assert!(problems.contains("set 0 binding 0 is"));
+        assert!(problems.contains("set 1 binding 0 isn't in the layout"));
+        assert!(problems.contains("push constants"));
+    }
+
+    #[test]
+    fn fits_within_checks_push_constant_ranges() {
+        let layout = |stages, range| PipelineLayoutDesc {
+            sets: vec![],
+            push_constants: vec![(stages, range)],
+        };
+        let vertex = ShaderStageFlags::VERTEX;
+        let both = ShaderStageFlags::VERTEX | ShaderStageFlags::FRAGMENT;
+
+        assert!(layout(vertex, 0..16)
+            .fits_within(&layout(both, 0..32))
+            .is_ok());
+        assert!(layout(vertex, 16..32)
+            .fits_within(&layout(both, 0..32))
+            .is_ok());
+        assert!(layout(vertex, 0..48)
+            .fits_within(&layout(both, 0..32))
+            .is_err());
+        assert!(layout(both, 0..16)
+            .fits_within(&layout(vertex, 0..32))
+            .is_err());
+    }
+
+    #[test]
+    fn push_constants_match_rust_layout() {
+        let spirv = compile(shaderc::ShaderKind::Vertex, VERTEX);
+        let rust = block(
+            "PushConstants",
+            &[("transform", 0, 64), ("tint", 64, 12), ("scale", 76, 4)],
+        );
+        let layout = PushConstantLayout::new(ShaderStageFlags::VERTEX, 0..80, rust);
+
+        assert!(check_push_constants(ShaderStageFlags::VERTEX, &spirv, Some(&layout)).is_ok());
+
+        // A shader with no push constants doesn't need a range.
+        let fragment = compile(shaderc::ShaderKind::Fragment, WITHOUT_PUSH_CONSTANTS);
+        assert!(check_push_constants(ShaderStageFlags::FRAGMENT, &fragment, None).is_ok());
+    }
+
+    #[test]
+    fn push_constant_mismatches_are_described() {
+        let spirv = compile(shaderc::ShaderKind::Vertex, VERTEX);
+        let check = |stages, rust: BlockLayout| {
+            let layout = PushConstantLayout::new(stages, 0..rust.size, rust);
+            check_push_constants(ShaderStageFlags::VERTEX, &spirv, Some(&layout)).unwrap_err()
+        };
+
+        // `tint` as a `[f32; 4]`, pushing `scale` along.
+        let message = check(
+            ShaderStageFlags::VERTEX,
+            block(
+                "PushConstants",
+                &[("transform", 0, 64), ("tint", 64, 16), ("scale", 80, 4)],
+            ),
+        );
+        assert!(message.contains("member layouts differ"));
+        assert!(message.contains("! tint (offset 64, size 16)"));
+
+        // A struct that's too small for the shader.
+        let message = check(
+            ShaderStageFlags::VERTEX,
+            block("PushConstants", &[("transform", 0, 64)]),
+        );
+        assert!(message.contains("past the end of the push constant range"));
+
+        // A range the vertex shader can't see.
+        let message = check(
+            ShaderStageFlags::FRAGMENT,
+            block(
+                "PushConstants",
+                &[("transform", 0, 64), ("tint", 64, 12), ("scale", 76, 4)],
+            ),
+        );
+        assert!(message.contains("only visible to"));
+
+        // No range at all.
+        let message = check_push_constants(ShaderStageFlags::VERTEX, &spirv, None).unwrap_err();
+        assert!(message.contains("the pipeline has no push constants"));
+    }
+
+    #[test]
+    fn push_constant_range_is_checked_against_the_struct() {
+        let spirv = compile(shaderc::ShaderKind::Vertex, VERTEX);
+        let rust = block(
+            "PushConstants",
+            &[("transform", 0, 64), ("tint", 64, 12), ("scale", 76, 4)],
+        );
+        let check = |range| {
+            let layout = PushConstantLayout::new(ShaderStageFlags::VERTEX, range, rust.clone());
+            check_push_constants(ShaderStageFlags::VERTEX, &spirv, Some(&layout)).unwrap_err()
+        };
+
+        // The struct matches the shader, but the pipeline layout was made
+        // with a range that's too small for either of them.
+        let message = check(0..64);
+        assert!(message.contains("`PushConstants` is 80 bytes, but the push constant range 0..64"));
+        assert!(message.contains("past the end of the push constant range"));
+
+        // Or one that's bigger than the struct.
+        let message = check(0..96);
+        assert!(message.contains("`PushConstants` is 80 bytes, but the push constant range 0..96"));
+
+        // The struct is pushed at the start of the range, so a range that
+        // doesn't start at `0` moves every member along.
+        let message = check(16..96);
+        assert!(message.contains("member layouts differ"));
+        assert!(message.contains("! transform (offset 16, size 64)"));
+    }
+
+    #[test]
+    fn vertex_inputs_are_checked_against_attributes() {
+        let spirv = compile(shaderc::ShaderKind::Vertex, VERTEX);
+
+        let attributes = [
+            attribute(0, Format::Rgb32Sfloat),
+            attribute(1, Format::Rg32Sint),
+            attribute(2, Format::R32Uint),
+        ];
+        assert!(check_vertex_inputs(&spirv, &attributes).is_ok());
+
+        let attributes = [
+            attribute(0, Format::Rgb32Sint),
+            attribute(2, Format::R32Uint),
+        ];
+        let message = check_vertex_inputs(&spirv, &attributes).unwrap_err();
+        assert!(message.contains("input `position` at location 0 expects Rgb32Sfloat"));
+        assert!(message.contains("input `cell` at location 1 has no attribute"));
+    }
+
+    #[test]
+    fn normalized_attributes_feed_float_inputs() {
+        assert!(format_compatible(Format::Rgba8Unorm, Format::Rgba32Sfloat));
+        assert!(format_compatible(Format::Rgb32Sfloat, Format::Rgb32Sfloat));
+        assert!(!format_compatible(Format::Rgba8Uint, Format::Rgba32Sfloat));
+        assert!(!format_compatible(Format::R32Sint, Format::R32Uint));
+    }
+
+    #[test]
+    fn attributes_can_have_fewer_components_than_inputs() {
+        assert!(format_compatible(Format::Rgb32Sfloat, Format::Rgba32Sfloat));
+        assert!(format_compatible(Format::Rg8Unorm, Format::Rgba32Sfloat));
+        assert!(format_compatible(Format::R32Uint, Format::Rg32Uint));
+        assert!(!format_compatible(
+            Format::Rgba32Sfloat,
+            Format::Rgb32Sfloat
+        ));
+        assert!(!format_compatible(Format::Rgb32Uint, Format::Rgba32Sfloat));
+        assert!(!format_compatible(
+            Format::Rgb64Sfloat,
+            Format::Rgba32Sfloat
+        ));
+    }
+
+    #[test]
+    fn entry_point_is_found() {
+        let spirv = compile(shaderc::ShaderKind::Vertex, VERTEX);
+        assert_eq!(entry_point_name(&spirv).unwrap(), "main");
+    }
+
+    #[test]
+    fn specialization_constants_are_checked() {
+        let source = "
+            #version 450
+            layout(constant_id = 0) const uint MODE = 0;
+            layout(constant_id = 1) const bool WIREFRAME = false;
+            layout(location = 0) out vec4 color;
+            void main() { color = vec4(float(MODE), WIREFRAME ? 1.0 : 0.0, 0.0, 1.0); }
+        ";
+        let spirv = compile(shaderc::ShaderKind::Fragment, source);
+        let stage = ShaderStageFlags::FRAGMENT;
+
+        let constants = SpecConstants::new().with(0, 2u32).with(1, true);
+        assert!(check_specialization(stage, &spirv, &constants).is_ok());
+
+        let constants = SpecConstants::new().with(0, 1.0f32).with(2, 1u32);
+        let message = check_specialization(stage, &spirv, &constants).unwrap_err();
+        assert!(message.contains("constant `MODE` with constant_id 0 is Uint"));
+        assert!(message.contains("there's no constant with constant_id 2"));
+    }
+}
@@ -0,0 +1,151 @@
+//! A ring buffer for per-frame uniform data.
+//!
+//! Push constants are quick and easy, but there's very little room for them:
+//! often only 128 bytes. For anything bigger, we write the data into a
+//! uniform buffer instead, and point a dynamic uniform buffer descriptor at
+//! it with an offset when we bind the descriptor set.
+//!
+//! The buffer is split into one region per frame in flight. Each frame, we
+//! move on to the next region and fill it up from the start. That way we
+//! never overwrite data that the GPU might still be reading from a previous
+//! frame.
+
+use gfx_hal::{
+    adapter::PhysicalDevice, buffer::Offset, command::DescriptorSetOffset, device::Device,
+    memory::Properties, memory::Segment, Backend,
+};
+
+use crate::buffer::make_buffer;
+use crate::shader_data::ShaderData;
+
+/// A persistently-mapped uniform buffer, split into one region per frame.
+pub struct UniformRing<B: Backend> {
+    memory: B::Memory,
+    buffer: B::Buffer,
+    mapped_memory: *mut u8,
+    frame_size: Offset,
+    frame_count: usize,
+    alignment: Offset,
+    atom_size: Offset,
+    frame: usize,
+    cursor: Offset,
+}
+
+/// Round `value` up to the next multiple of `alignment`, which must be a
+/// power of two.
+fn align_up(value: Offset, alignment: Offset) -> Offset {
+    let mask = alignment.max(1) - 1;
+    (value + mask) & !mask
+}
+
+impl<B: Backend> UniformRing<B> {
+    /// Create a ring with `frame_count` regions of at least `frame_size`
+    /// bytes each.
+    ///
+    /// The `frame_count` should be at least the number of frames that can be
+    /// in flight at once.
+    pub unsafe fn new(
+        device: &B::Device,
+        physical_device: &B::PhysicalDevice,
+        frame_size: usize,
+        frame_count: usize,
+    ) -> Self {
+        use gfx_hal::buffer::Usage;
+
+        // Dynamic offsets have to be a multiple of the device's uniform buffer
+        // offset alignment. Because the memory might not be coherent, we also
+        // keep each frame's region a multiple of the atom size, so that we can
+        // flush one region without touching its neighbours.
+        let limits = physical_device.limits();
+        let alignment = limits.min_uniform_buffer_offset_alignment.max(1);
+        let atom_size = (limits.non_coherent_atom_size as Offset).max(1);
+        let frame_size = align_up(frame_size as Offset, alignment.max(atom_size));
+
+        let (memory, buffer) = make_buffer::<B>(
+            device,
+            physical_device,
+            (frame_size as usize) * frame_count,
+            Usage::UNIFORM,
+            Properties::CPU_VISIBLE,
+        );
+
+        // Rather than mapping and unmapping the memory every frame, we map it
+        // once and leave it mapped until the ring is destroyed.
+        let mapped_memory = device
+            .map_memory(&memory, Segment::ALL)
+            .expect("Failed to map memory");
+
+        UniformRing {
+            memory,
+            buffer,
+            mapped_memory,
+            frame_size,
+            frame_count,
+            alignment,
+            atom_size,
+            frame: 0,
+            cursor: 0,
+        }
+    }
+
+    /// The buffer to point uniform buffer descriptors at.
+    pub fn buffer(&self) -> &B::Buffer {
+        &self.buffer
+    }
+
+    /// Move on to the next frame's region.
+    ///
+    /// Call this once per frame, after waiting for the GPU to finish with
+    /// the frame that last used that region.
+    pub fn begin_frame(&mut self) {
+        self.frame = (self.frame + 1) % self.frame_count;
+        self.cursor = 0;
+    }
+
+    /// Copy `data` into the current frame's region, and return the dynamic
+    /// offset to bind it with.
+    ///
+    /// Panics if there's no room left in this frame's region.
+    pub unsafe fn push<T: ShaderData>(&mut self, data: &T) -> DescriptorSetOffset {
+        let size = std::mem::size_of::<T>() as Offset;
+        let offset = align_up(self.cursor, self.alignment);
+
+        assert!(
+            offset + size <= self.frame_size,
+            "Uniform ring region is full: increase the frame size"
+        );
+
+        let absolute_offset = self.frame as Offset * self.frame_size + offset;
+        let dest = self.mapped_memory.add(absolute_offset as usize) as *mut T;
+        std::ptr::write_unaligned(dest, *data);
+
+        self.cursor = offset + size;
+
+        absolute_offset as DescriptorSetOffset
+    }
+
+    /// Make everything pushed this frame visible to the GPU.
+    ///
+    /// Call this after the last `push` of the frame, before submitting.
+    pub unsafe fn flush(&self, device: &B::Device) {
+        if self.cursor == 0 {
+            return;
+        }
+
+        let segment = Segment {
+            offset: self.frame as Offset * self.frame_size,
+            size: Some(align_up(self.cursor, self.atom_size)),
+        };
+
+        device
+            .flush_mapped_memory_ranges(vec![(&self.memory, segment)])
+            .expect("Out of memory");
+    }
+
+    /// Unmap and destroy the buffer, and free its memory.
+    pub unsafe fn destroy(self, device: &B::Device) {
+        device.unmap_memory(&self.memory);
+        device.destroy_buffer(self.buffer);
+        device.free_memory(self.memory);
+    }
+}
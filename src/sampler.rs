@@ -0,0 +1,232 @@
+//! Sampler settings.
+//!
+//! Rather than hard-coding a `SamplerDesc` for each texture, we describe
+//! samplers with a `SamplerConfig`, which can be loaded from a RON file like
+//! `assets/sampler.ron`. That way, filtering and wrapping can be tweaked
+//! without touching the code.
+
+use gfx_hal::image::{Filter, Lod, PackedColor, SamplerDesc, WrapMode};
+use gfx_hal::{Features, Limits};
+use serde::{Deserialize, Serialize};
+
+/// How texels are filtered when a texture is scaled up or down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FilterMode {
+    /// Pick the single nearest texel (or mip level).
+    Nearest,
+
+    /// Blend between the nearest texels (or mip levels).
+    Linear,
+}
+
+impl From<FilterMode> for Filter {
+    fn from(mode: FilterMode) -> Filter {
+        match mode {
+            FilterMode::Nearest => Filter::Nearest,
+            FilterMode::Linear => Filter::Linear,
+        }
+    }
+}
+
+/// What happens when a texture is sampled outside the `0.0..1.0` range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AddressMode {
+    /// Repeat the texture.
+    Wrap,
+
+    /// Repeat the texture, flipping it every other time.
+    Mirror,
+
+    /// Stretch the texels at the edge of the texture.
+    Clamp,
+
+    /// Use the sampler's border color.
+    Border,
+
+    /// Mirror the texture once, then clamp. If the device doesn't support
+    /// this, it falls back to `Clamp`.
+    MirrorClamp,
+}
+
+impl AddressMode {
+    fn wrap_mode(self, features: Features) -> WrapMode {
+        match self {
+            AddressMode::Wrap => WrapMode::Tile,
+            AddressMode::Mirror => WrapMode::Mirror,
+            AddressMode::Clamp => WrapMode::Clamp,
+            AddressMode::Border => WrapMode::Border,
+            AddressMode::MirrorClamp => {
+                if features.contains(Features::SAMPLER_MIRROR_CLAMP_EDGE) {
+                    WrapMode::MirrorClamp
+                } else {
+                    WrapMode::Clamp
+                }
+            }
+        }
+    }
+}
+
+/// Level-of-detail settings for sampling mipmapped textures.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LodSettings {
+    /// Added to the mip level the GPU would otherwise pick. Positive values
+    /// make textures blurrier, negative values make them sharper (and more
+    /// prone to aliasing).
+    pub bias: f32,
+
+    /// The most detailed mip level that may be sampled.
+    pub min: f32,
+
+    /// The least detailed mip level that may be sampled, or `None` to allow
+    /// every level.
+    pub max: Option<f32>,
+}
+
+impl Default for LodSettings {
+    fn default() -> Self {
+        LodSettings {
+            bias: 0.0,
+            min: 0.0,
+            max: None,
+        }
+    }
+}
+
+/// A serializable description of a sampler.
+///
+/// Any fields missing from a config file take their value from the
+/// `Default` implementation, which is a trilinear sampler that wraps in
+/// every direction.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SamplerConfig {
+    /// Filter used when the texture is drawn smaller than its actual size.
+    pub min_filter: FilterMode,
+
+    /// Filter used when the texture is drawn larger than its actual size.
+    pub mag_filter: FilterMode,
+
+    /// Filter used between mip levels.
+    pub mip_filter: FilterMode,
+
+    /// Address modes for the `u`, `v`, and `w` texture coordinates.
+    pub address: (AddressMode, AddressMode, AddressMode),
+
+    /// The color used by `AddressMode::Border`, as RGBA.
+    pub border: [f32; 4],
+
+    /// The maximum anisotropy to filter with, or `None` to disable
+    /// anisotropic filtering. This is ignored if the device doesn't support
+    /// it, and lowered to the device's limit if it's higher.
+    pub anisotropy: Option<u8>,
+
+    pub lod: LodSettings,
+}
+
+impl Default for SamplerConfig {
+    fn default() -> Self {
+        SamplerConfig {
+            min_filter: FilterMode::Linear,
+            mag_filter: FilterMode::Linear,
+            mip_filter: FilterMode::Linear,
+            address: (AddressMode::Wrap, AddressMode::Wrap, AddressMode::Wrap),
+            border: [0.0, 0.0, 0.0, 1.0],
+            anisotropy: None,
+            lod: LodSettings::default(),
+        }
+    }
+}
+
+impl SamplerConfig {
+    /// Parse a sampler config from a RON string.
+    pub fn from_ron(source: &str) -> ron::Result<Self> {
+        ron::de::from_str(source)
+    }
+
+    /// The optional device features that a config can make use of. These
+    /// should be requested when opening the device, if they're supported.
+    pub fn wanted_features() -> Features {
+        Features::SAMPLER_ANISOTROPY | Features::SAMPLER_MIRROR_CLAMP_EDGE
+    }
+
+    /// Create a `SamplerDesc` from this config.
+    ///
+    /// The `features` should be the ones the device was opened with. Any
+    /// settings which need a feature that isn't enabled are dropped, or
+    /// replaced with the closest setting that is supported. Likewise, any
+    /// settings beyond the device's `limits` are lowered to fit.
+    pub fn sampler_desc(&self, features: Features, limits: &Limits) -> SamplerDesc {
+        let (u, v, w) = self.address;
+
+        // The limit is a float, but anisotropy only comes in whole steps.
+        let max_anisotropy = limits.max_sampler_anisotropy as u8;
+
+        let anisotropy_clamp = match self.anisotropy {
+            Some(anisotropy)
+                if features.contains(Features::SAMPLER_ANISOTROPY) && max_anisotropy > 1 =>
+            {
+                Some(anisotropy.min(max_anisotropy))
+            }
+            _ => None,
+        };
+
+        SamplerDesc {
+            min_filter: self.min_filter.into(),
+            mag_filter: self.mag_filter.into(),
+            mip_filter: self.mip_filter.into(),
+            wrap_mode: (
+                u.wrap_mode(features),
+                v.wrap_mode(features),
+                w.wrap_mode(features),
+            ),
+            lod_bias: Lod(self.lod.bias),
+            lod_range: Lod(self.lod.min)..Lod(self.lod.max.unwrap_or(f32::MAX)),
+            border: PackedColor::from(self.border),
+            anisotropy_clamp,
+            ..SamplerDesc::new(Filter::Linear, WrapMode::Tile)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_fields_use_defaults() {
+        let config = SamplerConfig::from_ron("(mag_filter: Nearest)").unwrap();
+
+        assert_eq!(
+            config,
+            SamplerConfig {
+                mag_filter: FilterMode::Nearest,
+                ..SamplerConfig::default()
+            }
+        );
+    }
+
+    #[test]
+    fn anisotropy_is_limited_by_the_device() {
+        let config = SamplerConfig {
+            anisotropy: Some(16),
+            ..SamplerConfig::default()
+        };
+        let limits = |max_sampler_anisotropy| Limits {
+            max_sampler_anisotropy,
+            ..Limits::default()
+        };
+        let anisotropy =
+            |features, max| config.sampler_desc(features, &limits(max)).anisotropy_clamp;
+
+        assert_eq!(anisotropy(Features::SAMPLER_ANISOTROPY, 16.0), Some(16));
+        assert_eq!(anisotropy(Features::SAMPLER_ANISOTROPY, 8.0), Some(8));
+        assert_eq!(anisotropy(Features::SAMPLER_ANISOTROPY, 1.0), None);
+        assert_eq!(anisotropy(Features::empty(), 16.0), None);
+    }
+
+    #[test]
+    fn shipped_config_parses() {
+        SamplerConfig::from_ron(include_str!("../assets/sampler.ron")).unwrap();
+    }
+}
@@ -0,0 +1,591 @@
+//! Compiling shader files to SPIR-V.
+//!
+//! This is used both by the build script, which compiles every shader ahead
+//! of time, and by `ShaderWatcher`, which recompiles them when they change.
+//! Keeping it in one place means a shader compiles the same way in both.
+//!
+//! Shaders can share code with `#include`. A quoted path like
+//! `#include "camera.glsl"` is relative to the file doing the including (or
+//! failing that, the shader directory), and an angle-bracketed one like
+//! `#include <camera.glsl>` is relative to the shader directory. Only files
+//! ending in a shader stage (like `.vert`) are compiled by themselves, so
+//! shared code should go in `.glsl` files.
+//!
+//! Shaders can also be written in HLSL. Since `.hlsl` files are often used
+//! for more than one stage, the stage goes before the extension instead, as
+//! in `sky.vert.hlsl`, and plain `.hlsl` files are only used for includes.
+//! HLSL entry points are rarely called `main`, so a pipeline in
+//! `assets/pipelines.ron` can name its shaders' entry points with
+//! `vertex_entry_point` and `fragment_entry_point`.
+//!
+//! A pipeline can also give some `defines`, in which case its shaders are
+//! compiled again with those macros defined, as a separate variant. The
+//! build script reads the variants and entry points from the same file, so
+//! they're embedded along with everything else.
+//!
+//! The build script includes this file directly, so it can't depend on
+//! anything else in the crate.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use shaderc::{CompileOptions, Compiler, IncludeType, ResolvedInclude, ShaderKind, SourceLanguage};
+
+/// A version of a shader compiled with some extra macros defined.
+///
+/// For example, a variant of `teapot.frag` with the define `UNLIT = 1` is
+/// embedded as `TEAPOT_FRAG_UNLIT`, and the shader can check for it with
+/// `#ifdef UNLIT`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShaderVariant {
+    /// The shader's file name in the shader directory.
+    pub file_name: String,
+
+    /// The macros to define, and their values.
+    pub defines: BTreeMap<String, String>,
+}
+
+impl ShaderVariant {
+    /// The name of the constant this variant is embedded as.
+    pub fn constant_name(&self) -> String {
+        constant_name(&self.file_name, &variant_suffix(&self.defines))
+    }
+}
+
+/// The suffix added to the constant name of a variant with some defines:
+/// the name of each define, followed by its value unless that's `1`. For
+/// example, `UPSAMPLE = 1` and `TAPS = 9` give `TAPS_9_UPSAMPLE`.
+///
+/// With no defines, this is empty, and the name is the plain shader's.
+pub fn variant_suffix(defines: &BTreeMap<String, String>) -> String {
+    let parts: Vec<String> = defines
+        .iter()
+        .map(|(name, value)| match value.as_str() {
+            "1" => name.clone(),
+            _ => format!("{}_{}", name, value),
+        })
+        .collect();
+
+    parts.join("_")
+}
+
+/// The entry point a shader uses unless its pipeline config says otherwise.
+pub const DEFAULT_ENTRY_POINT: &str = "main";
+
+/// The default for the entry point fields of `PipelineConfig`.
+pub fn default_entry_point() -> String {
+    DEFAULT_ENTRY_POINT.to_owned()
+}
+
+/// What the pipeline configs need from the shader compiler: the variants to
+/// compile, and the entry point of each shader.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ShaderSettings {
+    pub variants: Vec<ShaderVariant>,
+
+    /// The entry point of every shader used by a pipeline, by file name.
+    pub entry_points: BTreeMap<String, String>,
+}
+
+impl ShaderSettings {
+    /// Add the settings for one pipeline, given the file name and entry
+    /// point of each of its shaders, and its defines.
+    ///
+    /// Each file is only compiled once (per variant), so it's an error for
+    /// two pipelines to give the same shader different entry points.
+    pub fn add_pipeline(
+        &mut self,
+        shaders: [(&str, &str); 2],
+        defines: &BTreeMap<String, String>,
+    ) -> Result<(), String> {
+        for &(file_name, entry_point) in &shaders {
+            match self.entry_points.get(file_name) {
+                Some(existing) if existing != entry_point => {
+                    return Err(format!(
+                        "`{}` has two entry points: `{}` and `{}`",
+                        file_name, existing, entry_point
+                    ))
+                }
+                Some(_) => (),
+                None => {
+                    self.entry_points
+                        .insert(file_name.to_owned(), entry_point.to_owned());
+                }
+            }
+        }
+
+        let [(vertex_shader, _), (fragment_shader, _)] = shaders;
+        self.variants
+            .extend(pipeline_variants(vertex_shader, fragment_shader, defines));
+
+        Ok(())
+    }
+
+    /// The entry point of a shader file: the one its pipelines give, or
+    /// `main` if none of them use it.
+    pub fn entry_point(&self, file_name: &str) -> &str {
+        self.entry_points
+            .get(file_name)
+            .map_or(DEFAULT_ENTRY_POINT, String::as_str)
+    }
+}
+
+/// The parts of a pipeline config that decide how its shaders are compiled.
+///
+/// This mirrors `PipelineConfig`, which the build script can't use, and
+/// ignores everything else.
+#[derive(Deserialize)]
+struct ShaderConfig {
+    vertex_shader: String,
+    fragment_shader: String,
+
+    #[serde(default = "default_entry_point")]
+    vertex_entry_point: String,
+
+    #[serde(default = "default_entry_point")]
+    fragment_entry_point: String,
+
+    #[serde(default)]
+    defines: BTreeMap<String, String>,
+}
+
+/// The settings for the pipeline configs in a RON string, like
+/// `assets/pipelines.ron`.
+pub fn settings_from_ron(source: &str) -> Result<ShaderSettings, String> {
+    let configs: BTreeMap<String, ShaderConfig> =
+        ron::de::from_str(source).map_err(|error| error.to_string())?;
+
+    let mut settings = ShaderSettings::default();
+    for (name, config) in &configs {
+        let shaders = [
+            (
+                config.vertex_shader.as_str(),
+                config.vertex_entry_point.as_str(),
+            ),
+            (
+                config.fragment_shader.as_str(),
+                config.fragment_entry_point.as_str(),
+            ),
+        ];
+
+        settings
+            .add_pipeline(shaders, &config.defines)
+            .map_err(|message| format!("{}: {}", name, message))?;
+    }
+
+    Ok(settings)
+}
+
+/// The variants of a pipeline's shaders, compiled with its defines. If
+/// there are no defines, the plain shaders are used, so there are none.
+pub fn pipeline_variants(
+    vertex_shader: &str,
+    fragment_shader: &str,
+    defines: &BTreeMap<String, String>,
+) -> Vec<ShaderVariant> {
+    if defines.is_empty() {
+        return vec![];
+    }
+
+    [vertex_shader, fragment_shader]
+        .iter()
+        .map(|&file_name| ShaderVariant {
+            file_name: file_name.to_owned(),
+            defines: defines.clone(),
+        })
+        .collect()
+}
+
+/// The language a shader file is written in, based on its extension.
+pub fn source_language(path: &Path) -> SourceLanguage {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("hlsl") => SourceLanguage::HLSL,
+        _ => SourceLanguage::GLSL,
+    }
+}
+
+/// The kind of shader in a file, based on its extension. Returns `None` for
+/// files that aren't shaders.
+pub fn shader_kind(path: &Path) -> Option<ShaderKind> {
+    // The stage of an HLSL shader comes before the `.hlsl`.
+    let stage_path = match source_language(path) {
+        SourceLanguage::HLSL => Path::new(path.file_stem()?),
+        SourceLanguage::GLSL => path,
+    };
+
+    match stage_path.extension()?.to_str()? {
+        "vert" => Some(ShaderKind::Vertex),
+        "frag" => Some(ShaderKind::Fragment),
+        "comp" => Some(ShaderKind::Compute),
+        "geom" => Some(ShaderKind::Geometry),
+        "tesc" => Some(ShaderKind::TessControl),
+        "tese" => Some(ShaderKind::TessEvaluation),
+        _ => None,
+    }
+}
+
+/// The name a compiled shader is embedded as: the file name in upper case,
+/// followed by the variant's suffix, if any. For example, `part-1.vert`
+/// becomes `PART_1_VERT`.
+pub fn constant_name(file_name: &str, suffix: &str) -> String {
+    let name = if suffix.is_empty() {
+        file_name.to_owned()
+    } else {
+        format!("{}_{}", file_name, suffix)
+    };
+
+    name.chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' => c.to_ascii_uppercase(),
+            _ => '_',
+        })
+        .collect()
+}
+
+/// A shader to compile: either the plain version of a file, or a variant.
+pub struct CompileJob {
+    pub path: PathBuf,
+
+    /// The name of the constant it's embedded as.
+    pub name: String,
+
+    pub entry_point: String,
+    pub defines: BTreeMap<String, String>,
+}
+
+/// Everything to compile from the shader directory: the plain version of
+/// every shader, and then each of the variants in `settings`. Variants
+/// shared by more than one pipeline are only compiled once.
+pub fn compile_jobs(
+    shader_dir: &Path,
+    settings: &ShaderSettings,
+) -> std::io::Result<Vec<CompileJob>> {
+    let mut paths = vec![];
+    for entry in std::fs::read_dir(shader_dir)? {
+        let path = entry?.path();
+        if shader_kind(&path).is_some() {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    let plain = paths.into_iter().map(|path| {
+        let file_name = path.file_name().unwrap().to_string_lossy();
+
+        CompileJob {
+            name: constant_name(&file_name, ""),
+            entry_point: settings.entry_point(&file_name).to_owned(),
+            path,
+            defines: BTreeMap::new(),
+        }
+    });
+
+    let variants = settings.variants.iter().map(|variant| CompileJob {
+        path: shader_dir.join(&variant.file_name),
+        name: variant.constant_name(),
+        entry_point: settings.entry_point(&variant.file_name).to_owned(),
+        defines: variant.defines.clone(),
+    });
+
+    let mut jobs: Vec<CompileJob> = vec![];
+    for job in plain.chain(variants) {
+        if !jobs.iter().any(|existing| existing.name == job.name) {
+            jobs.push(job);
+        }
+    }
+
+    Ok(jobs)
+}
+
+/// A successfully compiled shader.
+pub struct CompiledShader {
+    pub spirv: Vec<u32>,
+
+    /// Any warnings, one per line. Empty if there were none.
+    pub warnings: String,
+}
+
+/// Compile a shader file to SPIR-V, with some macros defined. The SPIR-V
+/// entry point has the same name as the `entry_point` in the source.
+///
+/// On failure, returns the compiler's error message, which includes the
+/// file's path and the line number of each error.
+pub fn compile_file(
+    compiler: &mut Compiler,
+    path: &Path,
+    entry_point: &str,
+    defines: &BTreeMap<String, String>,
+) -> Result<CompiledShader, String> {
+    let kind =
+        shader_kind(path).ok_or_else(|| format!("{}: unknown shader kind", path.display()))?;
+    let source =
+        std::fs::read_to_string(path).map_err(|error| format!("{}: {}", path.display(), error))?;
+    let file_name = path.to_string_lossy();
+    let shader_dir = path.parent().unwrap_or_else(|| Path::new("")).to_owned();
+
+    let mut options = CompileOptions::new().expect("Failed to create compile options");
+    options.set_source_language(source_language(path));
+    for (name, value) in defines {
+        options.add_macro_definition(name, Some(value));
+    }
+    options.set_include_callback(|requested, include_type, requesting, _depth| {
+        // A quoted include is looked for next to the file including it
+        // first, and then in the shader directory.
+        let mut search_dirs = vec![];
+        if let IncludeType::Relative = include_type {
+            search_dirs.extend(Path::new(requesting).parent().map(Path::to_owned));
+        }
+        search_dirs.push(shader_dir.clone());
+
+        let resolved_path: PathBuf = search_dirs
+            .iter()
+            .map(|dir| dir.join(requested))
+            .find(|path| path.is_file())
+            .ok_or_else(|| format!("Couldn't find `{}` to include", requested))?;
+
+        let content = std::fs::read_to_string(&resolved_path)
+            .map_err(|error| format!("{}: {}", resolved_path.display(), error))?;
+
+        Ok(ResolvedInclude {
+            resolved_name: resolved_path.to_string_lossy().into_owned(),
+            content,
+        })
+    });
+
+    let artifact = compiler
+        .compile_into_spirv(&source, kind, &file_name, entry_point, Some(&options))
+        .map_err(|error| error.to_string())?;
+
+    let warnings = if artifact.get_num_warnings() > 0 {
+        artifact.get_warning_messages()
+    } else {
+        String::new()
+    };
+
+    Ok(CompiledShader {
+        spirv: artifact.as_binary().to_vec(),
+        warnings,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constant_names() {
+        assert_eq!(constant_name("part-1.vert", ""), "PART_1_VERT");
+        assert_eq!(constant_name("sky.vert.hlsl", ""), "SKY_VERT_HLSL");
+        assert_eq!(constant_name("teapot.frag", "unlit"), "TEAPOT_FRAG_UNLIT");
+    }
+
+    #[test]
+    fn shader_kinds() {
+        let kind = |path| shader_kind(Path::new(path));
+
+        assert_eq!(kind("teapot.vert"), Some(ShaderKind::Vertex));
+        assert_eq!(kind("teapot.frag"), Some(ShaderKind::Fragment));
+        assert_eq!(kind("cull.comp"), Some(ShaderKind::Compute));
+        assert_eq!(kind("sky.vert.hlsl"), Some(ShaderKind::Vertex));
+        assert_eq!(kind("dir/sky.frag.hlsl"), Some(ShaderKind::Fragment));
+
+        // Files only used for includes.
+        assert_eq!(kind("camera.glsl"), None);
+        assert_eq!(kind("common.hlsl"), None);
+        assert_eq!(kind("README"), None);
+    }
+
+    #[test]
+    fn source_languages() {
+        let language = |path| source_language(Path::new(path));
+
+        assert_eq!(language("sky.vert.hlsl"), SourceLanguage::HLSL);
+        assert_eq!(language("common.hlsl"), SourceLanguage::HLSL);
+        assert_eq!(language("teapot.vert"), SourceLanguage::GLSL);
+        assert_eq!(language("camera.glsl"), SourceLanguage::GLSL);
+    }
+
+    fn defines(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|&(name, value)| (name.to_owned(), value.to_owned()))
+            .collect()
+    }
+
+    /// A fresh directory to write test shaders into.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn variant_suffixes() {
+        assert_eq!(variant_suffix(&defines(&[])), "");
+        assert_eq!(variant_suffix(&defines(&[("UPSAMPLE", "1")])), "UPSAMPLE");
+        assert_eq!(
+            variant_suffix(&defines(&[("UPSAMPLE", "1"), ("TAPS", "9")])),
+            "TAPS_9_UPSAMPLE"
+        );
+
+        let variant = ShaderVariant {
+            file_name: "bloom_resample.frag".to_owned(),
+            defines: defines(&[("UPSAMPLE", "1")]),
+        };
+        assert_eq!(variant.constant_name(), "BLOOM_RESAMPLE_FRAG_UPSAMPLE");
+    }
+
+    #[test]
+    fn variants_come_from_pipelines_with_defines() {
+        let settings = settings_from_ron(
+            r#"{
+                "plain": (
+                    vertex_shader: "quad.vert",
+                    fragment_shader: "plain.frag",
+                    topology: TriangleList,
+                ),
+                "tinted": (
+                    vertex_shader: "quad.vert",
+                    fragment_shader: "plain.frag",
+                    blend: Some(Alpha),
+                    defines: { "TINT": "red" },
+                ),
+            }"#,
+        )
+        .unwrap();
+
+        let names: Vec<String> = settings
+            .variants
+            .iter()
+            .map(ShaderVariant::constant_name)
+            .collect();
+        assert_eq!(names, ["QUAD_VERT_TINT_RED", "PLAIN_FRAG_TINT_RED"]);
+    }
+
+    #[test]
+    fn entry_points_come_from_pipelines() {
+        let settings = settings_from_ron(
+            r#"{
+                "sky": (
+                    vertex_shader: "sky.vert.hlsl",
+                    fragment_shader: "sky.frag",
+                    vertex_entry_point: "vs_main",
+                ),
+                "tinted_sky": (
+                    vertex_shader: "sky.vert.hlsl",
+                    fragment_shader: "tinted.frag.hlsl",
+                    vertex_entry_point: "vs_main",
+                    fragment_entry_point: "ps_main",
+                ),
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(settings.entry_point("sky.vert.hlsl"), "vs_main");
+        assert_eq!(settings.entry_point("tinted.frag.hlsl"), "ps_main");
+        assert_eq!(settings.entry_point("sky.frag"), "main");
+        assert_eq!(settings.entry_point("unused.vert"), "main");
+
+        let conflict = settings_from_ron(
+            r#"{
+                "a": (vertex_shader: "quad.vert.hlsl", fragment_shader: "plain.frag"),
+                "b": (
+                    vertex_shader: "quad.vert.hlsl",
+                    fragment_shader: "plain.frag",
+                    vertex_entry_point: "vs_main",
+                ),
+            }"#,
+        );
+        assert_eq!(
+            conflict.unwrap_err(),
+            "b: `quad.vert.hlsl` has two entry points: `main` and `vs_main`"
+        );
+    }
+
+    #[test]
+    fn shared_variants_are_compiled_once() {
+        let dir = temp_dir("compile-jobs");
+        std::fs::write(dir.join("quad.vert"), "").unwrap();
+        std::fs::write(dir.join("common.glsl"), "").unwrap();
+
+        let variant = ShaderVariant {
+            file_name: "quad.vert".to_owned(),
+            defines: defines(&[("TINT", "1")]),
+        };
+        let settings = ShaderSettings {
+            variants: vec![variant.clone(), variant],
+            entry_points: BTreeMap::new(),
+        };
+        let jobs = compile_jobs(&dir, &settings).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let names: Vec<&str> = jobs.iter().map(|job| job.name.as_str()).collect();
+        assert_eq!(names, ["QUAD_VERT", "QUAD_VERT_TINT"]);
+        assert_eq!(jobs[1].defines, defines(&[("TINT", "1")]));
+    }
+
+    #[test]
+    fn defines_are_passed_to_the_shader() {
+        let dir = temp_dir("compile-defines");
+        let path = dir.join("test.frag");
+        std::fs::write(
+            &path,
+            "#version 450
+            layout(location = 0) out vec4 color;
+            void main() {
+            #ifndef TINT
+            #error TINT isn't defined
+            #endif
+                color = vec4(TINT);
+            }
+            ",
+        )
+        .unwrap();
+
+        let mut compiler = Compiler::new().unwrap();
+        let plain = compile_file(&mut compiler, &path, "main", &defines(&[]));
+        let tinted = compile_file(&mut compiler, &path, "main", &defines(&[("TINT", "0.5")]));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(plain.is_err());
+        assert!(tinted.is_ok());
+    }
+
+    #[test]
+    fn quoted_includes_fall_back_to_the_shader_directory() {
+        // `test.frag` includes `lighting/shade.glsl`, which includes
+        // `common.glsl`. That isn't next to `shade.glsl`, so it has to be
+        // found in the shader directory instead.
+        let dir = temp_dir("compile-includes");
+        std::fs::create_dir_all(dir.join("lighting")).unwrap();
+        std::fs::write(
+            dir.join("common.glsl"),
+            "vec4 common_color() { return vec4(1.0); }\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("lighting/shade.glsl"),
+            "#include \"common.glsl\"\nvec4 shade() { return common_color(); }\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("test.frag"),
+            "#version 450
+            #include \"lighting/shade.glsl\"
+            layout(location = 0) out vec4 color;
+            void main() { color = shade(); }
+            ",
+        )
+        .unwrap();
+
+        let mut compiler = Compiler::new().unwrap();
+        let result = compile_file(&mut compiler, &dir.join("test.frag"), "main", &defines(&[]));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        if let Err(message) = result {
+            panic!("{}", message);
+        }
+    }
+}
@@ -0,0 +1,177 @@
+//! Structs that can be shared with shaders.
+//!
+//! Push constants and uniform buffers are laid out by GLSL's `std430` and
+//! `std140` rules, which don't always agree with Rust. For example, a `vec3`
+//! is aligned to 16 bytes in GLSL, but a `[f32; 3]` is only aligned to 4
+//! bytes in Rust, so anything after it ends up at the wrong offset.
+//!
+//! Deriving `ShaderData` checks each field's offset against the rules at
+//! compile time, so a struct that doesn't match fails to build instead of
+//! rendering garbage. Missing padding has to be added as explicit fields:
+//!
+//! ```ignore
+//! #[repr(C)]
+//! #[derive(Debug, Clone, Copy, ShaderData)]
+//! #[shader_data(std140)]
+//! struct Light {
+//!     position: [f32; 3],
+//!     _padding: f32,
+//!     color: [f32; 4],
+//! }
+//! ```
+
+pub use gfx_hal_tutorials_derive::ShaderData;
+
+use crate::reflect::BlockLayout;
+
+/// A type that can be used as a field of a `ShaderData` struct.
+///
+/// Arrays of two to four scalars are treated as vectors. Matrices are arrays
+/// of columns, but the two sets of rules pad those columns differently, so
+/// which types are allowed depends on the rules:
+///
+/// - Under `std140`, every column is padded to a `vec4`, so a `mat2` is a
+///   `[[f32; 4]; 2]`.
+/// - Under `std430`, a `mat2`'s columns aren't padded, so it's a
+///   `[[f32; 2]; 2]`. A `[[f32; 4]; 2]` could be a `mat2x4` too, which is
+///   twice the size, so it isn't allowed.
+///
+/// Either way, a `mat3`'s columns are padded to a `vec4`, so it's a
+/// `[[f32; 4]; 3]`, and longer arrays of `[f32; 4]`s are arrays of `vec4`s.
+///
+/// # Safety
+///
+/// Implementors must be made entirely of 4-byte scalars, with no padding,
+/// and have the same size in Rust as in GLSL.
+pub unsafe trait ShaderType: Copy {}
+
+/// A `ShaderType` that can be used in a `std140` block.
+///
+/// A field whose type doesn't implement this can't be laid out the same
+/// way in Rust, so it fails to compile:
+///
+/// ```compile_fail
+/// use gfx_hal_tutorials::shader_data::ShaderData;
+///
+/// #[repr(C)]
+/// #[derive(Debug, Clone, Copy, ShaderData)]
+/// #[shader_data(std140)]
+/// struct Transform {
+///     matrix: [[f32; 2]; 2],
+/// }
+/// ```
+///
+/// # Safety
+///
+/// `ALIGN` must be the type's alignment under `std140`.
+pub unsafe trait Std140Type: ShaderType {
+    /// The alignment of this type in a `std140` block.
+    const ALIGN: usize;
+}
+
+/// A `ShaderType` that can be used in a `std430` block.
+///
+/// Types that mean different things in Rust and GLSL under these rules
+/// don't implement it. For example, a `mat2` has to be written as
+/// `[[f32; 2]; 2]`:
+///
+/// ```
+/// use gfx_hal_tutorials::shader_data::ShaderData;
+///
+/// #[repr(C)]
+/// #[derive(Debug, Clone, Copy, ShaderData)]
+/// #[shader_data(std430)]
+/// struct Transform {
+///     matrix: [[f32; 2]; 2],
+/// }
+/// ```
+///
+/// Writing it the `std140` way doesn't compile, instead of being silently
+/// twice the size the shader expects:
+///
+/// ```compile_fail
+/// use gfx_hal_tutorials::shader_data::ShaderData;
+///
+/// #[repr(C)]
+/// #[derive(Debug, Clone, Copy, ShaderData)]
+/// #[shader_data(std430)]
+/// struct Transform {
+///     matrix: [[f32; 4]; 2],
+/// }
+/// ```
+///
+/// # Safety
+///
+/// `ALIGN` must be the type's alignment under `std430`.
+pub unsafe trait Std430Type: ShaderType {
+    /// The alignment of this type in a `std430` block.
+    const ALIGN: usize;
+}
+
+macro_rules! impl_shader_type {
+    (@rules $ty:ty, $rules:ident, -) => {};
+    (@rules $ty:ty, $rules:ident, $align:expr) => {
+        unsafe impl $rules for $ty {
+            const ALIGN: usize = $align;
+        }
+    };
+    ($($ty:ty => $std140:tt, $std430:tt;)*) => {
+        $(
+            unsafe impl ShaderType for $ty {}
+            impl_shader_type!(@rules $ty, Std140Type, $std140);
+            impl_shader_type!(@rules $ty, Std430Type, $std430);
+        )*
+    };
+}
+
+// A `-` means the type isn't allowed under those rules.
+impl_shader_type! {
+    f32 => 4, 4;
+    i32 => 4, 4;
+    u32 => 4, 4;
+    [f32; 2] => 8, 8;
+    [i32; 2] => 8, 8;
+    [u32; 2] => 8, 8;
+    [f32; 3] => 16, 16;
+    [i32; 3] => 16, 16;
+    [u32; 3] => 16, 16;
+    [f32; 4] => 16, 16;
+    [i32; 4] => 16, 16;
+    [u32; 4] => 16, 16;
+    [[f32; 2]; 2] => -, 8;
+    [[f32; 4]; 2] => 16, -;
+    [[f32; 4]; 3] => 16, 16;
+    [[f32; 4]; 4] => 16, 16;
+    [[f32; 4]; 9] => 16, 16;
+}
+
+/// Round `offset` up to a multiple of `alignment`, which must be a power of
+/// two. Used by the derive macros.
+#[doc(hidden)]
+pub const fn align_up(offset: usize, alignment: usize) -> usize {
+    let mask = alignment - 1;
+    (offset + mask) & !mask
+}
+
+/// A struct whose layout matches a GLSL block.
+///
+/// This should be derived rather than implemented by hand.
+///
+/// # Safety
+///
+/// Implementors must be `repr(C)`, made entirely of `ShaderType` fields,
+/// and have no padding.
+pub unsafe trait ShaderData: Copy {
+    /// The layout of this struct, for checking against a shader's block.
+    fn block_layout() -> BlockLayout;
+
+    /// A view of this struct as a slice of `u32`s, which is how push
+    /// constants are passed to gfx-hal.
+    fn as_words(&self) -> &[u32] {
+        let len = std::mem::size_of::<Self>() / std::mem::size_of::<u32>();
+
+        // This is safe because every field is made of 4-byte scalars, and
+        // the derive macro checks that there's no padding between them.
+        unsafe { std::slice::from_raw_parts(self as *const Self as *const u32, len) }
+    }
+}
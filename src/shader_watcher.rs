@@ -0,0 +1,173 @@
+//! Recompiling shaders when they change on disk.
+//!
+//! Normally the shaders are compiled once by the build script and embedded
+//! in the binary. While working on them, it's much quicker to see changes
+//! without restarting, so a `ShaderWatcher` keeps an eye on the shader
+//! directory and recompiles any file that changes.
+//!
+//! There's no portable way to be notified of file changes in the standard
+//! library, so we just check the modification times every so often.
+//!
+//! We don't keep track of which shaders include which files, so when a file
+//! that isn't a shader changes, we assume it's included by all of them and
+//! recompile everything. There aren't many shaders, so this is quick enough.
+//!
+//! Variants are recompiled along with their shader. When the pipeline
+//! configs change, the watcher has to be told their new `ShaderSettings`.
+//! Any new variants, and shaders whose entry point changed, are compiled
+//! straight away.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::shader_compiler::{
+    compile_file, compile_jobs, constant_name, shader_kind, ShaderSettings,
+};
+
+/// How long to wait between checking for changes.
+pub(crate) const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Watches a directory of shaders, and recompiles them when they change.
+pub struct ShaderWatcher {
+    dir: PathBuf,
+    compiler: shaderc::Compiler,
+    modified: HashMap<PathBuf, SystemTime>,
+    last_poll: Instant,
+    settings: ShaderSettings,
+
+    /// The constant names of shaders that haven't been compiled with the
+    /// latest settings yet.
+    outdated: Vec<String>,
+}
+
+impl ShaderWatcher {
+    /// Start watching the shaders in `dir`, which are already compiled with
+    /// `settings`. Only changes made after this are picked up.
+    pub fn new(dir: impl Into<PathBuf>, settings: ShaderSettings) -> Self {
+        let mut watcher = ShaderWatcher {
+            dir: dir.into(),
+            compiler: shaderc::Compiler::new().expect("Failed to create shader compiler"),
+            modified: HashMap::new(),
+            last_poll: Instant::now(),
+            settings,
+            outdated: vec![],
+        };
+
+        // Record the current modification times, so that only later changes
+        // count.
+        watcher.changed_files();
+        watcher
+    }
+
+    /// Replace the settings to compile shaders with. Any variants that
+    /// weren't there before, and any shaders (and their variants) whose
+    /// entry point has changed, are compiled on the next `poll`.
+    pub fn set_settings(&mut self, settings: ShaderSettings) {
+        for variant in &settings.variants {
+            if !self.settings.variants.contains(variant) {
+                self.outdated.push(variant.constant_name());
+            }
+        }
+
+        let file_names = settings.entry_points.keys();
+        for file_name in file_names.chain(self.settings.entry_points.keys()) {
+            if settings.entry_point(file_name) != self.settings.entry_point(file_name) {
+                self.outdated.push(constant_name(file_name, ""));
+                self.outdated.extend(
+                    settings
+                        .variants
+                        .iter()
+                        .filter(|variant| &variant.file_name == file_name)
+                        .map(|variant| variant.constant_name()),
+                );
+            }
+        }
+
+        self.settings = settings;
+    }
+
+    /// Recompile every shader affected by changes since the last call, and
+    /// return the ones that compiled successfully. Each one is named after
+    /// the constant it's embedded as in `shaders`, like `TEAPOT_FRAG`.
+    ///
+    /// Compile errors are printed, and the shader is left out, so that the
+    /// caller can carry on using the old version.
+    pub fn poll(&mut self) -> Vec<(String, Vec<u32>)> {
+        if self.last_poll.elapsed() < POLL_INTERVAL && self.outdated.is_empty() {
+            return vec![];
+        }
+        self.last_poll = Instant::now();
+
+        let changed = self.changed_files();
+        let outdated = std::mem::take(&mut self.outdated);
+        if changed.is_empty() && outdated.is_empty() {
+            return vec![];
+        }
+
+        let jobs = match compile_jobs(&self.dir, &self.settings) {
+            Ok(jobs) => jobs,
+            Err(error) => {
+                eprintln!("Failed to read {}: {}", self.dir.display(), error);
+                return vec![];
+            }
+        };
+
+        let include_changed = changed.iter().any(|path| shader_kind(path).is_none());
+        let mut compiled = vec![];
+
+        for job in jobs {
+            let wanted =
+                include_changed || changed.contains(&job.path) || outdated.contains(&job.name);
+            if !wanted {
+                continue;
+            }
+
+            match compile_file(
+                &mut self.compiler,
+                &job.path,
+                &job.entry_point,
+                &job.defines,
+            ) {
+                Ok(shader) => {
+                    if !shader.warnings.is_empty() {
+                        eprintln!("{}", shader.warnings);
+                    }
+
+                    println!("Reloaded {}", job.name);
+                    compiled.push((job.name, shader.spirv));
+                }
+                Err(error) => eprintln!("Failed to reload {}:\n{}", job.name, error),
+            }
+        }
+
+        compiled
+    }
+
+    /// Find the files whose modification time has changed since we last
+    /// looked.
+    fn changed_files(&mut self) -> Vec<PathBuf> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(error) => {
+                eprintln!("Failed to read {}: {}", self.dir.display(), error);
+                return vec![];
+            }
+        };
+
+        let mut changed = vec![];
+
+        for entry in entries.filter_map(Result::ok) {
+            let path = entry.path();
+            let modified = entry.metadata().and_then(|metadata| metadata.modified());
+
+            if let Ok(modified) = modified {
+                if self.modified.insert(path.clone(), modified) != Some(modified) {
+                    changed.push(path);
+                }
+            }
+        }
+
+        changed
+    }
+}
@@ -0,0 +1,8 @@
+//! The shaders in `src/bin/shaders`, compiled to SPIR-V.
+//!
+//! The build script compiles every shader when the crate is built, and
+//! generates a constant for each one, named after its file. For example,
+//! `teapot.vert` becomes `TEAPOT_VERT`. They're also listed in `ALL`, so
+//! they can be looked up by name.
+
+include!(concat!(env!("OUT_DIR"), "/shaders.rs"));
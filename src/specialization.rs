@@ -0,0 +1,127 @@
+//! Typed specialization constants.
+//!
+//! A specialization constant is a constant in a shader whose value is
+//! filled in when the pipeline is created, rather than when the shader is
+//! compiled:
+//!
+//! ```glsl
+//! layout(constant_id = 0) const uint LIGHT_COUNT = 1;
+//! ```
+//!
+//! The driver can still optimize around the value, so one shader can be
+//! turned into several pipelines without recompiling any GLSL. gfx-hal takes
+//! the values as raw bytes, so `SpecConstants` builds those bytes from typed
+//! values instead, and remembers each type so it can be checked against the
+//! shader with `reflect::check_specialization`.
+
+use std::borrow::Cow;
+
+use gfx_hal::pso::{Specialization, SpecializationConstant};
+
+/// The types a specialization constant can have.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpecConstantType {
+    Bool,
+    Int,
+    Uint,
+    Float,
+}
+
+/// A type that can be used as the value of a specialization constant.
+pub trait SpecConstantValue: Copy {
+    const TYPE: SpecConstantType;
+
+    /// The value's bytes, as the shader reads them.
+    fn to_bytes(self) -> [u8; 4];
+}
+
+impl SpecConstantValue for bool {
+    const TYPE: SpecConstantType = SpecConstantType::Bool;
+
+    // Booleans are 32 bits wide in shaders.
+    fn to_bytes(self) -> [u8; 4] {
+        (self as u32).to_ne_bytes()
+    }
+}
+
+impl SpecConstantValue for i32 {
+    const TYPE: SpecConstantType = SpecConstantType::Int;
+
+    fn to_bytes(self) -> [u8; 4] {
+        self.to_ne_bytes()
+    }
+}
+
+impl SpecConstantValue for u32 {
+    const TYPE: SpecConstantType = SpecConstantType::Uint;
+
+    fn to_bytes(self) -> [u8; 4] {
+        self.to_ne_bytes()
+    }
+}
+
+impl SpecConstantValue for f32 {
+    const TYPE: SpecConstantType = SpecConstantType::Float;
+
+    fn to_bytes(self) -> [u8; 4] {
+        self.to_ne_bytes()
+    }
+}
+
+/// The values of the specialization constants for one shader stage.
+///
+/// Constants that aren't given a value keep the default from the shader.
+#[derive(Debug, Clone, Default)]
+pub struct SpecConstants {
+    constants: Vec<SpecializationConstant>,
+    types: Vec<SpecConstantType>,
+    data: Vec<u8>,
+}
+
+impl SpecConstants {
+    /// No constants, so every one keeps its default.
+    pub fn new() -> Self {
+        SpecConstants::default()
+    }
+
+    /// Set the constant with the given `constant_id`. Setting the same ID
+    /// twice replaces the earlier value.
+    pub fn with<T: SpecConstantValue>(mut self, id: u32, value: T) -> Self {
+        let bytes = value.to_bytes();
+
+        match self.constants.iter().position(|constant| constant.id == id) {
+            Some(index) => {
+                let start = self.constants[index].range.start as usize;
+                self.data[start..start + bytes.len()].copy_from_slice(&bytes);
+                self.types[index] = T::TYPE;
+            }
+            None => {
+                let start = self.data.len() as u16;
+                self.data.extend_from_slice(&bytes);
+                self.constants.push(SpecializationConstant {
+                    id,
+                    range: start..start + bytes.len() as u16,
+                });
+                self.types.push(T::TYPE);
+            }
+        }
+
+        self
+    }
+
+    /// The IDs and types of the constants that have been set.
+    pub fn types(&self) -> impl Iterator<Item = (u32, SpecConstantType)> + '_ {
+        self.constants
+            .iter()
+            .zip(&self.types)
+            .map(|(constant, &ty)| (constant.id, ty))
+    }
+
+    /// The constants in the form gfx-hal takes them, for an `EntryPoint`.
+    pub fn specialization(&self) -> Specialization<'_> {
+        Specialization {
+            constants: Cow::Borrowed(&self.constants),
+            data: Cow::Borrowed(&self.data),
+        }
+    }
+}
@@ -0,0 +1,481 @@
+//! A small SPIR-V parser, for reflecting on compiled shaders.
+//!
+//! This only understands the handful of instructions we need to find out
+//! which resources a shader uses and how its blocks are laid out: names,
+//! decorations, types, constants, and global variables. Everything else is
+//! skipped.
+
+use std::collections::HashMap;
+
+const MAGIC_NUMBER: u32 = 0x0723_0203;
+const HEADER_LEN: usize = 5;
+
+mod op {
+    pub const NAME: u16 = 5;
+    pub const MEMBER_NAME: u16 = 6;
+    pub const ENTRY_POINT: u16 = 15;
+    pub const TYPE_BOOL: u16 = 20;
+    pub const TYPE_INT: u16 = 21;
+    pub const TYPE_FLOAT: u16 = 22;
+    pub const TYPE_VECTOR: u16 = 23;
+    pub const TYPE_MATRIX: u16 = 24;
+    pub const TYPE_IMAGE: u16 = 25;
+    pub const TYPE_SAMPLER: u16 = 26;
+    pub const TYPE_SAMPLED_IMAGE: u16 = 27;
+    pub const TYPE_ARRAY: u16 = 28;
+    pub const TYPE_RUNTIME_ARRAY: u16 = 29;
+    pub const TYPE_STRUCT: u16 = 30;
+    pub const TYPE_POINTER: u16 = 32;
+    pub const CONSTANT: u16 = 43;
+    pub const SPEC_CONSTANT_TRUE: u16 = 48;
+    pub const SPEC_CONSTANT_FALSE: u16 = 49;
+    pub const SPEC_CONSTANT: u16 = 50;
+    pub const VARIABLE: u16 = 59;
+    pub const DECORATE: u16 = 71;
+    pub const MEMBER_DECORATE: u16 = 72;
+}
+
+/// The decorations we look at.
+pub mod decoration {
+    pub const SPEC_ID: u32 = 1;
+    pub const BLOCK: u32 = 2;
+    pub const BUFFER_BLOCK: u32 = 3;
+    pub const ARRAY_STRIDE: u32 = 6;
+    pub const MATRIX_STRIDE: u32 = 7;
+    pub const BUILT_IN: u32 = 11;
+    pub const NON_WRITABLE: u32 = 24;
+    pub const LOCATION: u32 = 30;
+    pub const BINDING: u32 = 33;
+    pub const DESCRIPTOR_SET: u32 = 34;
+    pub const OFFSET: u32 = 35;
+}
+
+/// The storage classes we look at.
+pub mod storage_class {
+    pub const UNIFORM_CONSTANT: u32 = 0;
+    pub const INPUT: u32 = 1;
+    pub const UNIFORM: u32 = 2;
+    pub const PUSH_CONSTANT: u32 = 9;
+    pub const STORAGE_BUFFER: u32 = 12;
+}
+
+/// The image dimensions we look at.
+pub mod dim {
+    pub const BUFFER: u32 = 5;
+    pub const SUBPASS_DATA: u32 = 6;
+}
+
+/// A type declared in a module.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Type {
+    Bool,
+    Int { width: u32, signed: bool },
+    Float { width: u32 },
+    Vector { component: u32, count: u32 },
+    Matrix { column: u32, count: u32 },
+    Image { dim: u32, sampled: u32 },
+    Sampler,
+    SampledImage { image: u32 },
+    Array { element: u32, length: u32 },
+    RuntimeArray { element: u32 },
+    Struct { members: Vec<u32> },
+    Pointer { storage_class: u32, pointee: u32 },
+}
+
+/// A global variable declared in a module.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Variable {
+    pub id: u32,
+    pub pointer_type: u32,
+    pub storage_class: u32,
+}
+
+/// A specialization constant declared in a module. Its specialization ID is
+/// in a `SPEC_ID` decoration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpecConstant {
+    pub id: u32,
+    pub result_type: u32,
+}
+
+/// An entry point declared in a module.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryPoint {
+    pub execution_model: u32,
+    pub name: String,
+}
+
+/// The parts of a SPIR-V module that we care about.
+#[derive(Debug, Default)]
+pub struct Module {
+    pub entry_points: Vec<EntryPoint>,
+    pub names: HashMap<u32, String>,
+    pub member_names: HashMap<(u32, u32), String>,
+    pub types: HashMap<u32, Type>,
+    pub constants: HashMap<u32, u32>,
+    pub variables: Vec<Variable>,
+    pub spec_constants: Vec<SpecConstant>,
+    decorations: HashMap<(u32, u32), Vec<u32>>,
+    member_decorations: HashMap<(u32, u32, u32), Vec<u32>>,
+}
+
+impl Module {
+    /// Parse a SPIR-V module from its words.
+    pub fn parse(words: &[u32]) -> Result<Module, String> {
+        if words.len() < HEADER_LEN || words[0] != MAGIC_NUMBER {
+            return Err("Not a SPIR-V module".to_owned());
+        }
+
+        let mut module = Module::default();
+        let mut remaining = &words[HEADER_LEN..];
+
+        while !remaining.is_empty() {
+            let word_count = (remaining[0] >> 16) as usize;
+            let opcode = (remaining[0] & 0xffff) as u16;
+
+            if word_count == 0 || word_count > remaining.len() {
+                return Err(format!("Malformed instruction with opcode {}", opcode));
+            }
+
+            module.parse_instruction(opcode, &remaining[1..word_count]);
+            remaining = &remaining[word_count..];
+        }
+
+        Ok(module)
+    }
+
+    fn parse_instruction(&mut self, opcode: u16, operands: &[u32]) {
+        let operand = |index: usize| operands.get(index).copied().unwrap_or(0);
+
+        match opcode {
+            op::NAME if !operands.is_empty() => {
+                self.names.insert(operand(0), parse_string(&operands[1..]));
+            }
+            op::MEMBER_NAME if operands.len() >= 2 => {
+                self.member_names
+                    .insert((operand(0), operand(1)), parse_string(&operands[2..]));
+            }
+            op::ENTRY_POINT if operands.len() >= 2 => {
+                self.entry_points.push(EntryPoint {
+                    execution_model: operand(0),
+                    name: parse_string(&operands[2..]),
+                });
+            }
+            op::TYPE_BOOL => {
+                self.types.insert(operand(0), Type::Bool);
+            }
+            op::TYPE_INT => {
+                let (width, signed) = (operand(1), operand(2) != 0);
+                self.types.insert(operand(0), Type::Int { width, signed });
+            }
+            op::TYPE_FLOAT => {
+                self.types
+                    .insert(operand(0), Type::Float { width: operand(1) });
+            }
+            op::TYPE_VECTOR => {
+                let (component, count) = (operand(1), operand(2));
+                self.types
+                    .insert(operand(0), Type::Vector { component, count });
+            }
+            op::TYPE_MATRIX => {
+                let (column, count) = (operand(1), operand(2));
+                self.types
+                    .insert(operand(0), Type::Matrix { column, count });
+            }
+            op::TYPE_IMAGE => {
+                let (dim, sampled) = (operand(2), operand(6));
+                self.types.insert(operand(0), Type::Image { dim, sampled });
+            }
+            op::TYPE_SAMPLER => {
+                self.types.insert(operand(0), Type::Sampler);
+            }
+            op::TYPE_SAMPLED_IMAGE => {
+                self.types
+                    .insert(operand(0), Type::SampledImage { image: operand(1) });
+            }
+            op::TYPE_ARRAY => {
+                // The length is the ID of a constant, which we resolve later.
+                let (element, length) = (operand(1), operand(2));
+                self.types
+                    .insert(operand(0), Type::Array { element, length });
+            }
+            op::TYPE_RUNTIME_ARRAY => {
+                self.types.insert(
+                    operand(0),
+                    Type::RuntimeArray {
+                        element: operand(1),
+                    },
+                );
+            }
+            op::TYPE_STRUCT if !operands.is_empty() => {
+                let members = operands[1..].to_vec();
+                self.types.insert(operand(0), Type::Struct { members });
+            }
+            op::TYPE_POINTER => {
+                let (storage_class, pointee) = (operand(1), operand(2));
+                self.types.insert(
+                    operand(0),
+                    Type::Pointer {
+                        storage_class,
+                        pointee,
+                    },
+                );
+            }
+            op::CONSTANT => {
+                self.constants.insert(operand(1), operand(2));
+            }
+            op::SPEC_CONSTANT_TRUE | op::SPEC_CONSTANT_FALSE | op::SPEC_CONSTANT => {
+                self.spec_constants.push(SpecConstant {
+                    result_type: operand(0),
+                    id: operand(1),
+                });
+            }
+            op::VARIABLE => {
+                self.variables.push(Variable {
+                    pointer_type: operand(0),
+                    id: operand(1),
+                    storage_class: operand(2),
+                });
+            }
+            op::DECORATE if operands.len() >= 2 => {
+                self.decorations
+                    .insert((operand(0), operand(1)), operands[2..].to_vec());
+            }
+            op::MEMBER_DECORATE if operands.len() >= 3 => {
+                self.member_decorations
+                    .insert((operand(0), operand(1), operand(2)), operands[3..].to_vec());
+            }
+            _ => (),
+        }
+    }
+
+    /// The name of an ID, or an empty string if it doesn't have one.
+    pub fn name(&self, id: u32) -> &str {
+        self.names.get(&id).map(String::as_str).unwrap_or("")
+    }
+
+    /// The name of a struct member, or an empty string if it doesn't have
+    /// one.
+    pub fn member_name(&self, struct_id: u32, member: u32) -> &str {
+        self.member_names
+            .get(&(struct_id, member))
+            .map(String::as_str)
+            .unwrap_or("")
+    }
+
+    /// Returns true if the ID has the given decoration.
+    pub fn has_decoration(&self, id: u32, decoration: u32) -> bool {
+        self.decorations.contains_key(&(id, decoration))
+    }
+
+    /// The first operand of a decoration on an ID, if it has one.
+    pub fn decoration(&self, id: u32, decoration: u32) -> Option<u32> {
+        self.decorations
+            .get(&(id, decoration))
+            .and_then(|operands| operands.first().copied())
+    }
+
+    /// Whether a struct member has a decoration.
+    pub fn has_member_decoration(&self, struct_id: u32, member: u32, decoration: u32) -> bool {
+        self.member_decorations
+            .contains_key(&(struct_id, member, decoration))
+    }
+
+    /// The first operand of a decoration on a struct member, if it has one.
+    pub fn member_decoration(&self, struct_id: u32, member: u32, decoration: u32) -> Option<u32> {
+        self.member_decorations
+            .get(&(struct_id, member, decoration))
+            .and_then(|operands| operands.first().copied())
+    }
+
+    /// The type that a pointer type points to, or the type itself if it
+    /// isn't a pointer.
+    pub fn pointee(&self, type_id: u32) -> u32 {
+        match self.types.get(&type_id) {
+            Some(Type::Pointer { pointee, .. }) => *pointee,
+            _ => type_id,
+        }
+    }
+
+    /// The size in bytes of a type, as laid out in a block.
+    ///
+    /// Matrices need a stride, which is decorated on the struct member that
+    /// contains them, so it has to be passed in. Runtime arrays have no fixed
+    /// size, so they count as zero bytes.
+    pub fn size_of(&self, type_id: u32, matrix_stride: Option<u32>) -> u32 {
+        match self.types.get(&type_id) {
+            Some(Type::Bool) => 4,
+            Some(Type::Int { width, .. }) | Some(Type::Float { width }) => width / 8,
+            Some(Type::Vector { component, count }) => self.size_of(*component, None) * count,
+            Some(Type::Matrix { column, count }) => {
+                let stride = matrix_stride.unwrap_or_else(|| self.size_of(*column, None));
+                stride * count
+            }
+            Some(Type::Array { element, length }) => {
+                let length = self.constants.get(length).copied().unwrap_or(0);
+                let stride = self
+                    .decoration(type_id, decoration::ARRAY_STRIDE)
+                    .unwrap_or_else(|| self.size_of(*element, matrix_stride));
+                stride * length
+            }
+            Some(Type::Struct { members }) => members
+                .iter()
+                .enumerate()
+                .map(|(index, &member)| {
+                    let index = index as u32;
+                    let offset = self
+                        .member_decoration(type_id, index, decoration::OFFSET)
+                        .unwrap_or(0);
+                    let stride = self.member_decoration(type_id, index, decoration::MATRIX_STRIDE);
+                    offset + self.size_of(member, stride)
+                })
+                .max()
+                .unwrap_or(0),
+            _ => 0,
+        }
+    }
+}
+
+/// Read a nul-terminated UTF-8 string packed into words.
+fn parse_string(words: &[u32]) -> String {
+    let bytes: Vec<u8> = words
+        .iter()
+        .flat_map(|word| word.to_le_bytes().to_vec())
+        .take_while(|&byte| byte != 0)
+        .collect();
+
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compile(source: &str) -> Vec<u32> {
+        let mut compiler = shaderc::Compiler::new().unwrap();
+        compiler
+            .compile_into_spirv(
+                source,
+                shaderc::ShaderKind::Fragment,
+                "test.frag",
+                "main",
+                None,
+            )
+            .unwrap()
+            .as_binary()
+            .to_vec()
+    }
+
+    const SHADER: &str = "
+        #version 450
+        layout(constant_id = 3) const int COUNT = 2;
+        layout(std140, set = 1, binding = 2) uniform Globals {
+            float values[4];
+            mat3 rotation;
+            vec3 light;
+        } globals;
+        layout(location = 0) out vec4 color;
+        void main() {
+            color = vec4(globals.rotation * globals.light * globals.values[COUNT], 1.0);
+        }
+    ";
+
+    fn struct_named<'a>(module: &'a Module, name: &str) -> (u32, &'a [u32]) {
+        module
+            .types
+            .iter()
+            .find_map(|(&id, ty)| match ty {
+                Type::Struct { members } if module.name(id) == name => Some((id, &members[..])),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn rejects_things_that_arent_spirv() {
+        assert!(Module::parse(&[]).is_err());
+        assert!(Module::parse(&[0xdead_beef, 0, 0, 0, 0]).is_err());
+
+        // An instruction claiming to be zero words long would never end.
+        let message = Module::parse(&[MAGIC_NUMBER, 0, 0, 0, 0, u32::from(op::NAME)]).unwrap_err();
+        assert_eq!(message, "Malformed instruction with opcode 5");
+
+        // As would one running past the end of the module.
+        let word = (3 << 16) | u32::from(op::NAME);
+        assert!(Module::parse(&[MAGIC_NUMBER, 0, 0, 0, 0, word, 1]).is_err());
+    }
+
+    #[test]
+    fn parses_an_empty_module() {
+        let module = Module::parse(&[MAGIC_NUMBER, 0, 0, 0, 0]).unwrap();
+        assert!(module.entry_points.is_empty());
+        assert!(module.types.is_empty());
+    }
+
+    #[test]
+    fn parses_entry_points_and_names() {
+        let module = Module::parse(&compile(SHADER)).unwrap();
+
+        assert_eq!(module.entry_points.len(), 1);
+        assert_eq!(module.entry_points[0].name, "main");
+
+        let (globals, members) = struct_named(&module, "Globals");
+        assert_eq!(members.len(), 3);
+        assert_eq!(module.member_name(globals, 0), "values");
+        assert_eq!(module.member_name(globals, 2), "light");
+        assert_eq!(module.member_name(globals, 3), "");
+        assert!(module.has_decoration(globals, decoration::BLOCK));
+    }
+
+    #[test]
+    fn parses_variables_and_decorations() {
+        let module = Module::parse(&compile(SHADER)).unwrap();
+
+        let globals = module
+            .variables
+            .iter()
+            .find(|variable| module.name(variable.id) == "globals")
+            .unwrap();
+        assert_eq!(globals.storage_class, storage_class::UNIFORM);
+        assert_eq!(
+            module.decoration(globals.id, decoration::DESCRIPTOR_SET),
+            Some(1)
+        );
+        assert_eq!(module.decoration(globals.id, decoration::BINDING), Some(2));
+
+        let (struct_id, _) = struct_named(&module, "Globals");
+        assert_eq!(module.pointee(globals.pointer_type), struct_id);
+        assert_eq!(module.pointee(struct_id), struct_id);
+
+        assert_eq!(module.spec_constants.len(), 1);
+        let constant = &module.spec_constants[0];
+        assert_eq!(module.name(constant.id), "COUNT");
+        assert_eq!(module.decoration(constant.id, decoration::SPEC_ID), Some(3));
+        assert_eq!(
+            module.types.get(&constant.result_type),
+            Some(&Type::Int {
+                width: 32,
+                signed: true
+            })
+        );
+    }
+
+    #[test]
+    fn sizes_use_array_and_matrix_strides() {
+        let module = Module::parse(&compile(SHADER)).unwrap();
+        let (globals, members) = struct_named(&module, "Globals");
+
+        // In std140, array elements and matrix columns are padded to 16 bytes.
+        let offset = |member| module.member_decoration(globals, member, decoration::OFFSET);
+        assert_eq!(offset(0), Some(0));
+        assert_eq!(offset(1), Some(64));
+        assert_eq!(offset(2), Some(112));
+
+        let stride = module.member_decoration(globals, 1, decoration::MATRIX_STRIDE);
+        assert_eq!(stride, Some(16));
+        assert_eq!(module.size_of(members[0], None), 64);
+        assert_eq!(module.size_of(members[1], stride), 48);
+        assert_eq!(module.size_of(members[2], None), 12);
+        assert_eq!(module.size_of(globals, None), 124);
+    }
+}
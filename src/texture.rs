@@ -0,0 +1,699 @@
+//! Textures, and other images like depth buffers.
+
+use gfx_hal::{
+    adapter::PhysicalDevice, device::Device, format::Format, image::Level, memory::Properties,
+    Backend,
+};
+use image::RgbaImage;
+
+use crate::buffer::{find_memory_type, make_buffer};
+use crate::command::submit_and_wait;
+
+/// An image on the GPU, along with its memory and a view for sampling it.
+pub struct Texture<B: Backend> {
+    pub memory: B::Memory,
+    pub image: B::Image,
+    pub view: B::ImageView,
+    pub mip_levels: Level,
+}
+
+impl<B: Backend> Texture<B> {
+    /// Destroy the image, its view, and free its memory.
+    pub unsafe fn destroy(self, device: &B::Device) {
+        device.destroy_image_view(self.view);
+        device.destroy_image(self.image);
+        device.free_memory(self.memory);
+    }
+}
+
+/// The number of levels in a full mip chain for an image of the given size.
+///
+/// Each level is half the size of the previous one (rounding down) until
+/// both dimensions reach `1`.
+pub fn mip_levels_for(width: u32, height: u32) -> Level {
+    (32 - width.max(height).max(1).leading_zeros()) as Level
+}
+
+/// Returns true if the device can generate mipmaps for images of the given
+/// format by blitting between levels with linear filtering.
+pub fn supports_linear_blit<B: Backend>(
+    physical_device: &B::PhysicalDevice,
+    format: Format,
+) -> bool {
+    use gfx_hal::format::ImageFeature;
+
+    physical_device
+        .format_properties(Some(format))
+        .optimal_tiling
+        .contains(ImageFeature::BLIT_SRC | ImageFeature::BLIT_DST | ImageFeature::SAMPLED_LINEAR)
+}
+
+/// Generate every mip level after the first on the CPU.
+///
+/// Each level is downsampled from the one before it. Note that the `image`
+/// crate filters the raw (sRGB-encoded) values, which is slightly less
+/// accurate than the GPU path, but close enough for a fallback.
+pub fn generate_mip_chain(pixels: &RgbaImage, mip_levels: Level) -> Vec<RgbaImage> {
+    use image::imageops::{resize, FilterType};
+
+    let mut levels: Vec<RgbaImage> = vec![];
+
+    for level in 1..mip_levels {
+        let width = (pixels.width() >> level).max(1);
+        let height = (pixels.height() >> level).max(1);
+        let previous = levels.last().unwrap_or(pixels);
+        let next = resize(previous, width, height, FilterType::Triangle);
+        levels.push(next);
+    }
+
+    levels
+}
+
+/// Upload an RGBA image to the GPU, with a full mip chain, ready to be
+/// sampled in a shader.
+///
+/// The pixels are first copied into a CPU-visible staging buffer, then
+/// copied on the GPU into a device-local image with optimal tiling. The
+/// rest of the mip chain is then generated on the GPU if the format allows
+/// it, or on the CPU otherwise.
+///
+/// Because this waits for the upload to finish, it's only suitable for use
+/// during initialization.
+pub unsafe fn upload_texture<B: Backend>(
+    device: &B::Device,
+    physical_device: &B::PhysicalDevice,
+    command_pool: &mut B::CommandPool,
+    queue: &mut B::CommandQueue,
+    pixels: &RgbaImage,
+    format: Format,
+) -> Texture<B> {
+    use gfx_hal::image::ViewKind;
+
+    upload_layers::<B>(
+        device,
+        physical_device,
+        command_pool,
+        queue,
+        &[pixels],
+        format,
+        ViewKind::D2,
+    )
+}
+
+/// Upload six square RGBA images to the GPU as the faces of a cube map,
+/// with a full mip chain.
+///
+/// The faces must be in the order given by `cube::FACES`. To make them from
+/// an equirectangular image, use `cube::faces_from_equirect`.
+pub unsafe fn upload_cube_texture<B: Backend>(
+    device: &B::Device,
+    physical_device: &B::PhysicalDevice,
+    command_pool: &mut B::CommandPool,
+    queue: &mut B::CommandQueue,
+    faces: &[RgbaImage; 6],
+    format: Format,
+) -> Texture<B> {
+    use gfx_hal::image::ViewKind;
+
+    let faces: Vec<&RgbaImage> = faces.iter().collect();
+
+    upload_layers::<B>(
+        device,
+        physical_device,
+        command_pool,
+        queue,
+        &faces,
+        format,
+        ViewKind::Cube,
+    )
+}
+
+/// Upload each of the given images to a layer of a single image, and create
+/// a view of the given kind for all of them.
+///
+/// Every layer must have the same dimensions.
+unsafe fn upload_layers<B: Backend>(
+    device: &B::Device,
+    physical_device: &B::PhysicalDevice,
+    command_pool: &mut B::CommandPool,
+    queue: &mut B::CommandQueue,
+    layers: &[&RgbaImage],
+    format: Format,
+    view_kind: gfx_hal::image::ViewKind,
+) -> Texture<B> {
+    use gfx_hal::format::{Aspects, Swizzle};
+    use gfx_hal::image::{
+        Access, Kind, Layer, Layout, SubresourceRange, Tiling, Usage, ViewCapabilities, ViewKind,
+    };
+    use gfx_hal::memory::{Barrier, Dependencies};
+    use gfx_hal::pso::PipelineStage;
+
+    let (width, height) = layers[0].dimensions();
+    let layer_count = layers.len() as Layer;
+    let mip_levels = mip_levels_for(width, height);
+
+    assert!(
+        layers
+            .iter()
+            .all(|pixels| pixels.dimensions() == (width, height)),
+        "Every layer of a texture must be the same size"
+    );
+
+    // If we can't blit on the GPU, we stage every mip level instead of just
+    // the first one.
+    let generate_on_gpu = supports_linear_blit::<B>(physical_device, format);
+    let cpu_mips: Vec<Vec<RgbaImage>> = if generate_on_gpu {
+        vec![]
+    } else {
+        layers
+            .iter()
+            .map(|pixels| generate_mip_chain(pixels, mip_levels))
+            .collect()
+    };
+
+    let mut images = vec![];
+    for (layer, &pixels) in layers.iter().enumerate() {
+        let mips = cpu_mips.get(layer).map(Vec::as_slice).unwrap_or(&[]);
+        for (level, pixels) in std::iter::once(pixels).chain(mips).enumerate() {
+            images.push((pixels, level as Level, layer as Layer));
+        }
+    }
+
+    let (staging_memory, staging_buffer, regions) =
+        stage_images::<B>(device, physical_device, &images);
+
+    let view_caps = if view_kind == ViewKind::Cube {
+        ViewCapabilities::KIND_CUBE
+    } else {
+        ViewCapabilities::empty()
+    };
+
+    let mut image = device
+        .create_image(
+            Kind::D2(width, height, layer_count, 1),
+            mip_levels,
+            format,
+            Tiling::Optimal,
+            Usage::TRANSFER_SRC | Usage::TRANSFER_DST | Usage::SAMPLED,
+            view_caps,
+        )
+        .expect("Failed to create image");
+
+    let req = device.get_image_requirements(&image);
+    let memory_type =
+        find_memory_type::<B>(physical_device, req.type_mask, Properties::DEVICE_LOCAL);
+
+    let memory = device
+        .allocate_memory(memory_type, req.size)
+        .expect("Failed to allocate image memory");
+
+    device
+        .bind_image_memory(&memory, 0, &mut image)
+        .expect("Failed to bind image memory");
+
+    let everything = SubresourceRange {
+        aspects: Aspects::COLOR,
+        level_start: 0,
+        level_count: Some(mip_levels),
+        layer_start: 0,
+        layer_count: Some(layer_count),
+    };
+
+    submit_and_wait::<B, _>(device, command_pool, queue, |command_buffer| {
+        use gfx_hal::command::CommandBuffer;
+
+        // The image starts out in an undefined layout, so we first transition
+        // it to a layout that we can copy into.
+        command_buffer.pipeline_barrier(
+            PipelineStage::TOP_OF_PIPE..PipelineStage::TRANSFER,
+            Dependencies::empty(),
+            &[Barrier::Image {
+                states: (Access::empty(), Layout::Undefined)
+                    ..(Access::TRANSFER_WRITE, Layout::TransferDstOptimal),
+                target: &image,
+                families: None,
+                range: everything.clone(),
+            }],
+        );
+
+        command_buffer.copy_buffer_to_image(
+            &staging_buffer,
+            &image,
+            Layout::TransferDstOptimal,
+            &regions,
+        );
+
+        if generate_on_gpu {
+            record_mip_blits::<B>(
+                command_buffer,
+                &image,
+                width,
+                height,
+                mip_levels,
+                layer_count,
+            );
+        } else {
+            // Once the copy is done, we transition the whole image to a
+            // layout that's optimal for reading from in a fragment shader.
+            command_buffer.pipeline_barrier(
+                PipelineStage::TRANSFER..PipelineStage::FRAGMENT_SHADER,
+                Dependencies::empty(),
+                &[Barrier::Image {
+                    states: (Access::TRANSFER_WRITE, Layout::TransferDstOptimal)
+                        ..(Access::SHADER_READ, Layout::ShaderReadOnlyOptimal),
+                    target: &image,
+                    families: None,
+                    range: everything.clone(),
+                }],
+            );
+        }
+    });
+
+    device.destroy_buffer(staging_buffer);
+    device.free_memory(staging_memory);
+
+    let view = device
+        .create_image_view(&image, view_kind, format, Swizzle::NO, everything)
+        .expect("Failed to create image view");
+
+    Texture {
+        memory,
+        image,
+        view,
+        mip_levels,
+    }
+}
+
+/// Copy each image into one staging buffer, returning the buffer and the
+/// regions to copy from it into the mip level and layer paired with each
+/// image.
+unsafe fn stage_images<B: Backend>(
+    device: &B::Device,
+    physical_device: &B::PhysicalDevice,
+    images: &[(&RgbaImage, Level, gfx_hal::image::Layer)],
+) -> (B::Memory, B::Buffer, Vec<gfx_hal::command::BufferImageCopy>) {
+    use gfx_hal::command::BufferImageCopy;
+    use gfx_hal::format::Aspects;
+    use gfx_hal::image::{Extent, Offset, SubresourceLayers};
+    use gfx_hal::memory::Segment;
+
+    // When copying from a buffer to an image, each row of pixels in the
+    // buffer has to start at a multiple of the device's pitch alignment, and
+    // each image has to start at a multiple of the offset alignment. So there
+    // may be some padding between them.
+    let limits = physical_device.limits();
+    let align = |value: u64, alignment: u64| {
+        let mask = alignment.max(1) - 1;
+        (value + mask) & !mask
+    };
+
+    let texel_size = std::mem::size_of::<image::Rgba<u8>>() as u64;
+    let mut regions = vec![];
+    let mut staging_len = 0;
+
+    for &(pixels, level, layer) in images {
+        let (width, height) = pixels.dimensions();
+        let row_pitch = align(
+            width as u64 * texel_size,
+            limits.optimal_buffer_copy_pitch_alignment,
+        );
+        let buffer_offset = align(
+            staging_len,
+            limits.optimal_buffer_copy_offset_alignment.max(texel_size),
+        );
+
+        regions.push(BufferImageCopy {
+            buffer_offset,
+            buffer_width: (row_pitch / texel_size) as u32,
+            buffer_height: height,
+            image_layers: SubresourceLayers {
+                aspects: Aspects::COLOR,
+                level,
+                layers: layer..layer + 1,
+            },
+            image_offset: Offset::ZERO,
+            image_extent: Extent {
+                width,
+                height,
+                depth: 1,
+            },
+        });
+
+        staging_len = buffer_offset + row_pitch * height as u64;
+    }
+
+    let (staging_memory, staging_buffer) = make_buffer::<B>(
+        device,
+        physical_device,
+        staging_len as usize,
+        gfx_hal::buffer::Usage::TRANSFER_SRC,
+        Properties::CPU_VISIBLE,
+    );
+
+    let mapped_memory = device
+        .map_memory(&staging_memory, Segment::ALL)
+        .expect("Failed to map memory");
+
+    for ((pixels, _, _), region) in images.iter().zip(&regions) {
+        let row_size = (pixels.width() as u64 * texel_size) as usize;
+        let row_pitch = (region.buffer_width as u64 * texel_size) as usize;
+
+        for (y, row) in pixels.as_raw().chunks_exact(row_size).enumerate() {
+            let dest = mapped_memory.add(region.buffer_offset as usize + y * row_pitch);
+            std::ptr::copy_nonoverlapping(row.as_ptr(), dest, row.len());
+        }
+    }
+
+    device
+        .flush_mapped_memory_ranges(vec![(&staging_memory, Segment::ALL)])
+        .expect("Out of memory");
+
+    device.unmap_memory(&staging_memory);
+
+    (staging_memory, staging_buffer, regions)
+}
+
+/// Record commands to fill in every mip level of an image from the first.
+///
+/// This expects every level to be in the `TransferDstOptimal` layout, with
+/// only the first level of each layer filled in. Each level is blitted
+/// (copied with scaling) from the one before it, for every layer at once,
+/// and then transitioned so that it's ready to be sampled in a fragment
+/// shader.
+unsafe fn record_mip_blits<B: Backend>(
+    command_buffer: &mut B::CommandBuffer,
+    image: &B::Image,
+    width: u32,
+    height: u32,
+    mip_levels: Level,
+    layer_count: gfx_hal::image::Layer,
+) {
+    use gfx_hal::command::{CommandBuffer, ImageBlit};
+    use gfx_hal::format::Aspects;
+    use gfx_hal::image::{Access, Filter, Layout, Offset, SubresourceLayers, SubresourceRange};
+    use gfx_hal::memory::{Barrier, Dependencies};
+    use gfx_hal::pso::PipelineStage;
+
+    let level_range = |level: Level| SubresourceRange {
+        aspects: Aspects::COLOR,
+        level_start: level,
+        level_count: Some(1),
+        layer_start: 0,
+        layer_count: Some(layer_count),
+    };
+
+    let level_layers = |level: Level| SubresourceLayers {
+        aspects: Aspects::COLOR,
+        level,
+        layers: 0..layer_count,
+    };
+
+    let level_bounds = |level: Level| {
+        Offset::ZERO..Offset {
+            x: (width >> level).max(1) as i32,
+            y: (height >> level).max(1) as i32,
+            z: 1,
+        }
+    };
+
+    for level in 1..mip_levels {
+        let source = level - 1;
+
+        // The previous level has just been written to, so we have to wait
+        // for that to finish before we can read from it.
+        command_buffer.pipeline_barrier(
+            PipelineStage::TRANSFER..PipelineStage::TRANSFER,
+            Dependencies::empty(),
+            &[Barrier::Image {
+                states: (Access::TRANSFER_WRITE, Layout::TransferDstOptimal)
+                    ..(Access::TRANSFER_READ, Layout::TransferSrcOptimal),
+                target: image,
+                families: None,
+                range: level_range(source),
+            }],
+        );
+
+        command_buffer.blit_image(
+            image,
+            Layout::TransferSrcOptimal,
+            image,
+            Layout::TransferDstOptimal,
+            Filter::Linear,
+            &[ImageBlit {
+                src_subresource: level_layers(source),
+                src_bounds: level_bounds(source),
+                dst_subresource: level_layers(level),
+                dst_bounds: level_bounds(level),
+            }],
+        );
+
+        // Now that nothing else will read from the previous level, it can
+        // be made ready for sampling.
+        command_buffer.pipeline_barrier(
+            PipelineStage::TRANSFER..PipelineStage::FRAGMENT_SHADER,
+            Dependencies::empty(),
+            &[Barrier::Image {
+                states: (Access::TRANSFER_READ, Layout::TransferSrcOptimal)
+                    ..(Access::SHADER_READ, Layout::ShaderReadOnlyOptimal),
+                target: image,
+                families: None,
+                range: level_range(source),
+            }],
+        );
+    }
+
+    // The last level is never blitted from, so it's still a transfer
+    // destination at this point.
+    command_buffer.pipeline_barrier(
+        PipelineStage::TRANSFER..PipelineStage::FRAGMENT_SHADER,
+        Dependencies::empty(),
+        &[Barrier::Image {
+            states: (Access::TRANSFER_WRITE, Layout::TransferDstOptimal)
+                ..(Access::SHADER_READ, Layout::ShaderReadOnlyOptimal),
+            target: image,
+            families: None,
+            range: level_range(mip_levels - 1),
+        }],
+    );
+}
+
+/// Create an image that can be rendered to, and then sampled by shaders,
+/// like an offscreen target for post-processing.
+///
+/// Like depth buffers, targets that match the size of the swapchain should
+/// be made again whenever it's reconfigured.
+pub unsafe fn make_render_target<B: Backend>(
+    device: &B::Device,
+    physical_device: &B::PhysicalDevice,
+    format: Format,
+    width: u32,
+    height: u32,
+) -> Texture<B> {
+    use gfx_hal::format::{Aspects, Swizzle};
+    use gfx_hal::image::{Kind, SubresourceRange, Tiling, Usage, ViewCapabilities, ViewKind};
+
+    let mut image = device
+        .create_image(
+            Kind::D2(width, height, 1, 1),
+            1,
+            format,
+            Tiling::Optimal,
+            Usage::COLOR_ATTACHMENT | Usage::SAMPLED,
+            ViewCapabilities::empty(),
+        )
+        .expect("Failed to create render target image");
+
+    let req = device.get_image_requirements(&image);
+    let memory_type =
+        find_memory_type::<B>(physical_device, req.type_mask, Properties::DEVICE_LOCAL);
+
+    let memory = device
+        .allocate_memory(memory_type, req.size)
+        .expect("Failed to allocate render target memory");
+
+    device
+        .bind_image_memory(&memory, 0, &mut image)
+        .expect("Failed to bind render target memory");
+
+    let range = SubresourceRange {
+        aspects: Aspects::COLOR,
+        level_start: 0,
+        level_count: Some(1),
+        layer_start: 0,
+        layer_count: Some(1),
+    };
+
+    let view = device
+        .create_image_view(&image, ViewKind::D2, format, Swizzle::NO, range)
+        .expect("Failed to create render target view");
+
+    Texture {
+        memory,
+        image,
+        view,
+        mip_levels: 1,
+    }
+}
+
+/// Pick a depth format that the device can render to.
+pub fn find_depth_format<B: Backend>(physical_device: &B::PhysicalDevice) -> Format {
+    use gfx_hal::format::ImageFeature;
+
+    // `D16Unorm` is the only depth format every device has to support, but
+    // it's also the least precise, so we only use it as a last resort.
+    let candidates = [Format::D32Sfloat, Format::D24UnormS8Uint, Format::D16Unorm];
+
+    candidates
+        .iter()
+        .copied()
+        .find(|&format| {
+            physical_device
+                .format_properties(Some(format))
+                .optimal_tiling
+                .contains(ImageFeature::DEPTH_STENCIL_ATTACHMENT)
+        })
+        .unwrap_or(Format::D16Unorm)
+}
+
+/// Pick a depth format for shadow maps, which are rendered to and then
+/// sampled.
+///
+/// Formats with a stencil component are skipped: a view for sampling can
+/// only see one aspect, but a view for rendering needs both.
+pub fn find_shadow_map_format<B: Backend>(physical_device: &B::PhysicalDevice) -> Format {
+    use gfx_hal::format::ImageFeature;
+
+    let candidates = [Format::D32Sfloat, Format::D16Unorm];
+
+    candidates
+        .iter()
+        .copied()
+        .find(|&format| {
+            physical_device
+                .format_properties(Some(format))
+                .optimal_tiling
+                .contains(ImageFeature::DEPTH_STENCIL_ATTACHMENT | ImageFeature::SAMPLED)
+        })
+        .unwrap_or(Format::D16Unorm)
+}
+
+/// Create an image to use as a depth buffer.
+///
+/// Unlike textures, depth buffers have to match the size of the swapchain,
+/// so this should be called again whenever the swapchain is reconfigured.
+pub unsafe fn make_depth_texture<B: Backend>(
+    device: &B::Device,
+    physical_device: &B::PhysicalDevice,
+    format: Format,
+    width: u32,
+    height: u32,
+) -> Texture<B> {
+    use gfx_hal::image::Usage;
+
+    make_depth_image::<B>(
+        device,
+        physical_device,
+        format,
+        width,
+        height,
+        Usage::DEPTH_STENCIL_ATTACHMENT,
+    )
+}
+
+/// Create a square depth image that can be rendered to, and then sampled
+/// by shaders, like a shadow map.
+///
+/// The `format` should come from `find_shadow_map_format`.
+pub unsafe fn make_shadow_map<B: Backend>(
+    device: &B::Device,
+    physical_device: &B::PhysicalDevice,
+    format: Format,
+    size: u32,
+) -> Texture<B> {
+    use gfx_hal::image::Usage;
+
+    make_depth_image::<B>(
+        device,
+        physical_device,
+        format,
+        size,
+        size,
+        Usage::DEPTH_STENCIL_ATTACHMENT | Usage::SAMPLED,
+    )
+}
+
+unsafe fn make_depth_image<B: Backend>(
+    device: &B::Device,
+    physical_device: &B::PhysicalDevice,
+    format: Format,
+    width: u32,
+    height: u32,
+    usage: gfx_hal::image::Usage,
+) -> Texture<B> {
+    use gfx_hal::format::Swizzle;
+    use gfx_hal::image::{Kind, SubresourceRange, Tiling, ViewCapabilities, ViewKind};
+
+    let mut image = device
+        .create_image(
+            Kind::D2(width, height, 1, 1),
+            1,
+            format,
+            Tiling::Optimal,
+            usage,
+            ViewCapabilities::empty(),
+        )
+        .expect("Failed to create depth image");
+
+    let req = device.get_image_requirements(&image);
+    let memory_type =
+        find_memory_type::<B>(physical_device, req.type_mask, Properties::DEVICE_LOCAL);
+
+    let memory = device
+        .allocate_memory(memory_type, req.size)
+        .expect("Failed to allocate depth image memory");
+
+    device
+        .bind_image_memory(&memory, 0, &mut image)
+        .expect("Failed to bind depth image memory");
+
+    // Formats with a stencil component need both aspects in the view to be
+    // usable as an attachment.
+    let range = SubresourceRange {
+        aspects: format.surface_desc().aspects,
+        level_start: 0,
+        level_count: Some(1),
+        layer_start: 0,
+        layer_count: Some(1),
+    };
+
+    let view = device
+        .create_image_view(&image, ViewKind::D2, format, Swizzle::NO, range)
+        .expect("Failed to create depth image view");
+
+    Texture {
+        memory,
+        image,
+        view,
+        mip_levels: 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mip_levels_go_down_to_one_pixel() {
+        assert_eq!(mip_levels_for(1, 1), 1);
+        assert_eq!(mip_levels_for(2, 2), 2);
+        assert_eq!(mip_levels_for(256, 256), 9);
+
+        // The larger side decides, and odd sizes round down each level.
+        assert_eq!(mip_levels_for(1024, 1), 11);
+        assert_eq!(mip_levels_for(1, 1024), 11);
+        assert_eq!(mip_levels_for(255, 100), 8);
+
+        // An empty image still has a level.
+        assert_eq!(mip_levels_for(0, 0), 1);
+    }
+}
@@ -0,0 +1,93 @@
+//! Vertex input layouts generated from Rust structs.
+//!
+//! A pipeline needs to know where each vertex attribute lives in the vertex
+//! buffer, and what format it's in. Writing those offsets and formats out by
+//! hand means that adding or reordering a field in the vertex struct quietly
+//! breaks the pipeline. Deriving `VertexLayout` generates them from the
+//! struct instead:
+//!
+//! ```ignore
+//! #[repr(C)]
+//! #[derive(Debug, Clone, Copy, VertexLayout)]
+//! struct Vertex {
+//!     position: [f32; 3], // location 0, Rgb32Sfloat, offset 0
+//!     normal: [f32; 3],   // location 1, Rgb32Sfloat, offset 12
+//!     uv: [f32; 2],       // location 2, Rg32Sfloat, offset 24
+//! }
+//! ```
+//!
+//! Locations are assigned in field order.
+
+pub use gfx_hal_tutorials_derive::VertexLayout;
+
+use gfx_hal::{
+    format::Format,
+    pso::{AttributeDesc, BufferIndex, Element, Location, VertexBufferDesc, VertexInputRate},
+};
+
+/// A type that can be used as a field of a `VertexLayout` struct.
+pub trait VertexAttribute: Copy {
+    /// The format the attribute is read as.
+    const FORMAT: Format;
+}
+
+macro_rules! impl_vertex_attribute {
+    ($($ty:ty => $format:ident;)*) => {
+        $(
+            impl VertexAttribute for $ty {
+                const FORMAT: Format = Format::$format;
+            }
+        )*
+    };
+}
+
+impl_vertex_attribute! {
+    f32 => R32Sfloat;
+    [f32; 2] => Rg32Sfloat;
+    [f32; 3] => Rgb32Sfloat;
+    [f32; 4] => Rgba32Sfloat;
+    i32 => R32Sint;
+    [i32; 2] => Rg32Sint;
+    [i32; 3] => Rgb32Sint;
+    [i32; 4] => Rgba32Sint;
+    u32 => R32Uint;
+    [u32; 2] => Rg32Uint;
+    [u32; 3] => Rgb32Uint;
+    [u32; 4] => Rgba32Uint;
+    [u8; 4] => Rgba8Unorm;
+}
+
+/// A struct that can be read from a vertex buffer.
+///
+/// This should be derived rather than implemented by hand.
+pub trait VertexLayout: Copy {
+    /// The attributes of this struct, read from the vertex buffer bound at
+    /// `binding`.
+    fn attributes(binding: BufferIndex) -> Vec<AttributeDesc>;
+
+    /// A vertex buffer description with one of these structs per vertex.
+    fn vertex_buffer(binding: BufferIndex) -> VertexBufferDesc {
+        VertexBufferDesc {
+            binding,
+            stride: std::mem::size_of::<Self>() as u32,
+            rate: VertexInputRate::Vertex,
+        }
+    }
+}
+
+/// Describe a single attribute. Used by the derive macro.
+#[doc(hidden)]
+pub fn attribute<T: VertexAttribute>(
+    location: Location,
+    binding: BufferIndex,
+    offset: usize,
+) -> AttributeDesc {
+    AttributeDesc {
+        location,
+        binding,
+        element: Element {
+            format: T::FORMAT,
+            offset: offset as u32,
+        },
+    }
+}